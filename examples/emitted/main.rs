@@ -0,0 +1,27 @@
+//! Builds a 'ftab' file with a function printed by `ftabutil unpack --emit_rust --inline`.
+//!
+//! Usage: `cargo run --example emitted`. The function in `snippet.rs` is the output for a sample
+//! manifest, a test of the `emit` module checks that it is up to date and builds the same file as
+//! the manifest it was generated from.
+
+use ftabutil::parser::Parser;
+
+include!("snippet.rs");
+
+fn main() {
+    let ftab = build_ftab();
+    let parser = Parser::parse(&ftab).expect("failed to parse the built file");
+
+    let mut segments = parser.segments();
+    while let Some(segment) = segments
+        .next_segment()
+        .expect("failed to parse the segment list")
+    {
+        println!(
+            "Segment with tag {} is {} bytes long.",
+            segment.tag.escape_ascii(),
+            segment.data.len()
+        );
+    }
+    println!("Built file is {} bytes long.", ftab.len());
+}
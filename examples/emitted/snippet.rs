@@ -0,0 +1,66 @@
+use ftabutil::{
+    builder::Builder,
+    manifest::{Manifest, SegmentDesc, Tag},
+};
+use std::path::PathBuf;
+
+fn build_ftab() -> Vec<u8> {
+    let manifest = Manifest {
+        unk_0: 0x1,
+        unk_1: 0x0,
+        unk_2: 0xffffffff,
+        unk_3: 0x0,
+        unk_4: 0x0,
+        unk_5: 0x0,
+        unk_6: 0x0,
+        label: Some("sample \"ftab\"".to_owned()),
+        ticket: Some(PathBuf::from("ApImg4Ticket.der")),
+        segments: vec![
+            SegmentDesc {
+                path: PathBuf::from("rkos.bin"),
+                tag: Tag(*b"rkos"),
+                unk: 0x0,
+                padding: None,
+                expected_len: Some(5),
+                alias_of: None,
+            },
+            SegmentDesc {
+                path: PathBuf::from("rrko.bin"),
+                tag: Tag(*b"rrko"),
+                unk: 0x10,
+                padding: Some(b"\xff\xff\xff".to_vec()),
+                expected_len: None,
+                alias_of: None,
+            },
+            SegmentDesc {
+                path: PathBuf::from("00010203.bin"),
+                tag: Tag([0x00, 0x01, 0x02, 0x03]),
+                unk: 0x0,
+                padding: None,
+                expected_len: None,
+                alias_of: None,
+            },
+            SegmentDesc {
+                path: PathBuf::from("rkos.bin"),
+                tag: Tag(*b"rkrn"),
+                unk: 0x0,
+                padding: None,
+                expected_len: None,
+                alias_of: Some(Tag(*b"rkos")),
+            },
+        ],
+        outputs: Vec::new(),
+    };
+
+    Builder::with_manifest_loader(&manifest, |_, path| {
+        Ok(match path.to_str().unwrap() {
+            "ApImg4Ticket.der" => b"0\x82ticket".to_vec(),
+            "rkos.bin" => b"hello".to_vec(),
+            "rrko.bin" => b"world!!!".to_vec(),
+            "00010203.bin" => b"\x00\"\\\n\xff".to_vec(),
+            path => panic!("no contents for {}", path),
+        })
+    })
+    .unwrap()
+    .to_vec()
+}
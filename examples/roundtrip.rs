@@ -0,0 +1,83 @@
+//! Parses a 'ftab' file, flips all bits of one of its segments and builds it back entirely in
+//! memory.
+//!
+//! Usage: `cargo run --example roundtrip [PATH]`. When no path is specified, a small built-in
+//! 'ftab' file is used.
+
+use ftabutil::{builder::Builder, parser::Parser};
+use std::{env, fs};
+
+/// Builds a 'ftab' file with two segments and no ticket by hand.
+fn sample_ftab() -> Vec<u8> {
+    let segments: [(&[u8; 4], &[u8]); 2] = [(b"rkos", b"hello"), (b"rrko", b"world!!!")];
+    let mut header = Vec::new();
+    let mut data = Vec::new();
+    let data_start = 48 + segments.len() * 16;
+
+    for (tag, contents) in segments {
+        data.resize(data.len() + (4 - data.len() % 4) % 4, 0);
+        header.extend_from_slice(tag);
+        header.extend_from_slice(&((data_start + data.len()) as u32).to_le_bytes());
+        header.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(contents);
+    }
+
+    let mut file = vec![0u8; 32];
+    file.extend_from_slice(b"rkosftab");
+    file.extend_from_slice(&(segments.len() as u32).to_le_bytes());
+    file.extend_from_slice(&0u32.to_le_bytes());
+    file.extend_from_slice(&header);
+    file.extend_from_slice(&data);
+    file
+}
+
+fn main() {
+    let original = match env::args_os().nth(1) {
+        Some(path) => fs::read(path).expect("failed to read the input file"),
+        None => sample_ftab(),
+    };
+
+    let parser = Parser::parse(&original).expect("failed to parse the input file");
+    let mut builder = Builder::with_parser(&parser).expect("failed to parse the segment list");
+
+    // Flip all bits of the first segment.
+    let mut segments = parser.segments();
+    let first = segments
+        .next_segment()
+        .expect("failed to parse the segment list")
+        .expect("the file has no segments");
+    for byte in builder.segment_data_mut(first.tag).unwrap() {
+        *byte = !*byte;
+    }
+
+    // Build the file and check that only the first segment has changed.
+    let rebuilt = builder.to_vec();
+    let rebuilt_parser =
+        Parser::try_from(rebuilt.as_slice()).expect("failed to parse the rebuilt file");
+    let mut segments = parser.segments();
+    let mut rebuilt_segments = rebuilt_parser.segments();
+
+    while let Some(segment) = segments.next_segment().unwrap() {
+        let rebuilt_segment = rebuilt_segments.next_segment().unwrap().unwrap();
+        assert_eq!(segment.tag, rebuilt_segment.tag);
+
+        if segment.offset == first.offset {
+            assert!(segment
+                .data
+                .iter()
+                .zip(rebuilt_segment.data)
+                .all(|(a, b)| *a == !*b));
+        } else {
+            assert_eq!(segment.data, rebuilt_segment.data);
+        }
+    }
+    assert!(rebuilt_segments.next_segment().unwrap().is_none());
+    assert_eq!(parser.ticket(), rebuilt_parser.ticket());
+
+    println!(
+        "Flipped segment with tag {}, rebuilt file is {} bytes long.",
+        first.tag.escape_ascii(),
+        rebuilt.len()
+    );
+}
@@ -1,14 +1,108 @@
 //! Provides the [`Builder`] structure that can be used to build 'ftab' files from
 //! [`Manifest`](../manifest/struct.Manifest.html)s.
 
-use crate::{error::FileOpError, format::*, manifest::Manifest, util};
+use crate::{
+    error::{BuildError, FileOpError},
+    format::*,
+    manifest::Manifest,
+    parser::{OobSegmentError, Parser},
+    util,
+};
 use std::{
-    io::{self, Write},
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom, Write},
     mem,
-    path::Path,
+    ops::Range,
+    path::{Path, PathBuf},
     slice,
 };
 
+/// The placement of the ticket in a built 'ftab' file.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub enum TicketPlacement {
+    /// The ticket follows the contents of the last segment. This is how the original ftab builder
+    /// seems to work.
+    #[default]
+    AfterData,
+    /// The ticket immediately follows the segment list and precedes the contents of the segments.
+    /// The ticket is padded with zeroes so that the contents of the segments stay aligned.
+    BeforeData,
+}
+
+/// The placement of the ticket and the segments' contents in a built 'ftab' file.
+#[derive(Copy, Clone, Default, Debug)]
+struct Layout {
+    /// The length of the padding between the segments' contents and the ticket.
+    padding_before: usize,
+    /// The length of the padding between the ticket and the segments' contents.
+    padding_after: usize,
+    /// The offset of the ticket or zero when there is no ticket.
+    ticket_offset: usize,
+    /// The shift of the segments' contents caused by the ticket preceding them.
+    data_shift: usize,
+}
+
+/// A part of the data following the segment list of a built 'ftab' file.
+#[derive(Clone, Debug)]
+enum Chunk {
+    /// Bytes kept in memory, i.e. padding and contents of segments loaded upfront.
+    Bytes(Vec<u8>),
+    /// The contents of a segment copied from a file of the recorded length while the built file is
+    /// written.
+    File(PathBuf, usize),
+}
+
+impl Chunk {
+    /// Returns the length of the chunk in the built file.
+    fn len(&self) -> usize {
+        match self {
+            Chunk::Bytes(bytes) => bytes.len(),
+            Chunk::File(_, len) => *len,
+        }
+    }
+
+    /// Returns the contents of the chunk reading them from the file if needed.
+    fn into_bytes(self, name: &'static str) -> Result<Vec<u8>, Box<FileOpError>> {
+        match self {
+            Chunk::Bytes(bytes) => Ok(bytes),
+            Chunk::File(path, _) => util::read_file(name, path),
+        }
+    }
+
+    /// Writes a range of the chunk's contents into the destination.
+    fn write_range<W: Write>(&self, dest: &mut W, range: Range<usize>) -> io::Result<()> {
+        let (path, len) = match self {
+            Chunk::Bytes(bytes) => return dest.write_all(&bytes[range]),
+            Chunk::File(path, len) => (path, *len),
+        };
+        let with_path = |error: io::Error| {
+            io::Error::new(
+                error.kind(),
+                format!("segment file at {}: {}", path.display(), error),
+            )
+        };
+
+        // The layout was computed from the length the file had when the builder was filled.
+        let mut file = File::open(path).map_err(with_path)?;
+        if file.metadata().map_err(with_path)?.len() != len as u64 {
+            let error = io::Error::new(io::ErrorKind::InvalidData, "the file changed its length");
+            return Err(with_path(error));
+        }
+
+        file.seek(SeekFrom::Start(range.start as u64))
+            .map_err(with_path)?;
+        let expected = (range.end - range.start) as u64;
+        let copied = io::copy(&mut file.take(expected), dest)?;
+        if copied != expected {
+            let error = io::Error::new(io::ErrorKind::UnexpectedEof, "the file got truncated");
+            return Err(with_path(error));
+        }
+
+        Ok(())
+    }
+}
+
 /// A builder that can be used to build 'ftab' files from
 /// [`Manifest`](../manifest/struct.Manifest.html)s.
 ///
@@ -16,8 +110,11 @@ use std::{
 #[derive(Default, Clone, Debug)]
 pub struct Builder {
     segments: Vec<SegmentHeader>,
-    data: Vec<u8>,
+    chunks: Vec<Chunk>,
+    data_len: usize,
     ticket: Option<Box<[u8]>>,
+    ticket_placement: TicketPlacement,
+    pad_ticket: bool,
     unk_0: u32,
     unk_1: u32,
     unk_2: u32,
@@ -31,71 +128,384 @@ impl Builder {
     /// Creates a [`Builder`] and fills it using a description from a
     /// [`Manifest`](../manifest/struct.Manifest.html).
     ///
+    /// Symbolic links in paths from the manifest are followed. Use
+    /// [`Builder::with_manifest_loader`] with
+    /// [`read_manifest_file`](../util/fn.read_manifest_file.html) to reject them.
+    ///
     /// # Errors
-    /// Returns a boxed [`FileOpError`](../error/struct.FileOpError.html) error when one of the
-    /// files from the manifest's segments lists fails to load.
-    pub fn with_manifest(
-        manifest: &Manifest,
-        dir: Option<&Path>,
-    ) -> Result<Self, Box<FileOpError>> {
-        let mut data_offset = HEADER_LEN + manifest.segments.len() * SEGMENT_HEADER_LEN;
-        let mut segments = Vec::with_capacity(manifest.segments.len());
-        let mut data = Vec::new();
+    /// Returns [`BuildError::FileOp`](../error/enum.BuildError.html#variant.FileOp) when one of the
+    /// files from the manifest's segments lists fails to load and
+    /// [`BuildError::MissingAliasTarget`](../error/enum.BuildError.html#variant.MissingAliasTarget)
+    /// when a segment is an alias of a tag not used by any earlier segment.
+    pub fn with_manifest(manifest: &Manifest, dir: Option<&Path>) -> Result<Self, BuildError> {
+        Self::with_manifest_loader(manifest, |name, rel_path| {
+            let path = util::qualify_path_if_needed(rel_path, dir);
+            util::read_file(name, path)
+        })
+    }
+
+    /// Creates a [`Builder`] and fills it using a description from a
+    /// [`Manifest`](../manifest/struct.Manifest.html) loading the files referenced by the manifest
+    /// with the provided function.
+    ///
+    /// The function receives the name of a file to be used in error messages and the path from the
+    /// manifest.
+    ///
+    /// # Errors
+    /// Returns any error returned by the loader function wrapped into
+    /// [`BuildError::FileOp`](../error/enum.BuildError.html#variant.FileOp) and
+    /// [`BuildError::MissingAliasTarget`](../error/enum.BuildError.html#variant.MissingAliasTarget)
+    /// when a segment is an alias of a tag not used by any earlier segment.
+    pub fn with_manifest_loader<F>(manifest: &Manifest, mut load: F) -> Result<Self, BuildError>
+    where
+        F: FnMut(&'static str, &Path) -> Result<Vec<u8>, Box<FileOpError>>,
+    {
+        Self::with_manifest_impl(manifest, |name, path| load(name, path).map(Chunk::Bytes))
+    }
+
+    /// Creates a [`Builder`] and fills it using a description from a
+    /// [`Manifest`](../manifest/struct.Manifest.html) without loading the contents of the segments
+    /// into memory.
+    ///
+    /// The function receives the name of a file to be used in error messages and the path from the
+    /// manifest and returns the path of the file to read. Only the lengths of the segment files are
+    /// recorded here, their contents are copied into the destination while the built file is
+    /// written, so memory use doesn't depend on the size of the segments. The ticket is loaded
+    /// upfront.
+    ///
+    /// Writing the built file fails with an error of the `InvalidData` kind when the length of a
+    /// segment file changes after the builder is filled.
+    ///
+    /// # Errors
+    /// Returns any error returned by the function or occurring while getting the length of a file
+    /// wrapped into [`BuildError::FileOp`](../error/enum.BuildError.html#variant.FileOp) and
+    /// [`BuildError::MissingAliasTarget`](../error/enum.BuildError.html#variant.MissingAliasTarget)
+    /// when a segment is an alias of a tag not used by any earlier segment.
+    pub fn with_manifest_streamed<F>(manifest: &Manifest, mut locate: F) -> Result<Self, BuildError>
+    where
+        F: FnMut(&'static str, &Path) -> Result<PathBuf, Box<FileOpError>>,
+    {
+        Self::with_manifest_impl(manifest, |name, rel_path| {
+            let path = locate(name, rel_path)?;
+            let len = fs::metadata(&path)
+                .and_then(|metadata| {
+                    usize::try_from(metadata.len())
+                        .map_err(|_| io::Error::other("the file is too large"))
+                })
+                .map_err(|error| FileOpError::make_open(name, path.clone(), error))?;
+
+            Ok(Chunk::File(path, len))
+        })
+    }
+
+    /// Implements [`Builder::with_manifest_loader`] and [`Builder::with_manifest_streamed`] using a
+    /// function returning the chunk with the contents of a file.
+    fn with_manifest_impl<F>(manifest: &Manifest, mut load: F) -> Result<Self, BuildError>
+    where
+        F: FnMut(&'static str, &Path) -> Result<Chunk, Box<FileOpError>>,
+    {
+        let data_start = HEADER_LEN + manifest.segments.len() * SEGMENT_HEADER_LEN;
+        let mut builder = Self {
+            segments: Vec::with_capacity(manifest.segments.len()),
+            unk_0: manifest.unk_0,
+            unk_1: manifest.unk_1,
+            unk_2: manifest.unk_2,
+            unk_3: manifest.unk_3,
+            unk_4: manifest.unk_4,
+            unk_5: manifest.unk_5,
+            unk_6: manifest.unk_6,
+            ..Default::default()
+        };
 
         for segment in manifest.segments.iter() {
+            if let Some(alias_of) = segment.alias_of {
+                let index = builder
+                    .segment_index(alias_of.0)
+                    .ok_or(BuildError::MissingAliasTarget(segment.tag, alias_of))?;
+                debug!(
+                    "Segment with tag {} is an alias of segment with tag {}.",
+                    segment.tag, alias_of
+                );
+
+                builder.push_alias(segment.tag.0, index, segment.unk);
+                continue;
+            }
+
             debug!(
                 "Reading segment with tag {} from file at {}.",
                 segment.tag.0.escape_ascii(),
                 segment.path.display()
             );
 
-            let path = util::qualify_path_if_needed(&segment.path, dir);
-            let segment_data = util::read_file("segment", path)?;
-
-            // This will not pad the ticket, but that's how the original ftab builder seems to work
-            // so we do it this way.
-            let padding = (4 - data.len() % 4) % 4;
-            data.resize(data.len() + padding, 0);
-            data_offset += padding;
+            let segment_data = load("segment", &segment.path)?;
+            if let Some(expected_len) = segment.expected_len {
+                if segment_data.len() != expected_len as usize {
+                    warn!(
+                        "Segment with tag {} from file at {} is {} bytes long while {} bytes are \
+                        expected.",
+                        segment.tag.0.escape_ascii(),
+                        segment.path.display(),
+                        segment_data.len(),
+                        expected_len
+                    );
+                }
+            }
 
-            trace!(
-                "Segment offset is {}, length is {}.",
-                data_offset,
-                segment_data.len()
+            builder.push_segment(
+                data_start,
+                segment.tag.0,
+                segment_data,
+                segment.padding.as_deref(),
+                segment.unk,
             );
+        }
 
-            segments.push(SegmentHeader {
-                tag: segment.tag.0,
-                seg_off: data_offset.try_into().unwrap(),
-                seg_len: segment_data.len().try_into().unwrap(),
-                unk: 0,
-            });
-            data.extend_from_slice(&segment_data);
+        builder.ticket = if let Some(rel_path) = manifest.ticket.as_ref() {
+            let ticket = load("ticket", rel_path)?.into_bytes("ticket")?;
+            Some(ticket.into_boxed_slice())
+        } else {
+            None
+        };
 
-            trace!("Padded with {} null bytes.", padding);
+        Ok(builder)
+    }
+
+    /// Creates a [`Builder`] containing the same header fields, segments and ticket as a parsed
+    /// 'ftab' file.
+    ///
+    /// The segments are laid out the same way [`Builder::with_manifest`] lays them out. When a
+    /// segment directly follows the previous one and is aligned to a 4-byte boundary, the original
+    /// padding bytes are preserved. Otherwise the segment is padded with zeroes.
+    /// Segments pointing at exactly the same range as an earlier segment share its contents instead
+    /// of being stored twice.
+    ///
+    /// # Errors
+    /// Returns an [`OobSegmentError`](../parser/error/struct.OobSegmentError.html) when one of the
+    /// segment list entries points outside the range of the parsed file.
+    pub fn with_parser(parser: &Parser) -> Result<Self, OobSegmentError> {
+        Self::with_parser_impl(parser, true)
+    }
+
+    /// Creates a [`Builder`] containing the same header fields, segments and ticket as a parsed
+    /// 'ftab' file with a canonical layout.
+    ///
+    /// The header fields, the segment list order, tags, `unk` values and contents of the segments
+    /// and the ticket are preserved. Only the physical layout changes:
+    /// - segment contents are stored in the segment list order, so gaps between segments and
+    ///   segments stored out of order are compacted;
+    /// - every segment is preceded by zero padding to a 4-byte boundary, the original padding bytes
+    ///   are discarded;
+    /// - the ticket directly follows the last segment without padding;
+    /// - any bytes past the end of the last segment or the ticket are dropped.
+    ///
+    /// Segments pointing at exactly the same range as an earlier segment keep sharing its contents,
+    /// other overlapping segments are stored separately.
+    ///
+    /// # Errors
+    /// Returns an [`OobSegmentError`](../parser/error/struct.OobSegmentError.html) when one of the
+    /// segment list entries points outside the range of the parsed file.
+    pub fn with_parser_normalized(parser: &Parser) -> Result<Self, OobSegmentError> {
+        Self::with_parser_impl(parser, false)
+    }
+
+    /// Implements [`Builder::with_parser`] and [`Builder::with_parser_normalized`]. The original
+    /// padding bytes are only considered when `keep_padding` is set.
+    fn with_parser_impl(parser: &Parser, keep_padding: bool) -> Result<Self, OobSegmentError> {
+        let mut segments_parser = parser.segments();
+        let data_start = HEADER_LEN + segments_parser.count() * SEGMENT_HEADER_LEN;
+        let mut builder = Self {
+            segments: Vec::with_capacity(segments_parser.count()),
+            ticket: parser.ticket().map(Box::from),
+            unk_0: parser.unk_0(),
+            unk_1: parser.unk_1(),
+            unk_2: parser.unk_2(),
+            unk_3: parser.unk_3(),
+            unk_4: parser.unk_4(),
+            unk_5: parser.unk_5(),
+            unk_6: parser.unk_6(),
+            ..Default::default()
+        };
+
+        let mut prev_end = data_start;
+        let mut ranges = HashMap::new();
+        while let Some(segment) = segments_parser.next_segment()? {
+            // Segments pointing at the same range as an earlier one share its contents.
+            let range = (segment.offset, segment.data.len());
+            if let Some(&index) = ranges.get(&range) {
+                builder.push_alias(segment.tag, index, segment.unk);
+                continue;
+            }
+            ranges.insert(range, builder.segments.len());
+
+            let padding_len = alignment_padding(prev_end);
+            let padding = parser
+                .bytes_at(prev_end..segment.offset)
+                .filter(|padding| keep_padding && padding.len() == padding_len);
 
-            data_offset += segment_data.len();
+            let contents = Chunk::Bytes(segment.data.to_vec());
+            builder.push_segment(data_start, segment.tag, contents, padding, segment.unk);
+            prev_end = segment.offset + segment.data.len();
         }
 
-        let ticket = if let Some(rel_path) = manifest.ticket.as_ref() {
-            let path = util::qualify_path_if_needed(rel_path, dir);
-            Some(util::read_file("ticket", path).map(Vec::into_boxed_slice)?)
-        } else {
-            None
+        Ok(builder)
+    }
+
+    /// Appends bytes to the data merging them into the last chunk when it is kept in memory.
+    fn push_bytes(&mut self, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+
+        match self.chunks.last_mut() {
+            Some(Chunk::Bytes(last)) => last.extend_from_slice(bytes),
+            _ => self.chunks.push(Chunk::Bytes(bytes.to_vec())),
+        }
+        self.data_len += bytes.len();
+    }
+
+    /// Appends the contents of a segment preceded by padding to the data and adds a segment list
+    /// entry for it. `data_start` is the offset of the data in the built file.
+    fn push_segment(
+        &mut self,
+        data_start: usize,
+        tag: [u8; 4],
+        contents: Chunk,
+        padding: Option<&[u8]>,
+        unk: u32,
+    ) {
+        // Use the provided padding bytes when these are present. Otherwise pad with zeroes. This
+        // will not pad the ticket, but that's how the original ftab builder seems to work so we do
+        // it this way.
+        let padding = match padding {
+            Some(padding) => {
+                self.push_bytes(padding);
+                padding.len()
+            }
+            None => {
+                let padding = alignment_padding(self.data_len);
+                self.push_bytes(&[0; SEGMENT_ALIGNMENT - 1][..padding]);
+                padding
+            }
         };
+        let data_offset = data_start + self.data_len;
+        let contents_len = contents.len();
 
-        Ok(Self {
-            segments,
-            data,
-            ticket,
-            unk_0: manifest.unk_0,
-            unk_1: manifest.unk_1,
-            unk_2: manifest.unk_2,
-            unk_3: manifest.unk_3,
-            unk_4: manifest.unk_4,
-            unk_5: manifest.unk_5,
-            unk_6: manifest.unk_6,
-        })
+        trace!(
+            "Segment offset is {}, length is {}.",
+            data_offset,
+            contents_len
+        );
+
+        self.segments.push(SegmentHeader {
+            tag,
+            seg_off: data_offset.try_into().unwrap(),
+            seg_len: contents_len.try_into().unwrap(),
+            unk,
+        });
+        match contents {
+            Chunk::Bytes(bytes) => self.push_bytes(&bytes),
+            file => {
+                self.data_len += contents_len;
+                self.chunks.push(file);
+            }
+        }
+
+        trace!("Padded with {} bytes.", padding);
+    }
+
+    /// Adds a segment list entry pointing at the same contents as the entry at `index`.
+    fn push_alias(&mut self, tag: [u8; 4], index: usize, unk: u32) {
+        let SegmentHeader {
+            seg_off, seg_len, ..
+        } = self.segments[index];
+
+        self.segments.push(SegmentHeader {
+            tag,
+            seg_off,
+            seg_len,
+            unk,
+        });
+    }
+
+    /// Returns the index of the first segment list entry with the specified tag.
+    fn segment_index(&self, tag: [u8; 4]) -> Option<usize> {
+        self.segments.iter().position(|segment| segment.tag == tag)
+    }
+
+    /// Returns a mutable slice with the contents of the first segment with the specified tag or
+    /// `None` if there is no such segment or its contents are not kept in memory, see
+    /// [`Builder::with_manifest_streamed`].
+    ///
+    /// The length of the contents can't be changed this way, so the layout of the built file stays
+    /// the same.
+    pub fn segment_data_mut(&mut self, tag: [u8; 4]) -> Option<&mut [u8]> {
+        let data_start = HEADER_LEN + self.segments.len() * SEGMENT_HEADER_LEN;
+        let segment = self.segments.iter().find(|segment| segment.tag == tag)?;
+        let start = segment.seg_off as usize - data_start;
+        let end = start + segment.seg_len as usize;
+
+        let mut chunk_start = 0;
+        for chunk in self.chunks.iter_mut() {
+            let chunk_end = chunk_start + chunk.len();
+            if start >= chunk_start && end <= chunk_end {
+                return match chunk {
+                    Chunk::Bytes(bytes) => Some(&mut bytes[start - chunk_start..end - chunk_start]),
+                    Chunk::File(..) => None,
+                };
+            }
+            chunk_start = chunk_end;
+        }
+
+        None
+    }
+
+    /// Writes a range of the data following the segment list into the destination.
+    fn write_data<W: Write>(&self, dest: &mut W, range: Range<usize>) -> io::Result<()> {
+        let mut chunk_start = 0;
+        for chunk in self.chunks.iter() {
+            let chunk_end = chunk_start + chunk.len();
+            let start = range.start.max(chunk_start);
+            let end = range.end.min(chunk_end);
+            if start < end {
+                chunk.write_range(dest, start - chunk_start..end - chunk_start)?;
+            }
+            chunk_start = chunk_end;
+        }
+
+        Ok(())
+    }
+
+    /// Sets the APTicket included into the built file. Passing `None` removes the ticket, in which
+    /// case the ticket offset and length fields of the header are set to zero.
+    pub fn set_ticket(&mut self, ticket: Option<Vec<u8>>) {
+        self.ticket = ticket.map(Vec::into_boxed_slice);
+    }
+
+    /// Sets where the ticket is placed in the built file. The default is
+    /// [`TicketPlacement::AfterData`].
+    pub fn set_ticket_placement(&mut self, ticket_placement: TicketPlacement) {
+        self.ticket_placement = ticket_placement;
+    }
+
+    /// Sets whether the ticket should be padded with zeroes to start on a 4-byte boundary when it is
+    /// placed after the contents of the segments.
+    ///
+    /// The ticket is not padded by default as the original ftab builder seems to work this way.
+    pub fn set_pad_ticket(&mut self, pad_ticket: bool) {
+        self.pad_ticket = pad_ticket;
+    }
+
+    /// Returns the built 'ftab' as a vector of bytes.
+    ///
+    /// # Panics
+    /// Will panic if placing the ticket before the data makes a segment offset overflow, see
+    /// [`Builder::write_to_counted`], or if a segment file of a builder filled by
+    /// [`Builder::with_manifest_streamed`] can't be read.
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut v = Vec::new();
+        self.write_to(&mut v)
+            .expect("segment offsets should not overflow and segment files should be readable");
+        v
     }
 
     /// Writes the built 'ftab' into anything implementing the `std::io::Write` trait.
@@ -103,50 +513,549 @@ impl Builder {
     /// # Errors
     /// Returns an I/O error in case it ever occurs.
     pub fn write_to<W: Write>(&self, dest: &mut W) -> io::Result<()> {
+        self.write_to_counted(dest).map(|_| ())
+    }
+
+    /// Computes the placement of the ticket and the segments' contents in the built file.
+    fn layout(&self) -> Layout {
         let data_offset = HEADER_LEN + self.segments.len() * SEGMENT_HEADER_LEN;
-        let header = FtabHeader {
+        let Some(ticket) = self.ticket.as_deref() else {
+            return Layout::default();
+        };
+
+        match self.ticket_placement {
+            TicketPlacement::AfterData => {
+                let padding = if self.pad_ticket {
+                    alignment_padding(self.data_len)
+                } else {
+                    0
+                };
+                Layout {
+                    padding_before: padding,
+                    padding_after: 0,
+                    ticket_offset: data_offset + self.data_len + padding,
+                    data_shift: 0,
+                }
+            }
+            TicketPlacement::BeforeData => {
+                // Keep the segments' contents aligned the same way as without a ticket.
+                let padding = alignment_padding(ticket.len());
+                Layout {
+                    padding_before: 0,
+                    padding_after: padding,
+                    ticket_offset: data_offset,
+                    data_shift: ticket.len() + padding,
+                }
+            }
+        }
+    }
+
+    /// Returns the header the builder writes at the start of the built 'ftab'. Comparing it with a
+    /// parsed header helps pinpoint differing fields when a built file doesn't match the expected
+    /// one.
+    pub fn header(&self) -> FtabHeader {
+        let ticket_len = self.ticket.as_deref().map(<[u8]>::len).unwrap_or(0);
+
+        FtabHeader {
             unk_0: self.unk_0,
             unk_1: self.unk_1,
             unk_2: self.unk_2,
             unk_3: self.unk_3,
-            ticket_offset: self
-                .ticket
-                .as_ref()
-                .map(|_| data_offset + self.data.len())
-                .unwrap_or(0)
-                .try_into()
-                .unwrap(),
-            ticket_len: self
-                .ticket
-                .as_ref()
-                .map(|x| x.len())
-                .unwrap_or(0)
-                .try_into()
-                .unwrap(),
+            ticket_offset: self.layout().ticket_offset.try_into().unwrap(),
+            ticket_len: ticket_len.try_into().unwrap(),
             unk_4: self.unk_4,
             unk_5: self.unk_5,
             magic: *b"rkosftab",
             segments_count: self.segments.len().try_into().unwrap(),
             unk_6: self.unk_6,
-        };
+        }
+    }
+
+    /// Returns the lengths of the segments in the segment list order, including aliases.
+    pub fn segment_lengths(&self) -> impl Iterator<Item = usize> + '_ {
+        self.segments.iter().map(|segment| segment.seg_len as usize)
+    }
+
+    /// Returns the number of segment list entries in the built 'ftab', including aliases.
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+
+    /// Returns the size of the built 'ftab' in bytes without building it. This includes the padding
+    /// of the segments and the ticket, so it's always equal to the number of bytes written by
+    /// [`Builder::write_to_counted`].
+    pub fn total_size(&self) -> u64 {
+        let Layout {
+            padding_before,
+            padding_after,
+            ..
+        } = self.layout();
+        let ticket_len = self.ticket.as_deref().map(<[u8]>::len).unwrap_or(0);
+
+        let size = HEADER_LEN
+            + self.segments.len() * SEGMENT_HEADER_LEN
+            + self.data_len
+            + padding_before
+            + ticket_len
+            + padding_after;
+
+        size.try_into().unwrap()
+    }
+
+    /// Writes the built 'ftab' into anything implementing the `std::io::Write` trait and returns
+    /// the total number of bytes written, i.e. the size of the built file.
+    ///
+    /// # Errors
+    /// Returns an I/O error in case it ever occurs. An error of the `InvalidData` kind is returned
+    /// when placing the ticket before the data makes a segment offset overflow.
+    pub fn write_to_counted<W: Write>(&self, dest: &mut W) -> io::Result<u64> {
+        let Layout {
+            padding_before,
+            padding_after,
+            data_shift,
+            ..
+        } = self.layout();
+        let ticket = self.ticket.as_deref();
 
-        // This is safe because of repr(C) and no padding.
-        let header_bytes: &[u8; HEADER_LEN] = unsafe { mem::transmute(&header) };
-        let segment_list_bytes: &[u8] = unsafe {
-            slice::from_raw_parts(
-                self.segments.as_ptr() as *const u8,
-                self.segments.len() * SEGMENT_HEADER_LEN,
+        let header = self.header();
+
+        // A ticket placed before the data shifts the contents of the segments, the offsets are
+        // validated not to overflow as they may come from a crafted file.
+        let overflow = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "segment offset overflows when shifted by the ticket",
             )
         };
+        let data_shift: u32 = data_shift.try_into().map_err(|_| overflow())?;
+        let segments = self
+            .segments
+            .iter()
+            .map(|segment| {
+                Ok(SegmentHeader {
+                    seg_off: segment
+                        .seg_off
+                        .checked_add(data_shift)
+                        .ok_or_else(overflow)?,
+                    ..segment.clone()
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let ticket = ticket.unwrap_or_default();
 
-        dest.write_all(header_bytes)?;
-        dest.write_all(segment_list_bytes)?;
-        dest.write_all(&self.data)?;
+        write_metadata(dest, &header, &segments)?;
+
+        match self.ticket_placement {
+            TicketPlacement::AfterData => {
+                self.write_data(dest, 0..self.data_len)?;
+                dest.write_all(&[0; SEGMENT_ALIGNMENT - 1][..padding_before])?;
+                dest.write_all(ticket)?;
+            }
+            TicketPlacement::BeforeData => {
+                dest.write_all(ticket)?;
+                dest.write_all(&[0; SEGMENT_ALIGNMENT - 1][..padding_after])?;
+                self.write_data(dest, 0..self.data_len)?;
+            }
+        }
+
+        Ok(self.total_size())
+    }
 
-        if let Some(ticket) = self.ticket.as_deref() {
+    /// Writes the built 'ftab' into a seekable sink and returns the total number of bytes written.
+    ///
+    /// Unlike [`Builder::write_to_counted`] this doesn't compute the offsets upfront. A zeroed
+    /// placeholder is written in place of the header and the segment list first, then the contents
+    /// of the segments and the ticket are written one after another recording the offsets they end
+    /// up at. Finally the sink is rewound to write the header and the segment list with these
+    /// offsets and positioned back at the end of the written file. Offsets are relative to the
+    /// position of the sink when the function is called, so the file may be written into the
+    /// middle of a larger one.
+    ///
+    /// The contents of segments filled by [`Builder::with_manifest_streamed`] are copied from their
+    /// files into the sink one segment at a time.
+    ///
+    /// # Errors
+    /// Returns an I/O error in case it ever occurs, including errors reading segment files.
+    pub fn write_to_seek<W: Write + Seek>(&self, dest: &mut W) -> io::Result<u64> {
+        let Layout {
+            padding_before,
+            padding_after,
+            ..
+        } = self.layout();
+        let ticket = self.ticket.as_deref();
+        let data_start = HEADER_LEN + self.segments.len() * SEGMENT_HEADER_LEN;
+
+        let start = dest.stream_position()?;
+        let position = |dest: &mut W| -> io::Result<u32> {
+            let offset = dest.stream_position()? - start;
+            offset
+                .try_into()
+                .map_err(|_| io::Error::other("the built file is too large"))
+        };
+
+        dest.write_all(&vec![0; data_start])?;
+
+        let mut ticket_offset = 0;
+        if let (Some(ticket), TicketPlacement::BeforeData) = (ticket, self.ticket_placement) {
+            ticket_offset = position(dest)?;
             dest.write_all(ticket)?;
+            dest.write_all(&[0; SEGMENT_ALIGNMENT - 1][..padding_after])?;
         }
 
-        Ok(())
+        // Write the contents segment by segment. Aliases point into the contents that were already
+        // written, so only the segment list entry is produced for them.
+        let mut segments = Vec::with_capacity(self.segments.len());
+        let mut written = 0;
+        for segment in self.segments.iter() {
+            let start = segment.seg_off as usize - data_start;
+            let end = start + segment.seg_len as usize;
+            if end > written {
+                // skip the padding preceding the contents so that the recorded offset is exact
+                self.write_data(dest, written..start.max(written))?;
+                written = written.max(start);
+            }
+
+            let seg_off = position(dest)? - u32::try_from(written - start).unwrap();
+            if end > written {
+                self.write_data(dest, written..end)?;
+                written = end;
+            }
+
+            segments.push(SegmentHeader {
+                seg_off,
+                ..segment.clone()
+            });
+        }
+        self.write_data(dest, written..self.data_len)?;
+
+        if let (Some(ticket), TicketPlacement::AfterData) = (ticket, self.ticket_placement) {
+            dest.write_all(&[0; SEGMENT_ALIGNMENT - 1][..padding_before])?;
+            ticket_offset = position(dest)?;
+            dest.write_all(ticket)?;
+        }
+
+        let end = dest.stream_position()?;
+        let header = FtabHeader {
+            ticket_offset,
+            ..self.header()
+        };
+        dest.seek(SeekFrom::Start(start))?;
+        write_metadata(dest, &header, &segments)?;
+        dest.seek(SeekFrom::Start(end))?;
+
+        Ok(end - start)
+    }
+}
+
+/// Writes the header followed by the segment list.
+fn write_metadata<W: Write>(
+    dest: &mut W,
+    header: &FtabHeader,
+    segments: &[SegmentHeader],
+) -> io::Result<()> {
+    // This is safe because of repr(C) and no padding.
+    let header_bytes: &[u8; HEADER_LEN] = unsafe { mem::transmute(header) };
+    let segment_list_bytes: &[u8] = unsafe {
+        slice::from_raw_parts(
+            segments.as_ptr() as *const u8,
+            segments.len() * SEGMENT_HEADER_LEN,
+        )
+    };
+
+    dest.write_all(header_bytes)?;
+    dest.write_all(segment_list_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::{SegmentDesc, Tag};
+    use std::path::PathBuf;
+
+    /// Creates an empty directory for a test under the system's temporary directory.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("ftabutil-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    /// Describes a segment with the specified tag read from a file named after the tag.
+    fn segment(tag: &[u8; 4]) -> SegmentDesc {
+        SegmentDesc {
+            path: PathBuf::from(tag.escape_ascii().to_string()),
+            tag: Tag(*tag),
+            unk: 0,
+            padding: None,
+            expected_len: None,
+            alias_of: None,
+        }
+    }
+
+    /// Fills a builder from a manifest with segments of odd lengths and a 6-byte ticket, so that
+    /// both the segments and the ticket need padding to be aligned.
+    fn try_sample_builder(segments: Vec<SegmentDesc>) -> Result<Builder, BuildError> {
+        let manifest = Manifest {
+            unk_0: 0x5000100,
+            unk_1: u32::MAX,
+            ticket: Some(PathBuf::from("ticket")),
+            segments,
+            ..Default::default()
+        };
+
+        Builder::with_manifest_loader(&manifest, |_, path| {
+            Ok(match path.to_str().unwrap() {
+                "rkos" => b"abc".to_vec(),
+                "rkrn" => b"defgh".to_vec(),
+                "ticket" => b"TICKET".to_vec(),
+                path => panic!("unexpected file {}", path),
+            })
+        })
+    }
+
+    /// Same as [`try_sample_builder`] but panics when the builder fails to be filled.
+    fn sample_builder(segments: Vec<SegmentDesc>) -> Builder {
+        try_sample_builder(segments).unwrap()
+    }
+
+    /// Describes a segment with the specified tag sharing the contents of the segment with the
+    /// `target` tag.
+    fn alias(tag: &[u8; 4], target: &[u8; 4]) -> SegmentDesc {
+        SegmentDesc {
+            // the path is never read for aliases
+            path: PathBuf::from("missing"),
+            alias_of: Some(Tag(*target)),
+            ..segment(tag)
+        }
+    }
+
+    /// Checks that a built file parses and rebuilds from the parsed file into the same bytes.
+    fn assert_round_trips(built: &[u8]) -> Parser<'_> {
+        let parser = Parser::parse(built).unwrap();
+        let rebuilt = Builder::with_parser(&parser).unwrap();
+
+        assert_eq!(rebuilt.to_vec(), built);
+
+        parser
+    }
+
+    #[test]
+    fn unpadded_ticket_round_trip() {
+        let builder = sample_builder(vec![segment(b"rkos"), segment(b"rkrn")]);
+        let built = builder.to_vec();
+        let parser = assert_round_trips(&built);
+
+        // the ticket immediately follows the last segment ending at 80 + 3 + 1 + 5
+        assert_eq!(parser.ticket(), Some(&b"TICKET"[..]));
+        assert_eq!(parser.ticket_range(), Some(89..95));
+        assert_eq!(built.len(), 95);
+    }
+
+    #[test]
+    fn padded_ticket_round_trip() {
+        let mut builder = sample_builder(vec![segment(b"rkos"), segment(b"rkrn")]);
+        builder.set_pad_ticket(true);
+        let built = builder.to_vec();
+        let parser = Parser::parse(&built).unwrap();
+
+        assert_eq!(parser.ticket(), Some(&b"TICKET"[..]));
+        assert_eq!(parser.ticket_range(), Some(92..98));
+        assert_eq!(&built[89..92], [0; 3]);
+
+        // the padding is kept as a part of the parsed file and rebuilt with a padded ticket
+        let mut rebuilt = Builder::with_parser(&parser).unwrap();
+        rebuilt.set_pad_ticket(true);
+        assert_eq!(rebuilt.to_vec(), built);
+    }
+
+    #[test]
+    fn ticket_before_data_round_trip() {
+        let mut builder = sample_builder(vec![segment(b"rkos"), segment(b"rkrn")]);
+        builder.set_ticket_placement(TicketPlacement::BeforeData);
+        let built = builder.to_vec();
+        let parser = Parser::parse(&built).unwrap();
+
+        // the ticket follows the segment list and is padded to keep the data aligned
+        assert_eq!(parser.ticket(), Some(&b"TICKET"[..]));
+        assert_eq!(parser.ticket_range(), Some(80..86));
+        let mut segments = parser.segments();
+        let rkos = segments.next_segment().unwrap().unwrap();
+        assert_eq!((rkos.offset, rkos.data), (88, &b"abc"[..]));
+        let rkrn = segments.next_segment().unwrap().unwrap();
+        assert_eq!((rkrn.offset, rkrn.data), (92, &b"defgh"[..]));
+
+        let mut rebuilt = Builder::with_parser(&parser).unwrap();
+        rebuilt.set_ticket_placement(TicketPlacement::BeforeData);
+        assert_eq!(rebuilt.to_vec(), built);
+
+        // moving the ticket back after the data produces the default layout
+        rebuilt.set_ticket_placement(TicketPlacement::AfterData);
+        let default = sample_builder(vec![segment(b"rkos"), segment(b"rkrn")]);
+        assert_eq!(rebuilt.to_vec(), default.to_vec());
+    }
+
+    #[test]
+    fn ticket_before_data_offset_overflow() {
+        let builder = Builder {
+            segments: vec![SegmentHeader {
+                tag: *b"rkos",
+                seg_off: u32::MAX - 2,
+                seg_len: 0,
+                unk: 0,
+            }],
+            ticket: Some(Box::from(&b"TICKET"[..])),
+            ticket_placement: TicketPlacement::BeforeData,
+            ..Default::default()
+        };
+
+        let error = builder.write_to(&mut Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn alias_shares_target_range() {
+        let builder = sample_builder(vec![
+            segment(b"rkos"),
+            segment(b"rkrn"),
+            alias(b"alia", b"rkos"),
+        ]);
+        let built = builder.to_vec();
+        let parser = assert_round_trips(&built);
+
+        let headers = parser.segments_slice();
+        assert_eq!(headers.len(), 3);
+        assert_eq!(headers[2][..4], *b"alia");
+        // the offset and the length of an alias are the same as the ones of its target
+        assert_eq!(headers[2][4..12], headers[0][4..12]);
+
+        let mut segments = parser.segments();
+        let rkos = segments.next_segment().unwrap().unwrap();
+        segments.next_segment().unwrap().unwrap();
+        let alia = segments.next_segment().unwrap().unwrap();
+        assert_eq!((alia.offset, alia.data), (rkos.offset, &b"abc"[..]));
+    }
+
+    #[test]
+    fn alias_of_missing_segment() {
+        let error =
+            try_sample_builder(vec![segment(b"rkos"), alias(b"alia", b"rkrn")]).unwrap_err();
+        assert!(matches!(
+            error,
+            BuildError::MissingAliasTarget(Tag(alias), Tag(target))
+                if alias == *b"alia" && target == *b"rkrn"
+        ));
+
+        // the target has to precede the alias
+        let error =
+            try_sample_builder(vec![alias(b"alia", b"rkrn"), segment(b"rkrn")]).unwrap_err();
+        assert!(matches!(error, BuildError::MissingAliasTarget(..)));
+    }
+
+    #[test]
+    fn write_to_seek_matches_to_vec() {
+        use std::io::Cursor;
+
+        let segments = vec![segment(b"rkos"), segment(b"rkrn"), alias(b"alia", b"rkos")];
+        let mut padded = sample_builder(segments.clone());
+        padded.set_pad_ticket(true);
+        let mut before_data = sample_builder(segments.clone());
+        before_data.set_ticket_placement(TicketPlacement::BeforeData);
+        let builders = [sample_builder(segments), padded, before_data];
+
+        for builder in builders.iter() {
+            let expected = builder.to_vec();
+
+            let mut cursor = Cursor::new(Vec::new());
+            let written = builder.write_to_seek(&mut cursor).unwrap();
+            assert_eq!(written, expected.len() as u64);
+            assert_eq!(cursor.position(), written);
+            assert_eq!(cursor.into_inner(), expected);
+
+            // offsets are relative to the initial position of the sink
+            let mut cursor = Cursor::new(b"prefix".to_vec());
+            cursor.seek(SeekFrom::End(0)).unwrap();
+            let written = builder.write_to_seek(&mut cursor).unwrap();
+            assert_eq!(cursor.position(), 6 + written);
+            let bytes = cursor.into_inner();
+            assert_eq!(&bytes[..6], b"prefix");
+            assert_eq!(&bytes[6..], &expected[..]);
+        }
+    }
+
+    #[test]
+    fn manifest_unk_fields() {
+        let segments = vec![
+            SegmentDesc {
+                unk: 7,
+                ..segment(b"rkos")
+            },
+            segment(b"rkrn"),
+            SegmentDesc {
+                unk: u32::MAX,
+                ..alias(b"alia", b"rkos")
+            },
+        ];
+        let built = sample_builder(segments).to_vec();
+        let parser = assert_round_trips(&built);
+
+        let mut segments = parser.segments();
+        let mut unks = Vec::new();
+        while let Some(segment) = segments.next_segment().unwrap() {
+            unks.push(segment.unk);
+        }
+        assert_eq!(unks, [7, 0, u32::MAX]);
+    }
+
+    #[test]
+    fn streamed_segments_are_copied_while_writing() {
+        use std::io::Cursor;
+
+        let dir = test_dir("streamed-segments");
+        fs::write(dir.join("rkos"), b"abc").unwrap();
+        fs::write(dir.join("rkrn"), b"defgh").unwrap();
+        fs::write(dir.join("ticket"), b"TICKET").unwrap();
+
+        let segments = vec![segment(b"rkos"), segment(b"rkrn"), alias(b"alia", b"rkos")];
+        let manifest = Manifest {
+            unk_0: 0x5000100,
+            unk_1: u32::MAX,
+            ticket: Some(PathBuf::from("ticket")),
+            segments: segments.clone(),
+            ..Default::default()
+        };
+        let mut streamed =
+            Builder::with_manifest_streamed(&manifest, |_, path| Ok(dir.join(path))).unwrap();
+
+        // only the paths and the lengths of the files are kept
+        assert!(streamed.segment_data_mut(*b"rkos").is_none());
+        assert!(matches!(
+            &streamed.chunks[..],
+            [Chunk::File(..), Chunk::Bytes(padding), Chunk::File(..)] if padding == &[0]
+        ));
+
+        for placement in [TicketPlacement::AfterData, TicketPlacement::BeforeData] {
+            streamed.set_ticket_placement(placement);
+            let mut in_memory = sample_builder(segments.clone());
+            in_memory.set_ticket_placement(placement);
+            let expected = in_memory.to_vec();
+
+            let mut cursor = Cursor::new(Vec::new());
+            let written = streamed.write_to_seek(&mut cursor).unwrap();
+            assert_eq!(written, expected.len() as u64);
+            assert_eq!(cursor.into_inner(), expected);
+            assert_eq!(streamed.to_vec(), expected);
+        }
+
+        // the layout depends on the lengths the files had when the builder was filled
+        fs::write(dir.join("rkrn"), b"defghi").unwrap();
+        let error = streamed
+            .write_to_seek(&mut Cursor::new(Vec::new()))
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
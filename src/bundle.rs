@@ -0,0 +1,151 @@
+//! Provides the [`Bundle`] structure that holds a manifest together with all files referenced by it
+//! in a single file.
+//!
+//! # Layout
+//! All integers are little-endian.
+//!
+//! | Field         | Size      | Description                                |
+//! |---------------|-----------|--------------------------------------------|
+//! | magic         | 8         | The `ftbundle` string.                     |
+//! | entries count | 4         | The number of entries that follow.         |
+//! | entries       | variable  | The entries, one after another.            |
+//!
+//! Each entry is laid out as follows:
+//!
+//! | Field         | Size      | Description                                |
+//! |---------------|-----------|--------------------------------------------|
+//! | name length   | 4         | The byte length of the name.               |
+//! | name          | variable  | The UTF-8 encoded relative path.           |
+//! | data length   | 8         | The byte length of the data.               |
+//! | data          | variable  | The contents of the file.                  |
+//!
+//! The manifest is stored as an entry named [`MANIFEST_NAME`] and other entries are named after
+//! the paths specified in the manifest.
+
+use crate::error::FileOpError;
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+use thiserror::Error;
+
+/// The magic value bundle files start with.
+pub const MAGIC: &[u8; 8] = b"ftbundle";
+
+/// The name of the entry containing the manifest.
+pub const MANIFEST_NAME: &str = "manifest.toml";
+
+/// An error which may occur when parsing a bundle.
+#[derive(Error, Debug)]
+pub enum BundleParseError {
+    /// Returned when the data doesn't start with the bundle magic value.
+    #[error("file is not a bundle (invalid magic value)")]
+    UnknownMagic,
+    /// Returned when an entry extends past the end of the data.
+    #[error("bundle is truncated")]
+    Truncated,
+    /// Returned when an entry name is not a valid UTF-8 string.
+    #[error("bundle entry name is not a valid UTF-8 string")]
+    InvalidName,
+}
+
+/// A collection of named files stored in a single bundle file.
+#[derive(Default, Clone, Debug)]
+pub struct Bundle {
+    entries: Vec<(String, Vec<u8>)>,
+}
+
+/// Splits `len` bytes off the start of a slice returning `None` if the slice is too short.
+fn take(bytes: &[u8], len: usize) -> Option<(&[u8], &[u8])> {
+    (bytes.len() >= len).then(|| bytes.split_at(len))
+}
+
+impl Bundle {
+    /// Creates an empty [`Bundle`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks if the provided data starts with the bundle magic value.
+    pub fn is_bundle(bytes: &[u8]) -> bool {
+        bytes.starts_with(MAGIC)
+    }
+
+    /// Parses a bundle copying all entries.
+    ///
+    /// # Errors
+    /// Returns a [`BundleParseError`] in case the data is not a valid bundle.
+    pub fn parse(bytes: &[u8]) -> Result<Self, BundleParseError> {
+        use BundleParseError::*;
+
+        let bytes = bytes.strip_prefix(MAGIC).ok_or(UnknownMagic)?;
+        let (count, mut bytes) = take(bytes, 4).ok_or(Truncated)?;
+        let count = u32::from_le_bytes(count.try_into().unwrap());
+
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            let (name_len, tail) = take(bytes, 4).ok_or(Truncated)?;
+            let name_len = u32::from_le_bytes(name_len.try_into().unwrap());
+            let (name, tail) = take(tail, name_len.try_into().unwrap()).ok_or(Truncated)?;
+            let name = std::str::from_utf8(name).map_err(|_| InvalidName)?;
+
+            let (data_len, tail) = take(tail, 8).ok_or(Truncated)?;
+            let data_len = u64::from_le_bytes(data_len.try_into().unwrap());
+            let data_len = data_len.try_into().map_err(|_| Truncated)?;
+            let (data, tail) = take(tail, data_len).ok_or(Truncated)?;
+
+            entries.push((name.to_owned(), data.to_vec()));
+            bytes = tail;
+        }
+
+        Ok(Self { entries })
+    }
+
+    /// Adds an entry to the bundle.
+    pub fn add(&mut self, name: String, data: Vec<u8>) {
+        self.entries.push((name, data));
+    }
+
+    /// Returns the data of the entry with the specified name or `None` if there is no such entry.
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.entries
+            .iter()
+            .find(|(entry_name, _)| entry_name == name)
+            .map(|(_, data)| data.as_slice())
+    }
+
+    /// Reads the contents of a file referenced by a manifest from the bundle. This is a counterpart
+    /// of [`read_file`](../util/fn.read_file.html) that can be used with
+    /// [`Builder::with_manifest_loader`](../builder/struct.Builder.html#method.with_manifest_loader).
+    ///
+    /// # Errors
+    /// Returns a boxed [`FileOpError`] with the `FileOpAction::Open` action and the
+    /// `ErrorKind::NotFound` error kind when there is no entry with such name.
+    pub fn read_file(&self, name: &'static str, path: &Path) -> Result<Vec<u8>, Box<FileOpError>> {
+        path.to_str()
+            .and_then(|entry_name| self.get(entry_name))
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| {
+                let error = io::Error::new(io::ErrorKind::NotFound, "no such entry in the bundle");
+                FileOpError::make_open(name, path.to_path_buf(), error)
+            })
+    }
+
+    /// Writes the bundle into anything implementing the `std::io::Write` trait.
+    ///
+    /// # Errors
+    /// Returns an I/O error in case it ever occurs.
+    pub fn write_to<W: Write>(&self, dest: &mut W) -> io::Result<()> {
+        dest.write_all(MAGIC)?;
+        dest.write_all(&u32::try_from(self.entries.len()).unwrap().to_le_bytes())?;
+
+        for (name, data) in self.entries.iter() {
+            dest.write_all(&u32::try_from(name.len()).unwrap().to_le_bytes())?;
+            dest.write_all(name.as_bytes())?;
+            dest.write_all(&u64::try_from(data.len()).unwrap().to_le_bytes())?;
+            dest.write_all(data)?;
+        }
+
+        Ok(())
+    }
+}
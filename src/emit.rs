@@ -0,0 +1,224 @@
+//! Provides conversion of manifests into Rust source snippets which build the same 'ftab' file using
+//! the library.
+//!
+//! The snippets contain the manifest as a [`Manifest`] literal and build the file with
+//! `Builder::with_manifest` or `Builder::with_manifest_loader` rather than adding segments to a
+//! builder one by one. `examples/emitted` contains a snippet generated for a sample manifest.
+
+use ftabutil::manifest::{Manifest, Tag};
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+/// Formats a tag as a Rust expression constructing it.
+fn tag_expr(tag: Tag) -> String {
+    match tag.as_str() {
+        Some(s) => format!("Tag(*b\"{}\")", s),
+        None => format!(
+            "Tag([{:#04x}, {:#04x}, {:#04x}, {:#04x}])",
+            tag.0[0], tag.0[1], tag.0[2], tag.0[3]
+        ),
+    }
+}
+
+/// Formats a path as a Rust expression constructing a `PathBuf`.
+fn path_expr(path: &Path) -> String {
+    format!("PathBuf::from({:?})", path.to_string_lossy())
+}
+
+/// Formats an optional value as a Rust expression using a function formatting the inner value.
+fn option_expr<T>(value: Option<T>, f: impl FnOnce(T) -> String) -> String {
+    match value {
+        Some(value) => format!("Some({})", f(value)),
+        None => "None".to_owned(),
+    }
+}
+
+/// Converts a manifest into a Rust function `build_ftab` returning the bytes of the built file.
+///
+/// By default the files referenced by the manifest are read relative to the directory passed to
+/// the function. When `inline` contains the contents of the files keyed by their paths from the
+/// manifest, the contents are embedded into the snippet as byte string literals instead.
+pub fn manifest_to_rust(manifest: &Manifest, inline: Option<&[(PathBuf, &[u8])]>) -> String {
+    let mut s = String::new();
+
+    s.push_str("use ftabutil::{\n");
+    s.push_str("    builder::Builder,\n");
+    s.push_str("    manifest::{Manifest, SegmentDesc, Tag},\n");
+    s.push_str("};\n");
+    if inline.is_some() {
+        s.push_str("use std::path::PathBuf;\n\n");
+        s.push_str("fn build_ftab() -> Vec<u8> {\n");
+    } else {
+        s.push_str("use std::path::{Path, PathBuf};\n\n");
+        s.push_str("fn build_ftab(dir: &Path) -> Vec<u8> {\n");
+    }
+
+    s.push_str("    let manifest = Manifest {\n");
+    let unks = [
+        manifest.unk_0,
+        manifest.unk_1,
+        manifest.unk_2,
+        manifest.unk_3,
+        manifest.unk_4,
+        manifest.unk_5,
+        manifest.unk_6,
+    ];
+    for (index, unk) in unks.iter().enumerate() {
+        writeln!(s, "        unk_{}: {:#x},", index, unk).unwrap();
+    }
+    writeln!(
+        s,
+        "        label: {},",
+        option_expr(manifest.label.as_ref(), |label| format!(
+            "{:?}.to_owned()",
+            label
+        ))
+    )
+    .unwrap();
+    writeln!(
+        s,
+        "        ticket: {},",
+        option_expr(manifest.ticket.as_deref(), path_expr)
+    )
+    .unwrap();
+    s.push_str("        segments: vec![\n");
+    for segment in manifest.segments.iter() {
+        s.push_str("            SegmentDesc {\n");
+        writeln!(s, "                path: {},", path_expr(&segment.path)).unwrap();
+        writeln!(s, "                tag: {},", tag_expr(segment.tag)).unwrap();
+        writeln!(s, "                unk: {:#x},", segment.unk).unwrap();
+        writeln!(
+            s,
+            "                padding: {},",
+            option_expr(segment.padding.as_ref(), |padding| format!(
+                "b\"{}\".to_vec()",
+                padding.escape_ascii()
+            ))
+        )
+        .unwrap();
+        writeln!(
+            s,
+            "                expected_len: {:?},",
+            segment.expected_len
+        )
+        .unwrap();
+        writeln!(
+            s,
+            "                alias_of: {},",
+            option_expr(segment.alias_of, tag_expr)
+        )
+        .unwrap();
+        s.push_str("            },\n");
+    }
+    s.push_str("        ],\n");
+    s.push_str("        outputs: Vec::new(),\n");
+    s.push_str("    };\n\n");
+
+    match inline {
+        Some(files) => {
+            s.push_str("    Builder::with_manifest_loader(&manifest, |_, path| {\n");
+            s.push_str("        Ok(match path.to_str().unwrap() {\n");
+            for (path, data) in files.iter() {
+                writeln!(
+                    s,
+                    "            {:?} => b\"{}\".to_vec(),",
+                    path.to_string_lossy(),
+                    data.escape_ascii()
+                )
+                .unwrap();
+            }
+            s.push_str("            path => panic!(\"no contents for {}\", path),\n");
+            s.push_str("        })\n");
+            s.push_str("    })\n");
+        }
+        None => s.push_str("    Builder::with_manifest(&manifest, Some(dir))\n"),
+    }
+    s.push_str("    .unwrap()\n");
+    s.push_str("    .to_vec()\n");
+    s.push_str("}\n");
+
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ftabutil::{builder::Builder, manifest::SegmentDesc};
+
+    /// The snippet of the example compiled as a part of the tests.
+    mod emitted {
+        include!("../examples/emitted/snippet.rs");
+
+        pub fn build() -> Vec<u8> {
+            build_ftab()
+        }
+    }
+
+    /// Returns the manifest and the inlined files `examples/emitted/snippet.rs` is generated from.
+    fn sample() -> (Manifest, Vec<(PathBuf, &'static [u8])>) {
+        let segment = |path: &str, tag: [u8; 4]| SegmentDesc {
+            path: PathBuf::from(path),
+            tag: Tag(tag),
+            unk: 0,
+            padding: None,
+            expected_len: None,
+            alias_of: None,
+        };
+
+        let manifest = Manifest {
+            unk_0: 0x1,
+            unk_2: 0xffff_ffff,
+            label: Some("sample \"ftab\"".to_owned()),
+            ticket: Some(PathBuf::from("ApImg4Ticket.der")),
+            segments: vec![
+                SegmentDesc {
+                    expected_len: Some(5),
+                    ..segment("rkos.bin", *b"rkos")
+                },
+                SegmentDesc {
+                    unk: 0x10,
+                    padding: Some(vec![0xff; 3]),
+                    ..segment("rrko.bin", *b"rrko")
+                },
+                segment("00010203.bin", [0, 1, 2, 3]),
+                SegmentDesc {
+                    alias_of: Some(Tag(*b"rkos")),
+                    ..segment("rkos.bin", *b"rkrn")
+                },
+            ],
+            ..Default::default()
+        };
+        let files: Vec<(PathBuf, &'static [u8])> = vec![
+            (PathBuf::from("ApImg4Ticket.der"), b"\x30\x82ticket"),
+            (PathBuf::from("rkos.bin"), b"hello"),
+            (PathBuf::from("rrko.bin"), b"world!!!"),
+            (PathBuf::from("00010203.bin"), b"\x00\"\\\n\xff"),
+        ];
+
+        (manifest, files)
+    }
+
+    #[test]
+    fn emitted_example_is_up_to_date() {
+        let (manifest, files) = sample();
+        assert_eq!(
+            manifest_to_rust(&manifest, Some(&files)),
+            include_str!("../examples/emitted/snippet.rs")
+        );
+    }
+
+    #[test]
+    fn emitted_example_builds_the_same_file() {
+        let (manifest, files) = sample();
+        let expected = Builder::with_manifest_loader(&manifest, |_, path| {
+            let (_, data) = files.iter().find(|(name, _)| name == path).unwrap();
+            Ok(data.to_vec())
+        })
+        .unwrap()
+        .to_vec();
+
+        assert_eq!(emitted::build(), expected);
+    }
+}
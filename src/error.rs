@@ -1,8 +1,13 @@
-use crate::parser::{OobSegmentError, ParseError};
+use crate::{
+    bundle::BundleParseError,
+    manifest::Tag,
+    parser::{OobSegmentError, ParseError, TicketOverlapError},
+};
 use std::{
     error::Error,
     fmt, io,
     path::{Path, PathBuf},
+    process::ExitStatus,
 };
 use thiserror::Error;
 
@@ -101,15 +106,117 @@ impl Error for FileOpError {
     }
 }
 
+/// A type that describes errors which may occur while filling a
+/// [`Builder`](../builder/struct.Builder.html) from a manifest.
+#[derive(Debug, Error)]
+pub enum BuildError {
+    /// An error returned when one of the files referenced by the manifest fails to load.
+    #[error("{0}")]
+    FileOp(#[from] Box<FileOpError>),
+    /// An error returned when a segment is an alias of a tag which isn't used by any earlier
+    /// segment. Contains the tag of the alias and the tag it refers to.
+    #[error("segment with tag {0} is an alias of a missing segment with tag {1}")]
+    MissingAliasTarget(Tag, Tag),
+}
+
+/// A type that unifies the errors returned by the library, so that code using the parser, the
+/// builder and bundles may propagate all of them with `?` into a single error type. The errors of
+/// the command line operations below are more specific and include paths of the files involved.
+#[derive(Debug, Error)]
+pub enum FtabError {
+    /// A catch-all for all file I/O errors.
+    #[error("{0}")]
+    FileOp(#[from] Box<FileOpError>),
+    /// An error returned when the 'ftab' file parser fails while parsing the header.
+    #[error("{0}")]
+    ParseError(#[from] ParseError),
+    /// An error returned when a segment header of a 'ftab' file specifies an out of bounds range.
+    #[error("{0}")]
+    OobSegmentError(#[from] OobSegmentError),
+    /// An error returned when the ticket of a 'ftab' file overlaps one of its segments.
+    #[error("{0}")]
+    TicketOverlapError(#[from] TicketOverlapError),
+    /// An error returned when a bundle fails to parse.
+    #[error("{0}")]
+    BundleParseError(#[from] BundleParseError),
+    /// An error returned when a builder fails to be filled from a manifest.
+    #[error("{0}")]
+    BuildError(#[from] BuildError),
+}
+
 /// A type that describes errors which may be returned by the `pack` operation.
 #[derive(Debug, Error)]
 pub enum PackError<'a> {
     /// A catch-all for all file I/O errors.
     #[error("{0}")]
     FileOp(#[from] Box<FileOpError>),
+    /// An error returned when the builder fails to be filled from the manifest.
+    #[error("{0}")]
+    BuildError(#[from] BuildError),
     /// An error that may occur during manifest parsing.
     #[error("failed to parse the manifest file at {}: {}", .0.display(), .1)]
     ManifestParseError(&'a Path, #[source] toml::de::Error),
+    /// An error that may occur during parsing of a JSON manifest.
+    #[error("failed to parse the manifest file at {}: {}", .0.display(), .1)]
+    JsonManifestParseError(&'a Path, String),
+    /// An error that may occur during bundle parsing.
+    #[error("failed to parse the bundle file at {}: {}", .0.display(), .1)]
+    BundleParseError(&'a Path, #[source] BundleParseError),
+    /// An error returned when a line of a `TAG=PATH` segment list is malformed.
+    #[error("failed to parse the segment list at {} on line {}: {}", .0.display(), .1, .2)]
+    SegmentListParseError(&'a Path, usize, String),
+    /// An error returned when a bundle doesn't contain a manifest.
+    #[error("bundle file at {} doesn't contain a manifest", .0.display())]
+    NoManifestInBundle(&'a Path),
+    /// An error returned when the written 'ftab' file fails to parse during verification.
+    #[error("verification of the written file at {} failed: {}", .0.display(), .1)]
+    OutputParseError(PathBuf, #[source] ParseError),
+    /// An error returned when a segment of the written 'ftab' file is out of bounds during
+    /// verification.
+    #[error("verification of the written file at {} failed: {}", .0.display(), .1)]
+    OutputOobSegmentError(PathBuf, #[source] OobSegmentError),
+    /// An error returned when the header of the written 'ftab' file differs from the header the
+    /// builder was expected to write. Contains the descriptions of the differing fields.
+    #[error(
+        "verification of the written file at {} failed: header differs from the expected one ({})",
+        .0.display(),
+        .1.join(", ")
+    )]
+    OutputHeaderMismatch(PathBuf, Vec<String>),
+    /// An error returned when the built file differs from the reference file.
+    #[error("built file differs from the reference file at {} starting at offset {:#x}", .0.display(), .1)]
+    OutputMismatch(&'a Path, usize),
+    /// An error returned when the built file is a prefix of the reference file or vice versa.
+    #[error(
+        "built file is {} bytes long while the reference file at {} is {} bytes long",
+        .1,
+        .0.display(),
+        .2
+    )]
+    OutputLengthMismatch(&'a Path, usize, usize),
+    /// An error returned when an output of a multi-output manifest references a tag which doesn't
+    /// correspond to any segment.
+    #[error(
+        "output {} in the manifest file at {} references an undefined segment with tag {}",
+        .1.display(),
+        .0.display(),
+        .2
+    )]
+    UndefinedOutputTag(&'a Path, PathBuf, Tag),
+    /// An error returned when comparing the output of a manifest with multiple outputs.
+    #[error("manifest file at {} has multiple outputs and can't be compared to a single file", .0.display())]
+    CompareMultipleOutputs(&'a Path),
+    /// An error returned by a deterministic build when the file of a segment isn't as long as the
+    /// `expected_len` field of the segment.
+    #[error(
+        "segment with tag {} from the manifest file at {} is {} bytes long while {} bytes are \
+        expected, which a deterministic build doesn't allow",
+        .1,
+        .0.display(),
+        .2,
+        .3
+    )]
+    SegmentLengthMismatch(&'a Path, Tag, usize, u32),
 }
 
 /// A type that describes errors which may be returned by the `unpack` operation.
@@ -121,13 +228,216 @@ pub enum UnpackError<'a> {
     /// An error returned when the output directory path points to something other than a directory.
     #[error("path {} exists and is not a directory.", .0.display())]
     OutDirIsNotDir(&'a Path),
+    /// An error returned when the output directory couldn't be created due to insufficient
+    /// permissions.
+    #[error("couldn't create target directory at {}: permission denied", .0.display())]
+    OutDirPermissionDenied(&'a Path, #[source] io::Error),
+    /// An error returned when a component of the output directory path is not a directory.
+    #[error(
+        "couldn't create target directory at {}: a component of the path is not a directory",
+        .0.display()
+    )]
+    OutDirParentIsNotDir(&'a Path, #[source] io::Error),
+    /// An error returned when the parent of the output directory doesn't exist and creating parent
+    /// directories was not requested.
+    #[error(
+        "couldn't create target directory at {}: the parent directory doesn't exist (pass \
+        --create_parent_dirs to create it)",
+        .0.display()
+    )]
+    OutDirParentMissing(&'a Path, #[source] io::Error),
     /// An error returned when the output directory couldn't be created.
     #[error("couldn't create target directory at {}: {}", .0.display(), .1)]
     FailedToCreateOutDir(&'a Path, #[source] io::Error),
+    /// An error returned when the contents of the existing output directory couldn't be listed.
+    #[error("couldn't list the contents of target directory at {}: {}", .0.display(), .1)]
+    FailedToListOutDir(&'a Path, #[source] io::Error),
+    /// An error returned when the output directory is required to be empty but contains entries.
+    #[error("target directory at {} is not empty, it contains {} entries", .0.display(), .1)]
+    OutDirNotEmpty(&'a Path, usize),
+    /// An error returned when the 'ftab' file parser fails while parsing the header.
+    #[error("failed to parse file at {}: {}", .0.display(), .1)]
+    HeaderParseError(&'a Path, #[source] ParseError),
+    /// An error returned when a segment header of a 'ftab' file specifies an out of bounds range.
+    #[error("{0}")]
+    OobSegmentError(#[from] OobSegmentError),
+}
+
+/// A type that describes errors which may be returned by the `info` operation.
+#[derive(Debug, Error)]
+pub enum InfoError<'a> {
+    /// A catch-all for all file I/O errors.
+    #[error("{0}")]
+    FileOp(#[from] Box<FileOpError>),
+    /// An error returned when the 'ftab' file parser fails while parsing the header.
+    #[error("failed to parse file at {}: {}", .0.display(), .1)]
+    HeaderParseError(&'a Path, #[source] ParseError),
+}
+
+/// A type that describes errors which may be returned by the `verify` operation.
+#[derive(Debug, Error)]
+pub enum VerifyError<'a> {
+    /// A catch-all for all file I/O errors.
+    #[error("{0}")]
+    FileOp(#[from] Box<FileOpError>),
+    /// An error returned when the 'ftab' file parser fails while parsing the header.
+    #[error("failed to parse file at {}: {}", .0.display(), .1)]
+    HeaderParseError(&'a Path, #[source] ParseError),
+    /// An error returned when problems were found in a 'ftab' file.
+    #[error("found {} problem(s) in file at {}", .1, .0.display())]
+    ProblemsFound(&'a Path, usize),
+}
+
+/// A type that describes errors which may be returned by the `validate_manifest` operation.
+#[derive(Debug, Error)]
+pub enum ValidateError<'a> {
+    /// A catch-all for all file I/O errors.
+    #[error("{0}")]
+    FileOp(#[from] Box<FileOpError>),
+    /// An error that may occur during manifest parsing.
+    #[error("failed to parse the manifest file at {}: {}", .0.display(), .1)]
+    ManifestParseError(&'a Path, #[source] toml::de::Error),
+    /// An error that may occur during parsing of a JSON manifest.
+    #[error("failed to parse the manifest file at {}: {}", .0.display(), .1)]
+    JsonManifestParseError(&'a Path, String),
+    /// An error that may occur during bundle parsing.
+    #[error("failed to parse the bundle file at {}: {}", .0.display(), .1)]
+    BundleParseError(&'a Path, #[source] BundleParseError),
+    /// An error returned when a bundle doesn't contain a manifest.
+    #[error("bundle file at {} doesn't contain a manifest", .0.display())]
+    NoManifestInBundle(&'a Path),
+    /// An error returned when problems were found in a manifest.
+    #[error("found {} problem(s) in the manifest at {}", .1, .0.display())]
+    ProblemsFound(&'a Path, usize),
+}
+
+/// A type that describes errors which may be returned by operations inspecting segments of a 'ftab'
+/// file like `stat` and `list`.
+#[derive(Debug, Error)]
+pub enum InspectError<'a> {
+    /// A catch-all for all file I/O errors.
+    #[error("{0}")]
+    FileOp(#[from] Box<FileOpError>),
+    /// An error returned when the 'ftab' file parser fails while parsing the header.
+    #[error("failed to parse file at {}: {}", .0.display(), .1)]
+    HeaderParseError(&'a Path, #[source] ParseError),
+    /// An error returned when a segment header of a 'ftab' file specifies an out of bounds range.
+    #[error("{0}")]
+    OobSegmentError(#[from] OobSegmentError),
+}
+
+/// A type that describes errors which may be returned by operations rewriting an existing 'ftab'
+/// file like `strip_ticket`, `set_ticket` and `normalize`.
+#[derive(Debug, Error)]
+pub enum RewriteError<'a> {
+    /// A catch-all for all file I/O errors.
+    #[error("{0}")]
+    FileOp(#[from] Box<FileOpError>),
+    /// An error returned when the 'ftab' file parser fails while parsing the header.
+    #[error("failed to parse file at {}: {}", .0.display(), .1)]
+    HeaderParseError(&'a Path, #[source] ParseError),
+    /// An error returned when a segment header of a 'ftab' file specifies an out of bounds range.
+    #[error("{0}")]
+    OobSegmentError(#[from] OobSegmentError),
+}
+
+/// A type that describes errors which may be returned by the `extract` operation.
+#[derive(Debug, Error)]
+pub enum ExtractError<'a> {
+    /// A catch-all for all file I/O errors.
+    #[error("{0}")]
+    FileOp(#[from] Box<FileOpError>),
+    /// An error returned when the header of a 'ftab' file at the specified offset doesn't fit into
+    /// the input file.
+    #[error(
+        "ftab header at offset {:#x} doesn't fit into file at {} of {} bytes",
+        .1,
+        .0.display(),
+        .2
+    )]
+    OffsetOutOfBounds(&'a Path, usize, usize),
     /// An error returned when the 'ftab' file parser fails while parsing the header.
     #[error("failed to parse file at {}: {}", .0.display(), .1)]
     HeaderParseError(&'a Path, #[source] ParseError),
     /// An error returned when a segment header of a 'ftab' file specifies an out of bounds range.
     #[error("{0}")]
     OobSegmentError(#[from] OobSegmentError),
+    /// An error returned when a 'ftab' file contains no segment with the requested tag.
+    #[error("file at {} contains no segment with tag {}", .0.display(), .1)]
+    TagNotFound(&'a Path, Tag),
+}
+
+/// A type that describes errors which may be returned by the `convert` operation.
+#[derive(Debug, Error)]
+pub enum ConvertError<'a> {
+    /// A catch-all for all file I/O errors.
+    #[error("{0}")]
+    FileOp(#[from] Box<FileOpError>),
+    /// An error returned when the format of a manifest can't be determined from its extension.
+    #[error(
+        "can't determine the format of the manifest at {} from its extension, expected .toml or \
+        .json",
+        .0.display()
+    )]
+    UnknownFormat(&'a Path),
+    /// An error returned when the input manifest fails to parse.
+    #[error("failed to parse the manifest file at {}: {}", .0.display(), .1)]
+    ManifestParseError(&'a Path, String),
+}
+
+/// A type that describes errors which may be returned by the `selftest` operation.
+#[derive(Debug, Error)]
+pub enum SelftestError<'a> {
+    /// A catch-all for all file I/O errors.
+    #[error("{0}")]
+    FileOp(#[from] Box<FileOpError>),
+    /// An error returned when the 'ftab' file parser fails while parsing the header.
+    #[error("failed to parse file at {}: {}", .0.display(), .1)]
+    HeaderParseError(&'a Path, #[source] ParseError),
+    /// An error returned when a segment header of a 'ftab' file specifies an out of bounds range.
+    #[error("{0}")]
+    OobSegmentError(#[from] OobSegmentError),
+    /// An error returned when a rebuilt file differs from the file rebuilt in the first iteration.
+    #[error("file rebuilt in iteration {0} differs from the one rebuilt in the first iteration")]
+    RebuildMismatch(usize),
+}
+
+/// A type that describes errors which may be returned by the `batch` operation.
+#[derive(Debug, Error)]
+pub enum BatchError<'a> {
+    /// A catch-all for all file I/O errors.
+    #[error("{0}")]
+    FileOp(#[from] Box<FileOpError>),
+    /// An error returned when the operation failed for some of the listed files.
+    #[error("{} of {} file(s) listed in {} failed", .1, .2, .0.display())]
+    FilesFailed(&'a Path, usize, usize),
+    /// An error returned when two listed files would be unpacked into the same directory. Nothing
+    /// is unpacked in this case.
+    #[error(
+        "files at {} and {} would both be unpacked into the directory {}",
+        .0.display(),
+        .1.display(),
+        .2.display()
+    )]
+    OutputDirCollision(PathBuf, PathBuf, PathBuf),
+}
+
+/// A type that describes errors which may be returned by the `repack` operation.
+#[derive(Debug, Error)]
+pub enum RepackError<'a> {
+    /// An error returned when the temporary directory couldn't be created.
+    #[error("couldn't create temporary directory at {}: {}", .0.display(), .1)]
+    FailedToCreateTempDir(PathBuf, #[source] io::Error),
+    /// An error returned when unpacking into the temporary directory fails.
+    #[error("unpacking failed: {0}")]
+    UnpackFailed(UnpackError<'a>),
+    /// An error returned when the edit command couldn't be started.
+    #[error("couldn't run command `{0}`: {1}")]
+    ExecFailed(String, #[source] io::Error),
+    /// An error returned when the edit command exits unsuccessfully.
+    #[error("command `{0}` failed with {1}")]
+    CommandFailed(String, ExitStatus),
+    /// An error returned when packing the edited files fails.
+    #[error("packing failed: {0}")]
+    PackFailed(PackError<'a>),
 }
@@ -0,0 +1,85 @@
+//! Provides shell-style expansion of `~` and environment variables in paths passed on the command
+//! line, which is useful when the tool is invoked from configs that don't go through a shell.
+
+use clap::{builder::TypedValueParser, error::ErrorKind, Arg, Command, Error};
+use std::{env, ffi::OsStr, path::PathBuf};
+
+/// Checks if a character may appear in an environment variable name.
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Expands a leading `~` or `~/` into the value of the `HOME` environment variable and `$NAME` or
+/// `${NAME}` anywhere in the path into the value of the `NAME` environment variable.
+///
+/// Like in a shell, `~` is only expanded at the start of the path and `~user` forms are not
+/// supported. Unlike in a shell, references to unset variables are left as is.
+pub fn expand_path(path: &str) -> PathBuf {
+    let mut expanded = String::with_capacity(path.len());
+    let mut rest = path;
+
+    if rest == "~" || rest.starts_with("~/") {
+        if let Ok(home) = env::var("HOME") {
+            expanded.push_str(&home);
+            rest = &rest[1..];
+        }
+    }
+
+    while let Some(index) = rest.find('$') {
+        expanded.push_str(&rest[..index]);
+        let after = &rest[index + 1..];
+
+        let (name, tail) = match after.strip_prefix('{').and_then(|braced| {
+            let end = braced.find('}')?;
+            Some((&braced[..end], &braced[end + 1..]))
+        }) {
+            Some(braced) => braced,
+            None => {
+                let end = after.find(|c| !is_name_char(c)).unwrap_or(after.len());
+                after.split_at(end)
+            }
+        };
+
+        // A `$` not followed by a valid name is kept as is.
+        if name.is_empty() || !name.chars().all(is_name_char) {
+            expanded.push('$');
+            rest = after;
+            continue;
+        }
+
+        match env::var(name) {
+            Ok(value) => expanded.push_str(&value),
+            Err(_) => expanded.push_str(&rest[index..rest.len() - tail.len()]),
+        }
+        rest = tail;
+    }
+    expanded.push_str(rest);
+
+    PathBuf::from(expanded)
+}
+
+/// A value parser for path arguments which applies [`expand_path`] to paths that are valid UTF-8
+/// when the wrapped flag is set and passes other paths through unchanged. Empty paths are rejected
+/// either way.
+#[derive(Copy, Clone, Debug)]
+pub struct ExpandedPathParser(pub bool);
+
+impl TypedValueParser for ExpandedPathParser {
+    type Value = PathBuf;
+
+    fn parse_ref(
+        &self,
+        cmd: &Command,
+        _arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, Error> {
+        if value.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidValue).with_cmd(cmd));
+        }
+
+        Ok(match value.to_str() {
+            Some(value) if self.0 => expand_path(value),
+            _ => PathBuf::from(value),
+        })
+    }
+}
@@ -2,8 +2,18 @@ use std::mem;
 
 pub const HEADER_LEN: usize = mem::size_of::<FtabHeader>();
 pub const SEGMENT_HEADER_LEN: usize = mem::size_of::<SegmentHeader>();
+/// The alignment of the contents of segments in files produced by the builder.
+pub const SEGMENT_ALIGNMENT: usize = 4;
 
-#[derive(Clone, Debug)]
+/// Returns the number of zero bytes the builder writes after `offset` to align the following data
+/// to [`SEGMENT_ALIGNMENT`].
+pub const fn alignment_padding(offset: usize) -> usize {
+    (SEGMENT_ALIGNMENT - offset % SEGMENT_ALIGNMENT) % SEGMENT_ALIGNMENT
+}
+
+/// The header of a 'ftab' file. This is a plain owned value, so it may be kept around after the
+/// buffer it was parsed from is dropped.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
 #[repr(C)]
 pub struct FtabHeader {
     pub unk_0: u32,
@@ -19,7 +29,7 @@ pub struct FtabHeader {
     pub unk_6: u32,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Eq, PartialEq, Debug)]
 #[repr(C)]
 pub struct SegmentHeader {
     pub tag: [u8; 4],
@@ -27,3 +37,37 @@ pub struct SegmentHeader {
     pub seg_len: u32,
     pub unk: u32,
 }
+
+impl FtabHeader {
+    /// Describes every field that differs between two headers as `name: <self> != <other>`, e.g.
+    /// `ticket_offset: 0x60 != 0x64`. Returns an empty vector when the headers are equal.
+    pub fn diff(&self, other: &FtabHeader) -> Vec<String> {
+        let fields = [
+            ("unk_0", self.unk_0, other.unk_0),
+            ("unk_1", self.unk_1, other.unk_1),
+            ("unk_2", self.unk_2, other.unk_2),
+            ("unk_3", self.unk_3, other.unk_3),
+            ("ticket_offset", self.ticket_offset, other.ticket_offset),
+            ("ticket_len", self.ticket_len, other.ticket_len),
+            ("unk_4", self.unk_4, other.unk_4),
+            ("unk_5", self.unk_5, other.unk_5),
+            ("segments_count", self.segments_count, other.segments_count),
+            ("unk_6", self.unk_6, other.unk_6),
+        ];
+
+        let mut diff: Vec<String> = fields
+            .iter()
+            .filter(|(_, ours, theirs)| ours != theirs)
+            .map(|(name, ours, theirs)| format!("{}: {:#x} != {:#x}", name, ours, theirs))
+            .collect();
+        if self.magic != other.magic {
+            diff.push(format!(
+                "magic: {} != {}",
+                self.magic.escape_ascii(),
+                other.magic.escape_ascii()
+            ));
+        }
+
+        diff
+    }
+}
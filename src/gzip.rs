@@ -0,0 +1,518 @@
+//! Provides decompression of gzip-compressed files, see RFC 1952 for the container and RFC 1951 for
+//! the DEFLATE format it wraps.
+//!
+//! Only decompression is implemented. Files consisting of several concatenated gzip members are
+//! decompressed into the concatenation of their contents, and zero bytes following the last member
+//! are ignored.
+
+use crate::checksum::crc32;
+use thiserror::Error;
+
+/// The magic value gzip files start with.
+pub const MAGIC: &[u8; 2] = b"\x1f\x8b";
+
+/// The compression method identifier of DEFLATE, the only method defined for gzip.
+const METHOD_DEFLATE: u8 = 8;
+
+/// Flags from the gzip member header.
+const FLAG_HCRC: u8 = 0x02;
+const FLAG_EXTRA: u8 = 0x04;
+const FLAG_NAME: u8 = 0x08;
+const FLAG_COMMENT: u8 = 0x10;
+const FLAGS_RESERVED: u8 = 0xe0;
+
+/// The longest code length allowed by DEFLATE.
+const MAX_CODE_LEN: usize = 15;
+
+/// Base lengths and extra bits counts of the length symbols 257..=285.
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+/// Base distances and extra bits counts of the distance symbols 0..=29.
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// The order in which code length code lengths are stored in a dynamic block header.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// An error which may occur when decompressing gzip data.
+#[derive(Error, Debug)]
+pub enum GzipError {
+    /// Returned when the data doesn't start with the gzip magic value.
+    #[error("data is not gzip-compressed (invalid magic value)")]
+    UnknownMagic,
+    /// Returned when a member uses a compression method other than DEFLATE.
+    #[error("gzip compression method {0} is not supported")]
+    UnsupportedMethod(u8),
+    /// Returned when the data ends in the middle of a member.
+    #[error("gzip data is truncated")]
+    Truncated,
+    /// Returned when the header or the compressed data of a member is malformed.
+    #[error("gzip data is corrupted ({0})")]
+    Corrupted(&'static str),
+    /// Returned when the CRC-32 of the decompressed data doesn't match the one from the trailer.
+    #[error("CRC-32 of the decompressed data doesn't match the gzip trailer")]
+    ChecksumMismatch,
+    /// Returned when the length of the decompressed data doesn't match the one from the trailer.
+    #[error("length of the decompressed data doesn't match the gzip trailer")]
+    LengthMismatch,
+}
+
+/// Checks if the provided data starts with the gzip magic value.
+pub fn is_gzip(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Decompresses gzip-compressed data.
+///
+/// # Errors
+/// Returns a [`GzipError`] in case the data is not valid gzip data or its checksum doesn't match.
+pub fn decompress(mut bytes: &[u8]) -> Result<Vec<u8>, GzipError> {
+    let mut decompressed = Vec::new();
+
+    loop {
+        let mut reader = BitReader::new(skip_header(bytes)?);
+        let mut member = Vec::new();
+        inflate(&mut reader, &mut member)?;
+
+        let trailer = reader.take_bytes(8)?;
+        let (crc, len) = trailer.split_at(4);
+        if crc32(&member) != u32::from_le_bytes(crc.try_into().unwrap()) {
+            return Err(GzipError::ChecksumMismatch);
+        }
+        if member.len() as u32 != u32::from_le_bytes(len.try_into().unwrap()) {
+            return Err(GzipError::LengthMismatch);
+        }
+        decompressed.extend_from_slice(&member);
+
+        bytes = reader.remaining();
+        if bytes.iter().all(|&byte| byte == 0) {
+            return Ok(decompressed);
+        }
+    }
+}
+
+/// Splits `len` bytes off the start of a slice returning [`GzipError::Truncated`] if the slice is
+/// too short.
+fn take(bytes: &[u8], len: usize) -> Result<(&[u8], &[u8]), GzipError> {
+    if bytes.len() >= len {
+        Ok(bytes.split_at(len))
+    } else {
+        Err(GzipError::Truncated)
+    }
+}
+
+/// Splits a zero-terminated string off the start of a slice.
+fn skip_string(bytes: &[u8]) -> Result<&[u8], GzipError> {
+    let len = bytes
+        .iter()
+        .position(|&byte| byte == 0)
+        .ok_or(GzipError::Truncated)?;
+
+    Ok(&bytes[len + 1..])
+}
+
+/// Validates the header of a gzip member and returns the data following it.
+fn skip_header(bytes: &[u8]) -> Result<&[u8], GzipError> {
+    let (header, mut tail) = take(bytes, 10)?;
+    if !header.starts_with(MAGIC) {
+        return Err(GzipError::UnknownMagic);
+    }
+    if header[2] != METHOD_DEFLATE {
+        return Err(GzipError::UnsupportedMethod(header[2]));
+    }
+
+    let flags = header[3];
+    if flags & FLAGS_RESERVED != 0 {
+        return Err(GzipError::Corrupted("reserved header flags are set"));
+    }
+    if flags & FLAG_EXTRA != 0 {
+        let (len, rest) = take(tail, 2)?;
+        let len = u16::from_le_bytes(len.try_into().unwrap());
+        (_, tail) = take(rest, len.into())?;
+    }
+    if flags & FLAG_NAME != 0 {
+        tail = skip_string(tail)?;
+    }
+    if flags & FLAG_COMMENT != 0 {
+        tail = skip_string(tail)?;
+    }
+    if flags & FLAG_HCRC != 0 {
+        let (crc, rest) = take(tail, 2)?;
+        let header_len = bytes.len() - tail.len();
+        if crc32(&bytes[..header_len]) as u16 != u16::from_le_bytes(crc.try_into().unwrap()) {
+            return Err(GzipError::Corrupted("header CRC mismatch"));
+        }
+        tail = rest;
+    }
+
+    Ok(tail)
+}
+
+/// Reads bits from a byte slice starting from the least significant bit of each byte as DEFLATE
+/// requires.
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    bits: u32,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            bits: 0,
+            count: 0,
+        }
+    }
+
+    /// Reads an integer stored in `count` bits, at most 16.
+    fn bits(&mut self, count: u32) -> Result<u32, GzipError> {
+        while self.count < count {
+            let (&byte, tail) = self.bytes.split_first().ok_or(GzipError::Truncated)?;
+            self.bytes = tail;
+            self.bits |= u32::from(byte) << self.count;
+            self.count += 8;
+        }
+
+        let value = self.bits & ((1 << count) - 1);
+        self.bits >>= count;
+        self.count -= count;
+
+        Ok(value)
+    }
+
+    /// Discards the bits remaining in the current byte. Bytes are only loaded when needed, so less
+    /// than 8 bits are ever buffered between reads.
+    fn align(&mut self) {
+        self.bits = 0;
+        self.count = 0;
+    }
+
+    /// Reads `len` whole bytes starting from the next byte boundary.
+    fn take_bytes(&mut self, len: usize) -> Result<&'a [u8], GzipError> {
+        self.align();
+        let (bytes, tail) = take(self.bytes, len)?;
+        self.bytes = tail;
+
+        Ok(bytes)
+    }
+
+    /// Returns the bytes following the current byte.
+    fn remaining(&self) -> &'a [u8] {
+        self.bytes
+    }
+}
+
+/// A canonical Huffman code described by the number of codes of each length and the symbols
+/// ordered by their codes.
+struct Huffman {
+    counts: [u16; MAX_CODE_LEN + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    /// Constructs a code from the code lengths of the symbols, a zero length means that a symbol
+    /// isn't used. Incomplete codes are allowed since DEFLATE permits them in some cases, decoding a
+    /// missing code is an error.
+    fn new(lengths: &[u8]) -> Result<Self, GzipError> {
+        let mut counts = [0u16; MAX_CODE_LEN + 1];
+        for &len in lengths {
+            counts[usize::from(len)] += 1;
+        }
+
+        let mut left = 1i32;
+        for &count in counts[1..].iter() {
+            left = (left << 1) - i32::from(count);
+            if left < 0 {
+                return Err(GzipError::Corrupted("over-subscribed Huffman code"));
+            }
+        }
+
+        let mut offsets = [0u16; MAX_CODE_LEN + 1];
+        for len in 1..MAX_CODE_LEN {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                let offset = &mut offsets[usize::from(len)];
+                symbols[usize::from(*offset)] = symbol as u16;
+                *offset += 1;
+            }
+        }
+
+        Ok(Self { counts, symbols })
+    }
+
+    /// Decodes a single symbol reading its code bit by bit.
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, GzipError> {
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+        for &count in self.counts[1..].iter() {
+            code |= reader.bits(1)? as i32;
+            let count = i32::from(count);
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(GzipError::Corrupted("invalid Huffman code"))
+    }
+}
+
+/// Decompresses DEFLATE blocks until the last one appending their contents to `out`.
+fn inflate(reader: &mut BitReader, out: &mut Vec<u8>) -> Result<(), GzipError> {
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => inflate_stored(reader, out)?,
+            1 => {
+                let (lengths, distances) = fixed_codes()?;
+                inflate_codes(reader, out, &lengths, &distances)?;
+            }
+            2 => {
+                let (lengths, distances) = dynamic_codes(reader)?;
+                inflate_codes(reader, out, &lengths, &distances)?;
+            }
+            _ => return Err(GzipError::Corrupted("invalid block type")),
+        }
+
+        if last {
+            return Ok(());
+        }
+    }
+}
+
+/// Copies the contents of a block stored without compression.
+fn inflate_stored(reader: &mut BitReader, out: &mut Vec<u8>) -> Result<(), GzipError> {
+    let header = reader.take_bytes(4)?;
+    let len = u16::from_le_bytes([header[0], header[1]]);
+    let len_complement = u16::from_le_bytes([header[2], header[3]]);
+    if len != !len_complement {
+        return Err(GzipError::Corrupted("stored block length mismatch"));
+    }
+
+    out.extend_from_slice(reader.take_bytes(len.into())?);
+
+    Ok(())
+}
+
+/// Constructs the literal/length and distance codes of blocks compressed with fixed codes.
+fn fixed_codes() -> Result<(Huffman, Huffman), GzipError> {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+/// Reads the literal/length and distance codes from the header of a block compressed with dynamic
+/// codes.
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), GzipError> {
+    let lengths_count = reader.bits(5)? as usize + 257;
+    let distances_count = reader.bits(5)? as usize + 1;
+    let code_lengths_count = reader.bits(4)? as usize + 4;
+    if lengths_count > 286 || distances_count > 30 {
+        return Err(GzipError::Corrupted("too many length or distance codes"));
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for &symbol in CODE_LENGTH_ORDER[..code_lengths_count].iter() {
+        code_lengths[symbol] = reader.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths)?;
+
+    let total = lengths_count + distances_count;
+    let mut lengths = Vec::with_capacity(total);
+    while lengths.len() < total {
+        let symbol = code_lengths.decode(reader)?;
+        let (len, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let &prev = lengths
+                    .last()
+                    .ok_or(GzipError::Corrupted("repeated length with no first length"))?;
+                (prev, 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        if lengths.len() + repeat > total {
+            return Err(GzipError::Corrupted("too many code lengths"));
+        }
+        lengths.resize(lengths.len() + repeat, len);
+    }
+
+    if lengths[256] == 0 {
+        return Err(GzipError::Corrupted("missing end-of-block code"));
+    }
+
+    let (lengths, distances) = lengths.split_at(lengths_count);
+    Ok((Huffman::new(lengths)?, Huffman::new(distances)?))
+}
+
+/// Decompresses the contents of a block compressed with the provided codes.
+fn inflate_codes(
+    reader: &mut BitReader,
+    out: &mut Vec<u8>,
+    lengths: &Huffman,
+    distances: &Huffman,
+) -> Result<(), GzipError> {
+    loop {
+        let symbol = usize::from(lengths.decode(reader)?);
+        if symbol < 256 {
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+
+        let symbol = symbol - 257;
+        if symbol >= LENGTH_BASE.len() {
+            return Err(GzipError::Corrupted("invalid length symbol"));
+        }
+        let len =
+            usize::from(LENGTH_BASE[symbol]) + reader.bits(LENGTH_EXTRA[symbol].into())? as usize;
+
+        let symbol = usize::from(distances.decode(reader)?);
+        if symbol >= DISTANCE_BASE.len() {
+            return Err(GzipError::Corrupted("invalid distance symbol"));
+        }
+        let distance = usize::from(DISTANCE_BASE[symbol])
+            + reader.bits(DISTANCE_EXTRA[symbol].into())? as usize;
+        if distance > out.len() {
+            return Err(GzipError::Corrupted("distance is too far back"));
+        }
+
+        // The copied range may overlap with the bytes being appended, so copy byte by byte.
+        let start = out.len() - distance;
+        for index in start..start + len {
+            out.push(out[index]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `rkosftab` compressed into a single stored block.
+    const STORED: &[u8] =
+        b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x00\xff\x01\x08\x00\xf7\xff\x72\x6b\x6f\
+        \x73\x66\x74\x61\x62\xca\x44\xd9\xd7\x08\x00\x00\x00";
+
+    /// `rkos rkos rkos rkrn` compressed with fixed codes.
+    const FIXED: &[u8] =
+        b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\xff\x2b\xca\xce\x2f\x56\x28\x42\x22\
+        \x8a\xf2\x00\x52\x7e\xe8\xc7\x13\x00\x00\x00";
+
+    /// The lines produced by [`named_contents`] compressed with fixed codes into a member with the
+    /// `ftab.bin` file name in its header.
+    const FIXED_NAMED: &[u8] =
+        b"\x1f\x8b\x08\x08\x00\x00\x00\x00\x02\xff\x66\x74\x61\x62\x2e\x62\x69\
+        \x6e\x00\x2b\x4e\x4d\xcf\x4d\xcd\x2b\x51\x30\x50\x28\x49\x4c\x57\x28\xca\xce\x2f\xe6\x2a\
+        \x86\x0a\x19\x62\x0a\x19\x61\x0a\x19\x63\x0a\x99\x60\x0a\x99\x62\x0a\x99\x61\x0a\x8d\x3a\
+        \x62\xd4\x11\x74\x72\x04\x00\xc2\x50\x46\x35\xf8\x02\x00\x00";
+
+    /// The lines produced by [`dynamic_contents`] compressed with dynamic codes.
+    const DYNAMIC: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0xff, 0x5d, 0xcf, 0x61, 0x0a, 0xc2,
+        0x30, 0x0c, 0x86, 0xe1, 0xff, 0x3d, 0xc5, 0x77, 0x84, 0x6c, 0xc9, 0x9a, 0xed, 0x38, 0x2a,
+        0x55, 0xa4, 0x38, 0xa1, 0xeb, 0xfd, 0xd1, 0xa2, 0x83, 0x24, 0xbf, 0x02, 0x21, 0x3c, 0xe4,
+        0x6d, 0xf5, 0x7d, 0x80, 0xd0, 0xbe, 0x23, 0xb5, 0xda, 0x76, 0xb0, 0xe2, 0x28, 0x8f, 0x57,
+        0xd9, 0x7b, 0xba, 0xf7, 0xcb, 0x15, 0x2a, 0x18, 0xfb, 0xf4, 0x5f, 0x62, 0x22, 0xf4, 0xe7,
+        0xad, 0x96, 0x9e, 0x7e, 0x03, 0xa2, 0x18, 0x87, 0x69, 0x10, 0x58, 0xc5, 0x50, 0x33, 0x79,
+        0x6a, 0x51, 0x4f, 0x6d, 0x12, 0x28, 0x26, 0x43, 0x65, 0xb5, 0x5f, 0x79, 0x49, 0xc8, 0x4b,
+        0xaa, 0x41, 0x9a, 0xd8, 0x48, 0x8b, 0xed, 0x5b, 0x43, 0xdf, 0xcc, 0x9e, 0xca, 0xb1, 0x6f,
+        0xb3, 0x7d, 0xcc, 0x86, 0xd2, 0xd0, 0x97, 0xbd, 0x24, 0x7c, 0x4a, 0x1f, 0x34, 0x71, 0xef,
+        0xdc, 0x65, 0x01, 0x00, 0x00,
+    ];
+
+    fn named_contents() -> Vec<u8> {
+        (0..40)
+            .flat_map(|i| format!("segment {} tag rkos\n", i % 7).into_bytes())
+            .collect()
+    }
+
+    fn dynamic_contents() -> Vec<u8> {
+        let words = ["rkos", "rkrn", "ftab", "segment", "ticket"];
+        (0..24)
+            .map(|i| format!("{} {} {}\n", words[i % 5], i * 37 % 101, words[i * 3 % 5]))
+            .flat_map(String::into_bytes)
+            .collect()
+    }
+
+    #[test]
+    fn stored_block() {
+        assert_eq!(decompress(STORED).unwrap(), b"rkosftab");
+    }
+
+    #[test]
+    fn fixed_codes() {
+        assert_eq!(decompress(FIXED).unwrap(), b"rkos rkos rkos rkrn");
+        assert_eq!(decompress(FIXED_NAMED).unwrap(), named_contents());
+    }
+
+    #[test]
+    fn dynamic_codes() {
+        assert_eq!(decompress(DYNAMIC).unwrap(), dynamic_contents());
+    }
+
+    #[test]
+    fn concatenated_members() {
+        let mut data = [STORED, FIXED].concat();
+        data.extend_from_slice(&[0; 16]);
+
+        assert_eq!(decompress(&data).unwrap(), b"rkosftabrkos rkos rkos rkrn");
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let mut data = DYNAMIC.to_vec();
+        let crc_offset = data.len() - 8;
+        data[crc_offset] ^= 1;
+
+        assert!(matches!(
+            decompress(&data),
+            Err(GzipError::ChecksumMismatch)
+        ));
+    }
+
+    #[test]
+    fn truncated() {
+        for len in [0, 5, 12, DYNAMIC.len() / 2, DYNAMIC.len() - 1] {
+            assert!(
+                matches!(decompress(&DYNAMIC[..len]), Err(GzipError::Truncated)),
+                "length {}",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn not_gzip() {
+        assert!(!is_gzip(b"rkosftab"));
+        assert!(matches!(decompress(&[0; 16]), Err(GzipError::UnknownMagic)));
+    }
+}
@@ -0,0 +1,342 @@
+//! Provides a minimal JSON value type used to print structured output and a parser for reading
+//! JSON manifests.
+
+use std::{
+    borrow::Cow,
+    fmt::{self, Write},
+    iter::Peekable,
+    str::Chars,
+};
+
+/// A JSON value.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<Value>),
+    /// An object with keys in the order they should be printed.
+    Object(Vec<(Cow<'static, str>, Value)>),
+}
+
+/// Writes a string as a quoted and escaped JSON string.
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            c if u32::from(c) < 0x20 => write!(f, "\\u{:04x}", u32::from(c))?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+/// Writes a line break followed by two spaces of indentation per nesting level.
+fn write_indent(f: &mut fmt::Formatter, level: usize) -> fmt::Result {
+    f.write_char('\n')?;
+    for _ in 0..level {
+        f.write_str("  ")?;
+    }
+    Ok(())
+}
+
+impl Value {
+    /// Writes the value as JSON. When `pretty` is set, every element of a non-empty array or object
+    /// is written on its own line indented according to the nesting level `level`.
+    fn write(&self, f: &mut fmt::Formatter, pretty: bool, level: usize) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Integer(value) => write!(f, "{}", value),
+            // JSON has no representation for NaN and infinities.
+            Value::Float(value) if !value.is_finite() => f.write_str("null"),
+            Value::Float(value) => write!(f, "{:?}", value),
+            Value::String(s) => write_string(f, s),
+            Value::Array(values) => {
+                f.write_char('[')?;
+                for (i, value) in values.iter().enumerate() {
+                    if i != 0 {
+                        f.write_char(',')?;
+                    }
+                    if pretty {
+                        write_indent(f, level + 1)?;
+                    }
+                    value.write(f, pretty, level + 1)?;
+                }
+                if pretty && !values.is_empty() {
+                    write_indent(f, level)?;
+                }
+                f.write_char(']')
+            }
+            Value::Object(fields) => {
+                f.write_char('{')?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i != 0 {
+                        f.write_char(',')?;
+                    }
+                    if pretty {
+                        write_indent(f, level + 1)?;
+                    }
+                    write_string(f, key)?;
+                    f.write_str(if pretty { ": " } else { ":" })?;
+                    value.write(f, pretty, level + 1)?;
+                }
+                if pretty && !fields.is_empty() {
+                    write_indent(f, level)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    /// Writes the value as compact JSON or as indented JSON with the alternate flag, i.e. `{:#}`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, f.alternate(), 0)
+    }
+}
+
+/// A recursive descent JSON parser.
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self
+            .chars
+            .next_if(|c| matches!(c, ' ' | '\t' | '\n' | '\r'))
+            .is_some()
+        {}
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(format!("expected '{}', found '{}'", expected, c)),
+            None => Err(format!("expected '{}', found the end of input", expected)),
+        }
+    }
+
+    fn expect_word(&mut self, word: &str, value: Value) -> Result<Value, String> {
+        for c in word.chars() {
+            self.expect(c)?;
+        }
+        Ok(value)
+    }
+
+    fn parse_value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('n') => self.expect_word("null", Value::Null),
+            Some('t') => self.expect_word("true", Value::Bool(true)),
+            Some('f') => self.expect_word("false", Value::Bool(false)),
+            Some('"') => self.parse_string().map(Value::String),
+            Some('[') => self.parse_array(),
+            Some('{') => self.parse_object(),
+            Some(c) if *c == '-' || c.is_ascii_digit() => self.parse_number(),
+            Some(c) => Err(format!("unexpected character '{}'", c)),
+            None => Err("unexpected end of input".to_owned()),
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<Value, String> {
+        let mut number = String::new();
+        while let Some(c) = self
+            .chars
+            .next_if(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+        {
+            number.push(c);
+        }
+
+        if let Ok(value) = number.parse() {
+            Ok(Value::Integer(value))
+        } else {
+            number
+                .parse()
+                .map(Value::Float)
+                .map_err(|_| format!("invalid number '{}'", number))
+        }
+    }
+
+    fn parse_hex_escape(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .chars
+                .next()
+                .and_then(|c| c.to_digit(16))
+                .ok_or("invalid \\u escape")?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    /// Parses the `\u` escape of the low surrogate that has to follow a high surrogate.
+    fn parse_low_surrogate(&mut self) -> Result<u32, String> {
+        let escape = (self.chars.next(), self.chars.next());
+        if escape != (Some('\\'), Some('u')) {
+            return Err("high surrogate is not followed by a \\u escape".to_owned());
+        }
+
+        let low = self.parse_hex_escape()?;
+        if (0xdc00..0xe000).contains(&low) {
+            Ok(low)
+        } else {
+            Err(format!("invalid low surrogate \\u{:04x}", low))
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+
+        let mut s = String::new();
+        loop {
+            match self.chars.next().ok_or("unterminated string")? {
+                '"' => break Ok(s),
+                '\\' => match self.chars.next().ok_or("unterminated string")? {
+                    '"' => s.push('"'),
+                    '\\' => s.push('\\'),
+                    '/' => s.push('/'),
+                    'b' => s.push('\u{8}'),
+                    'f' => s.push('\u{c}'),
+                    'n' => s.push('\n'),
+                    'r' => s.push('\r'),
+                    't' => s.push('\t'),
+                    'u' => {
+                        let mut code = self.parse_hex_escape()?;
+                        // Characters outside the BMP are encoded as surrogate pairs.
+                        if (0xd800..0xdc00).contains(&code) {
+                            let low = self.parse_low_surrogate()?;
+                            code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                        }
+                        s.push(char::from_u32(code).ok_or("invalid \\u escape")?);
+                    }
+                    c => return Err(format!("invalid escape '\\{}'", c)),
+                },
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        self.skip_whitespace();
+
+        let mut values = Vec::new();
+        if self.chars.next_if_eq(&']').is_some() {
+            return Ok(Value::Array(values));
+        }
+        loop {
+            values.push(self.parse_value()?);
+            self.skip_whitespace();
+            if self.chars.next_if_eq(&',').is_none() {
+                self.expect(']')?;
+                break Ok(Value::Array(values));
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        self.skip_whitespace();
+
+        let mut fields = Vec::new();
+        if self.chars.next_if_eq(&'}').is_some() {
+            return Ok(Value::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            fields.push((Cow::Owned(key), self.parse_value()?));
+            self.skip_whitespace();
+            if self.chars.next_if_eq(&',').is_none() {
+                self.expect('}')?;
+                break Ok(Value::Object(fields));
+            }
+        }
+    }
+}
+
+/// Parses a JSON document.
+pub fn parse(s: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        chars: s.chars().peekable(),
+    };
+    let value = parser.parse_value()?;
+
+    parser.skip_whitespace();
+    match parser.chars.next() {
+        Some(c) => Err(format!("unexpected character '{}' after the value", c)),
+        None => Ok(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_round_trip() {
+        let document = r#"{
+            "name": "quote \" backslash \\ slash \/ tab \t newline \n control \u0001",
+            "unicode": "caf\u00e9 \ud83d\ude00 é",
+            "values": [null, true, false, -1, 1.5e3, [], {}, [[{"deep": ["x"]}]]],
+            "nested": {"empty": "", "array": [{"a": 1}, {"b": [2, 3]}]}
+        }"#;
+        let value = parse(document).unwrap();
+
+        let Value::Object(fields) = &value else {
+            panic!("expected an object, got {:?}", value);
+        };
+        let keys: Vec<_> = fields.iter().map(|(key, _)| key.as_ref()).collect();
+        assert_eq!(keys, ["name", "unicode", "values", "nested"]);
+        assert_eq!(
+            fields[0].1,
+            Value::String(
+                "quote \" backslash \\ slash / tab \t newline \n control \u{1}".to_owned()
+            )
+        );
+        assert_eq!(fields[1].1, Value::String("café \u{1f600} é".to_owned()));
+
+        let pretty = format!("{:#}", value);
+        assert!(
+            pretty.contains("\n  \"values\": [\n    null,"),
+            "{}",
+            pretty
+        );
+        assert_eq!(parse(&pretty).unwrap(), value);
+
+        let compact = value.to_string();
+        assert!(!compact.contains('\n'));
+        assert_eq!(parse(&compact).unwrap(), value);
+        assert_eq!(format!("{:#}", parse(&compact).unwrap()), pretty);
+    }
+
+    #[test]
+    fn unpaired_surrogates() {
+        assert_eq!(
+            parse(r#""\ud83d\ude00""#).unwrap(),
+            Value::String("\u{1f600}".to_owned())
+        );
+
+        for document in [
+            r#""\ud83d""#,
+            r#""\ud83dx""#,
+            r#""\ud83d\n""#,
+            r#""\ud83d\u0041""#,
+            r#""\ud83d\ud83d""#,
+            r#""\ude00""#,
+        ] {
+            assert!(parse(document).is_err(), "{}", document);
+        }
+    }
+}
@@ -0,0 +1,35 @@
+//! A library to parse and build 'ftab' (aka 'rkosftab') files found in firmware images of
+//! accessories produced by Apple.
+//!
+//! Files are parsed in memory using the [`Parser`](parser/struct.Parser.html) and built using the
+//! [`Builder`](builder/struct.Builder.html).
+//!
+//! # Asynchronous I/O
+//!
+//! The crate has no async variants of its I/O functions and doesn't depend on an async runtime.
+//! `Builder::write_to_async` and an async reader built on `tokio` were requested, but are deferred
+//! until the crate can take `tokio` as an optional dependency behind an `async` feature, which
+//! would leave the synchronous API as the default.
+//!
+//! Until then async code can keep the I/O on the runtime and only hand buffers to the crate, since
+//! parsing already works on an in-memory slice:
+//!
+//! * to parse a file, read it into a `Vec<u8>`, e.g. with `tokio::fs::read`, and pass the slice
+//!   to `Parser::parse`;
+//! * to build a file, get the bytes from `Builder::to_vec` and write them with the runtime's
+//!   writer, or call `Builder::write_to_seek` inside a blocking task when the segments are
+//!   streamed from their files by a builder made with `Builder::with_manifest_streamed`.
+
+#[macro_use]
+extern crate log;
+
+pub mod builder;
+pub mod bundle;
+pub mod checksum;
+pub mod error;
+pub mod format;
+pub mod gzip;
+pub mod manifest;
+pub mod parser;
+pub mod util;
+pub mod verify;
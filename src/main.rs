@@ -1,67 +1,433 @@
 #[macro_use]
 extern crate log;
 
-mod builder;
-mod error;
-mod format;
-mod manifest;
-mod parser;
-mod util;
+mod emit;
+mod expand;
+mod json;
+mod term;
 
 use crate::{
-    builder::Builder,
-    error::{FileOpError, PackError, UnpackError},
-    manifest::{Manifest, SegmentDesc, Tag},
-    parser::Parser,
+    expand::ExpandedPathParser,
+    json::Value,
+    term::{paint, Color},
+};
+use clap::{arg, command, value_parser, Arg, ArgAction, ArgMatches, Command};
+use ftabutil::{
+    builder::{Builder, TicketPlacement},
+    bundle::{Bundle, MANIFEST_NAME},
+    checksum::{sha256, Sha256},
+    error::{
+        BatchError, ConvertError, ExtractError, FileOpError, InfoError, InspectError, PackError,
+        RepackError, RewriteError, SelftestError, UnpackError, ValidateError, VerifyError,
+    },
+    format::{alignment_padding, FtabHeader, HEADER_LEN, SEGMENT_ALIGNMENT, SEGMENT_HEADER_LEN},
+    manifest::{
+        filename_for_tag, filename_for_tag_with_extension, Manifest, SegmentDesc, Tag, TagPattern,
+        DEFAULT_SEGMENT_EXTENSION, MANIFEST_FIELDS, OUTPUT_FIELDS, SEGMENT_FIELDS, TICKET_FILENAME,
+    },
+    parser::{
+        count_segments, peek_header, ParseError, ParsedSegment, Parser, SegmentsParser,
+        DEFAULT_MAX_SEGMENTS,
+    },
+    util::{self, InputFormat, SymlinkPolicy, SUCCESS_LOG_TARGET},
+    verify::{check_alignment, check_metadata_checksum, metadata_crc32, verify, Diagnostic},
 };
-use clap::{arg, command, value_parser, Command};
 use log::LevelFilter;
+use serde::Serialize;
 use simple_logger::SimpleLogger;
 use std::{
-    fs,
-    io::ErrorKind as IoErrorKind,
+    collections::HashMap,
+    env,
+    ffi::OsString,
+    fmt,
+    fs::{self, File},
+    io::{self, BufWriter, ErrorKind as IoErrorKind, IsTerminal, Read, Write},
     path::{Path, PathBuf},
+    process,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 fn do_print_header(parser: &Parser) {
-    println!("unk_0: {:#08x}", parser.unk_0());
-    println!("unk_1: {:#08x}", parser.unk_1());
-    println!("unk_2: {:#08x}", parser.unk_2());
-    println!("unk_3: {:#08x}", parser.unk_3());
-    println!("unk_4: {:#08x}", parser.unk_4());
-    println!("unk_5: {:#08x}", parser.unk_5());
-    println!("unk_6: {:#08x}", parser.unk_6());
-}
-
-fn filename_for_tag(tag: [u8; 4]) -> PathBuf {
-    let filename = if tag.iter().all(u8::is_ascii_alphanumeric) {
-        let tag_str = std::str::from_utf8(&tag).unwrap();
-        format!("{}.bin", tag_str)
-    } else {
-        format!("tag_{}.bin", hex::encode(tag))
-    };
+    println!("unk_0: {:#08x}", paint(Color::Offset, parser.unk_0()));
+    println!("unk_1: {:#08x}", paint(Color::Offset, parser.unk_1()));
+    println!("unk_2: {:#08x}", paint(Color::Offset, parser.unk_2()));
+    println!("unk_3: {:#08x}", paint(Color::Offset, parser.unk_3()));
+    println!("unk_4: {:#08x}", paint(Color::Offset, parser.unk_4()));
+    println!("unk_5: {:#08x}", paint(Color::Offset, parser.unk_5()));
+    println!("unk_6: {:#08x}", paint(Color::Offset, parser.unk_6()));
+}
+
+/// The destination of files produced by the `unpack` operation.
+enum UnpackDest<'a> {
+    /// Files are written into a directory or into the current directory in case of `None`.
+    Dir(Option<&'a Path>),
+    /// Files are collected into a bundle to be written at the specified path.
+    Bundle(Bundle, &'a Path),
+}
+
+impl UnpackDest<'_> {
+    /// Saves a file to the destination. `filename` is the path that will be written to the manifest.
+    fn save(
+        &mut self,
+        name: &'static str,
+        filename: &Path,
+        data: &[u8],
+        overwrite: bool,
+        silent: bool,
+    ) -> Result<(), Box<FileOpError>> {
+        match self {
+            UnpackDest::Dir(dir) => {
+                let path = util::qualify_path_if_needed(filename, *dir);
+                // files may be nested into subdirectories of the output directory
+                if let Some(parent) = path.parent().filter(|_| filename.parent().is_some()) {
+                    fs::create_dir_all(parent).map_err(|error| {
+                        FileOpError::make_create("directory", parent.to_path_buf(), error)
+                    })?;
+                }
+                util::save_file(name, path, data, overwrite, silent)
+            }
+            UnpackDest::Bundle(bundle, _) => {
+                debug!("Adding {} to the bundle as {}.", name, filename.display());
+
+                bundle.add(filename.to_string_lossy().into_owned(), data.to_vec());
+                Ok(())
+            }
+        }
+    }
+
+    /// Checks if a regular file of the specified length already exists at the destination.
+    fn has_file(&self, filename: &Path, len: usize) -> bool {
+        match self {
+            UnpackDest::Dir(dir) => {
+                let path = util::qualify_path_if_needed(filename, *dir);
+                fs::metadata(path)
+                    .map(|metadata| metadata.is_file() && metadata.len() == len as u64)
+                    .unwrap_or(false)
+            }
+            UnpackDest::Bundle(..) => false,
+        }
+    }
 
-    let mut path = PathBuf::new();
-    path.push(filename);
+    /// Writes the bundle file in case the destination is a bundle.
+    fn finish(self, overwrite: bool, silent: bool) -> Result<(), Box<FileOpError>> {
+        if let UnpackDest::Bundle(bundle, path) = self {
+            let mut bundle_data = Vec::new();
+            bundle
+                .write_to(&mut bundle_data)
+                .expect("writing to a vector should never fail");
+            util::save_file("bundle", path, &bundle_data, overwrite, silent)?;
+        }
 
-    path
+        Ok(())
+    }
 }
 
-fn do_unpack<'a>(
-    in_file: &'a Path,
-    out_dir: Option<&'a Path>,
+/// Metadata of a segment written next to its contents by `unpack --sidecar`.
+#[derive(Serialize, Debug)]
+struct SegmentMeta {
+    /// The tag with non-printable bytes escaped.
+    tag: String,
+    /// The hex-encoded bytes of the tag.
+    tag_hex: String,
+    unk: u32,
+    offset: usize,
+    length: usize,
+}
+
+impl SegmentMeta {
+    fn with_parsed_segment(segment: &ParsedSegment) -> Self {
+        Self {
+            tag: segment.tag.escape_ascii().to_string(),
+            tag_hex: Tag(segment.tag).to_hex(),
+            unk: segment.unk,
+            offset: segment.offset,
+            length: segment.data.len(),
+        }
+    }
+}
+
+/// Options controlling how input 'ftab' files are read and parsed.
+#[derive(Copy, Clone, Debug)]
+struct InputOptions {
+    /// The format of input files.
+    format: InputFormat,
+    /// The maximum segments count of input files, not limited by default.
+    max_segments: usize,
+    /// Skip segment list entries with an all-zero tag and a zero length.
+    skip_empty: bool,
+}
+
+impl Default for InputOptions {
+    fn default() -> Self {
+        Self {
+            format: InputFormat::default(),
+            max_segments: usize::MAX,
+            skip_empty: false,
+        }
+    }
+}
+
+impl InputOptions {
+    /// Fails for `http://` and `https://` URLs passed as input paths. Inputs are only read from
+    /// local files since the tool has no HTTP client, and without the check a URL would be reported
+    /// as a missing file.
+    fn check_local(path: &Path) -> Result<(), Box<FileOpError>> {
+        let path_str = path.to_string_lossy();
+        if path_str.starts_with("http://") || path_str.starts_with("https://") {
+            return Err(FileOpError::make_open(
+                "input file",
+                path.to_path_buf(),
+                io::Error::new(
+                    IoErrorKind::Unsupported,
+                    "URLs are not supported as inputs, download the file first",
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Reads an input file.
+    fn read(&self, path: &Path) -> Result<Vec<u8>, Box<FileOpError>> {
+        Self::check_local(path)?;
+        util::read_input_file("input file", path, self.format)
+    }
+
+    /// Reads at most `len` bytes from the start of an input file.
+    fn read_prefix(&self, path: &Path, len: usize) -> Result<Vec<u8>, Box<FileOpError>> {
+        Self::check_local(path)?;
+        util::read_input_file_prefix("input file", path, len, self.format)
+    }
+
+    /// Reads at most `len` bytes starting at `offset` of an input file along with its length.
+    fn read_range(
+        &self,
+        path: &Path,
+        offset: usize,
+        len: usize,
+    ) -> Result<(Vec<u8>, usize), Box<FileOpError>> {
+        Self::check_local(path)?;
+        util::read_input_file_range("input file", path, offset, len, self.format)
+    }
+
+    /// Parses the contents of an input file.
+    fn parse<'d>(&self, data: &'d [u8]) -> Result<Parser<'d>, ParseError> {
+        Parser::parse_with_max_segments(data, self.max_segments)
+    }
+
+    /// Parses a 'ftab' file embedded into an input file at the specified offset.
+    fn parse_at<'d>(&self, data: &'d [u8], offset: usize) -> Result<Parser<'d>, ParseError> {
+        Parser::parse_at_with_max_segments(data, offset, self.max_segments)
+    }
+
+    /// Returns a parser over the segment list of a parsed input file.
+    fn segments<'d>(&self, parser: &Parser<'d>) -> SegmentsParser<'d> {
+        let mut segments_parser = parser.segments();
+        segments_parser.set_skip_empty(self.skip_empty);
+        segments_parser
+    }
+}
+
+/// Options controlling the behavior of the `unpack` operation.
+#[derive(Clone, Default, Debug)]
+struct UnpackOptions {
+    /// Overwrite existing files without asking.
     overwrite: bool,
+    /// Create parent directories of the output directory.
     create_parent_dirs: bool,
+    /// Print the header fields.
     print_header: bool,
+    /// Take the default action instead of displaying prompts.
     silent: bool,
-) -> Result<(), UnpackError<'a>> {
+    /// Skip writing segments and the ticket when files of the same size already exist.
+    skip_existing: bool,
+    /// The options of reading the input file.
+    input: InputOptions,
+    /// The filter selecting which segments are unpacked.
+    size_filter: SizeFilter,
+    /// Patterns selecting which segments are unpacked by tag, all segments are unpacked when empty.
+    tag_filter: Vec<TagPattern>,
+    /// Write a metadata file next to each segment.
+    sidecar: bool,
+    /// The offset of the 'ftab' file in the input file.
+    offset: usize,
+    /// Fail instead of warning when the output directory exists and is not empty.
+    require_empty: bool,
+    /// Print a Rust snippet building the unpacked file.
+    emit_rust: bool,
+    /// Embed the contents of the unpacked files into the Rust snippet.
+    inline: bool,
+    /// The path the ticket is written to instead of the output directory, `-` for stdout.
+    ticket_out: Option<PathBuf>,
+    /// Only write a manifest with the header fields reading just the header.
+    header_manifest: bool,
+    /// Draw a progress indicator on stderr.
+    show_progress: bool,
+    /// The extension of the segment files used instead of the default one, empty for none.
+    extension: Option<String>,
+    /// Write each segment into its own subdirectory named after the tag.
+    segment_per_dir: bool,
+    /// Record the length of each segment in the manifest as `expected_len`.
+    record_lengths: bool,
+}
+
+/// Returns the path of the file a segment with the specified tag is unpacked to relative to the
+/// output directory. This is either `<tag>.<extension>` or `<tag>/data.<extension>` when each
+/// segment is written into its own subdirectory.
+fn segment_path(tag: [u8; 4], extension: &str, segment_per_dir: bool) -> PathBuf {
+    if segment_per_dir {
+        let mut path = filename_for_tag_with_extension(tag, "");
+        path.push(Path::new("data").with_extension(extension));
+        path
+    } else {
+        filename_for_tag_with_extension(tag, extension)
+    }
+}
+
+/// A progress indicator drawn on stderr while processing segments. Progress is measured in bytes
+/// so that files dominated by a single large segment don't jump from 0% to 100%, and the segment
+/// count is shown next to it. Nothing is drawn when stderr is not a terminal.
+struct Progress {
+    enabled: bool,
+    total_bytes: usize,
+    total_count: usize,
+    done_bytes: usize,
+    done_count: usize,
+    /// The length of the segment being processed.
+    pending: Option<usize>,
+}
+
+impl Progress {
+    fn new(enabled: bool, total_bytes: usize, total_count: usize) -> Self {
+        Self {
+            enabled: enabled && io::stderr().is_terminal(),
+            total_bytes,
+            total_count,
+            done_bytes: 0,
+            done_count: 0,
+            pending: None,
+        }
+    }
+
+    /// Marks the previously started segment as done and starts a segment of the specified length.
+    fn begin(&mut self, len: usize) {
+        self.complete_pending();
+        self.pending = Some(len);
+        self.draw();
+    }
+
+    /// Marks the last started segment as done and ends the progress line.
+    fn finish(&mut self) {
+        self.complete_pending();
+        self.draw();
+        if self.enabled {
+            eprintln!();
+        }
+    }
+
+    fn complete_pending(&mut self) {
+        if let Some(len) = self.pending.take() {
+            self.done_bytes += len;
+            self.done_count += 1;
+        }
+    }
+
+    fn draw(&self) {
+        if !self.enabled {
+            return;
+        }
+
+        let percent = match self.total_bytes {
+            0 => 100,
+            total => self.done_bytes * 100 / total,
+        };
+        eprint!(
+            "\r[{:>3}%] {} / {}, {}/{} segment(s)\x1b[K",
+            percent,
+            format_size(self.done_bytes),
+            format_size(self.total_bytes),
+            self.done_count,
+            self.total_count
+        );
+    }
+}
+
+/// The outcome of a successful `unpack` operation.
+#[derive(Clone, Default, Debug)]
+struct UnpackSummary {
+    /// The number of segments written to the destination.
+    segments_written: usize,
+    /// The number of segments recorded in the manifest as aliases of earlier segments.
+    aliases: usize,
+    /// The number of segments skipped because of filters or existing files.
+    segments_skipped: usize,
+    /// The number of bytes of segments and the ticket written to the destination.
+    bytes_written: usize,
+    /// Problems that didn't prevent unpacking the file.
+    warnings: Vec<String>,
+}
+
+impl UnpackSummary {
+    /// Logs the warnings and the summary.
+    fn log(&self) {
+        for warning in self.warnings.iter() {
+            warn!("{}", warning);
+        }
+
+        info!(
+            target: SUCCESS_LOG_TARGET,
+            "Done, wrote {} segment(s) and {} byte(s), {} alias(es), {} skipped.",
+            self.segments_written,
+            self.bytes_written,
+            self.aliases,
+            self.segments_skipped
+        );
+    }
+}
+
+fn do_unpack<'a>(
+    in_file: &'a Path,
+    out_dir: Option<&'a Path>,
+    bundle_path: Option<&'a Path>,
+    options: UnpackOptions,
+) -> Result<UnpackSummary, UnpackError<'a>> {
     use UnpackError::*;
 
-    let data = util::read_file("input file", in_file)?;
+    let UnpackOptions {
+        overwrite,
+        create_parent_dirs,
+        print_header,
+        silent,
+        skip_existing,
+        input,
+        size_filter,
+        tag_filter,
+        sidecar,
+        offset,
+        require_empty,
+        emit_rust,
+        inline,
+        ticket_out,
+        header_manifest,
+        show_progress,
+        extension,
+        segment_per_dir,
+        record_lengths,
+    } = options;
+    let extension = extension.as_deref().unwrap_or(DEFAULT_SEGMENT_EXTENSION);
+
+    // Only the header is needed for a header manifest so the rest of the file is not read.
+    let data = if header_manifest {
+        input.read_prefix(in_file, offset.saturating_add(HEADER_LEN))?
+    } else {
+        input.read(in_file)?
+    };
+
+    info!(target: SUCCESS_LOG_TARGET, "Loaded file at path {}.", in_file.display());
 
-    info!("Loaded file at path {}.", in_file.display());
+    let mut summary = UnpackSummary::default();
 
-    if let Some(out_dir) = out_dir {
+    if let (Some(out_dir), None) = (out_dir, bundle_path) {
         if create_parent_dirs {
             fs::create_dir_all(out_dir)
         } else {
@@ -75,208 +441,3399 @@ fn do_unpack<'a>(
                     Ok(())
                 }
             }
+            IoErrorKind::PermissionDenied => Err(OutDirPermissionDenied(out_dir, e)),
+            IoErrorKind::NotADirectory => Err(OutDirParentIsNotDir(out_dir, e)),
+            IoErrorKind::NotFound if !create_parent_dirs => Err(OutDirParentMissing(out_dir, e)),
             _ => Err(FailedToCreateOutDir(out_dir, e)),
         })?;
+
+        // Files left from another unpack would get mixed with the unpacked ones. This is expected
+        // when resuming an unpack though.
+        let entries = fs::read_dir(out_dir)
+            .map_err(|e| FailedToListOutDir(out_dir, e))?
+            .count();
+        if entries != 0 && require_empty {
+            return Err(OutDirNotEmpty(out_dir, entries));
+        } else if entries != 0 && !skip_existing {
+            // Warn right away, so the warning is seen before any file is written and even when
+            // the unpack fails.
+            warn!(
+                "Target directory at {} is not empty, it already contains {} entries.",
+                out_dir.display(),
+                entries
+            );
+        }
+    }
+
+    if header_manifest {
+        let header = data
+            .get(offset..)
+            .ok_or(ParseError::TooShort)
+            .and_then(peek_header)
+            .map_err(|e| HeaderParseError(in_file, e))?;
+        let the_manifest = Manifest::with_header(&header);
+
+        let mut dest = match bundle_path {
+            Some(bundle_path) => UnpackDest::Bundle(Bundle::new(), bundle_path),
+            None => UnpackDest::Dir(out_dir),
+        };
+
+        // The manifest references the ticket, so it is written too. Only the ticket is read in
+        // addition to the header and the segments are never parsed.
+        if let Some(filename) = the_manifest.ticket.as_deref() {
+            let ticket_offset = header.ticket_offset as usize;
+            let ticket_len = header.ticket_len as usize;
+            if ticket_offset < HEADER_LEN {
+                return Err(HeaderParseError(
+                    in_file,
+                    ParseError::TicketOverlapsHeader {
+                        offset: ticket_offset,
+                        len: ticket_len,
+                    },
+                ));
+            }
+
+            let (ticket, file_len) = match offset.checked_add(ticket_offset) {
+                Some(ticket_start) => input.read_range(in_file, ticket_start, ticket_len)?,
+                None => (Vec::new(), 0),
+            };
+            if ticket.len() != ticket_len {
+                return Err(HeaderParseError(
+                    in_file,
+                    ParseError::TicketPastEof {
+                        offset: ticket_offset,
+                        len: ticket_len,
+                        file_len: file_len.saturating_sub(offset),
+                    },
+                ));
+            }
+
+            if skip_existing && dest.has_file(filename, ticket.len()) {
+                info!(
+                    "Skipping the ticket as {} already exists.",
+                    filename.display()
+                );
+            } else {
+                dest.save("ticket", filename, &ticket, overwrite, silent)?;
+                summary.bytes_written += ticket.len();
+            }
+        }
+
+        let serialized_manifest = the_manifest.to_toml();
+        dest.save(
+            "manifest",
+            Path::new(MANIFEST_NAME),
+            &serialized_manifest,
+            overwrite,
+            silent,
+        )?;
+        dest.finish(overwrite, silent)?;
+
+        return Ok(summary);
     }
 
     // Parse the header and initialize the parser.
-    let parser = Parser::parse(&data).map_err(|e| HeaderParseError(in_file, e))?;
+    let parser = input
+        .parse_at(&data, offset)
+        .map_err(|e| HeaderParseError(in_file, e))?;
 
     let mut the_manifest = Manifest::with_parser(&parser);
-    let manifest_path = util::qualify_path_if_needed("manifest.toml", out_dir);
+    let mut dest = match bundle_path {
+        Some(bundle_path) => UnpackDest::Bundle(Bundle::new(), bundle_path),
+        None => UnpackDest::Dir(out_dir),
+    };
 
     if print_header {
         do_print_header(&parser);
     }
 
-    if let Some(ticket) = parser.ticket() {
-        let mut filename = PathBuf::new();
-        filename.push("ApImg4Ticket.der");
+    // The contents of the unpacked files by their paths embedded into the Rust snippet.
+    let mut inline_files = Vec::new();
+    match (parser.ticket(), ticket_out.as_deref()) {
+        (Some(ticket), Some(path)) if path == Path::new("-") => {
+            io::stdout()
+                .write_all(ticket)
+                .map_err(|error| FileOpError::make_write("ticket", path.to_path_buf(), error))?;
+            summary.bytes_written += ticket.len();
+        }
+        (Some(ticket), ticket_out) => {
+            // A separate ticket path is relative to the current directory rather than the output
+            // directory, so the manifest refers to it by the absolute path.
+            let mut separate_dest = UnpackDest::Dir(None);
+            let (ticket_dest, filename) = match ticket_out {
+                Some(path) => (
+                    &mut separate_dest,
+                    env::current_dir()
+                        .map(|dir| dir.join(path))
+                        .unwrap_or_else(|_| path.to_path_buf()),
+                ),
+                None => (&mut dest, PathBuf::from(TICKET_FILENAME)),
+            };
 
-        let ticket_path = util::qualify_path_if_needed(&filename, out_dir);
-        util::save_file("ticket", ticket_path, ticket, overwrite, silent)?;
+            if skip_existing && ticket_dest.has_file(&filename, ticket.len()) {
+                info!(
+                    "Skipping the ticket as {} already exists.",
+                    filename.display()
+                );
+            } else {
+                ticket_dest.save("ticket", &filename, ticket, overwrite, silent)?;
+                summary.bytes_written += ticket.len();
+            }
 
-        the_manifest.ticket = Some(filename);
+            the_manifest.ticket = Some(filename.clone());
+            inline_files.push((filename, ticket));
+        }
+        (None, _) => {}
     }
 
-    let mut segments_parser = parser.segments();
+    let mut segments_parser = input.segments(&parser);
+    if segments_parser.count() == 0 {
+        summary.warnings.push(format!(
+            "File at {} contains no segments, the manifest will list no segments.",
+            in_file.display()
+        ));
+    }
     the_manifest.segments.reserve(segments_parser.count());
+    let mut prev_end = HEADER_LEN + segments_parser.count() * SEGMENT_HEADER_LEN;
+    let mut ranges = HashMap::new();
+    let mut progress = {
+        let total_count = segments_parser.count();
+        let mut total_bytes = 0;
+        let mut segments_parser = segments_parser.clone();
+        while segments_parser.count() != 0 {
+            if let Ok(Some(segment)) = segments_parser.next_segment() {
+                total_bytes += segment.data.len();
+            }
+        }
+        Progress::new(show_progress, total_bytes, total_count)
+    };
     loop {
         match segments_parser.next_segment()? {
             None => {
-                let serialized_manifest = toml::to_vec(&the_manifest).unwrap();
-                util::save_file(
+                progress.finish();
+
+                let serialized_manifest = the_manifest.to_toml();
+                dest.save(
                     "manifest",
-                    manifest_path,
+                    Path::new(MANIFEST_NAME),
                     &serialized_manifest,
                     overwrite,
                     silent,
                 )?;
+                dest.finish(overwrite, silent)?;
 
-                info!("Done.");
+                if emit_rust {
+                    let inline_files = inline.then_some(inline_files.as_slice());
+                    print!("{}", emit::manifest_to_rust(&the_manifest, inline_files));
+                }
 
-                break Ok(());
+                break Ok(summary);
             }
             Some(segment) => {
-                let filename = filename_for_tag(segment.tag);
-                let path = util::qualify_path_if_needed(&filename, out_dir);
+                progress.begin(segment.data.len());
+
+                if !size_filter.matches(segment.data.len()) {
+                    debug!(
+                        "Skipping segment with tag {} filtered out by size.",
+                        segment.tag.escape_ascii()
+                    );
+                    summary.segments_skipped += 1;
+                    prev_end = segment.offset + segment.data.len();
+                    continue;
+                }
+                if !tag_filter.is_empty()
+                    && !tag_filter
+                        .iter()
+                        .any(|pattern| pattern.matches(&segment.tag))
+                {
+                    debug!(
+                        "Skipping segment with tag {} not matching any tag pattern.",
+                        segment.tag.escape_ascii()
+                    );
+                    summary.segments_skipped += 1;
+                    prev_end = segment.offset + segment.data.len();
+                    continue;
+                }
+
+                // Segments pointing at the same range as an earlier one reference the same file.
+                let range = (segment.offset, segment.data.len());
+                if let Some(&index) = ranges.get(&range) {
+                    let desc = SegmentDesc::alias(&the_manifest.segments[index], &segment);
+                    debug!(
+                        "Segment with tag {} is an alias of segment with tag {}.",
+                        segment.tag.escape_ascii(),
+                        desc.alias_of.unwrap()
+                    );
+                    the_manifest.segments.push(desc);
+                    summary.aliases += 1;
+                    continue;
+                }
+                ranges.insert(range, the_manifest.segments.len());
+
+                let mut desc = SegmentDesc::with_parsed_segment(&parser, prev_end, &segment);
+                desc.path = segment_path(segment.tag, extension, segment_per_dir);
+                if record_lengths {
+                    desc.expected_len = Some(segment.data.len().try_into().unwrap());
+                }
+                let filename = &desc.path;
+                if skip_existing && dest.has_file(filename, segment.data.len()) {
+                    info!(
+                        "Skipping segment with tag {} as {} already exists.",
+                        segment.tag.escape_ascii(),
+                        filename.display()
+                    );
+                    summary.segments_skipped += 1;
+                } else {
+                    dest.save("segment", filename, segment.data, overwrite, silent)?;
+                    summary.segments_written += 1;
+                    summary.bytes_written += segment.data.len();
+                }
 
-                util::save_file("segment", path, segment.data, overwrite, silent)?;
+                if sidecar {
+                    let meta_filename = if segment_per_dir {
+                        filename.with_file_name("meta.toml").into_os_string()
+                    } else {
+                        let mut meta_filename = filename.clone().into_os_string();
+                        meta_filename.push(".meta");
+                        meta_filename
+                    };
+                    let meta = toml::to_vec(&SegmentMeta::with_parsed_segment(&segment)).unwrap();
+                    dest.save(
+                        "segment metadata",
+                        Path::new(&meta_filename),
+                        &meta,
+                        overwrite,
+                        silent,
+                    )?;
+                }
 
-                the_manifest.segments.push(SegmentDesc {
-                    path: filename,
-                    tag: Tag(segment.tag),
-                    unk: segment.unk,
-                });
+                prev_end = segment.offset + segment.data.len();
+                inline_files.push((desc.path.clone(), segment.data));
+                the_manifest.segments.push(desc);
             }
         }
     }
 }
 
-fn do_pack<'a>(
-    manifest_path: &'a Path,
-    out_path: Option<&'a Path>,
+/// Reads a written 'ftab' file back and checks that its header matches the expected one and that
+/// all segments parse.
+fn verify_output<'a>(path: &Path, expected_header: &FtabHeader) -> Result<(), PackError<'a>> {
+    use PackError::*;
+
+    let data = util::read_file("output file", path)?;
+    let header = peek_header(&data).map_err(|e| OutputParseError(path.to_path_buf(), e))?;
+    if header != *expected_header {
+        return Err(OutputHeaderMismatch(
+            path.to_path_buf(),
+            header.diff(expected_header),
+        ));
+    }
+    let parser = Parser::parse(&data).map_err(|e| OutputParseError(path.to_path_buf(), e))?;
+
+    let mut segments_parser = parser.segments();
+    while segments_parser
+        .next_segment()
+        .map_err(|e| OutputOobSegmentError(path.to_path_buf(), e))?
+        .is_some()
+    {}
+
+    debug!("Verified the written file at {}.", path.display());
+
+    Ok(())
+}
+
+/// Options controlling the behavior of the `pack` operation.
+#[derive(Clone, Default, Debug)]
+struct PackOptions {
+    /// Overwrite the output file without asking.
     overwrite: bool,
+    /// Take the default action instead of displaying prompts.
     silent: bool,
-) -> Result<(), PackError<'a>> {
-    use PackError::*;
+    /// Read the output file back and check that it parses.
+    verify_after_write: bool,
+    /// Remove the output file when the verification fails.
+    remove_invalid: bool,
+    /// Pad the ticket to start on a 4-byte boundary.
+    pad_ticket: bool,
+    /// Place the ticket before the contents of the segments.
+    ticket_before_data: bool,
+    /// How symbolic links in paths from the manifest are handled.
+    symlink_policy: SymlinkPolicy,
+    /// Reject manifests containing unknown keys.
+    strict_manifest: bool,
+    /// The template of the output file name used when no output path is specified.
+    output_template: Option<String>,
+    /// Write the output file atomically.
+    atomic: bool,
+    /// Build the file without the ticket referenced by the manifest.
+    exclude_ticket: bool,
+    /// Write a `sha256sum` compatible checksum file next to the output file.
+    checksum_manifest: bool,
+    /// Values overriding the `unk_<N>` header fields from the manifest keyed by `N`.
+    unk_overrides: Vec<(usize, u32)>,
+    /// Read the manifest path as a list of `TAG=PATH` lines instead of a manifest.
+    segments_from: bool,
+    /// Replace the padding bytes from the manifest with zeroes and fail on segment files of
+    /// unexpected lengths.
+    deterministic: bool,
+    /// Reorder the segments by [`ftabutil::manifest::CANONICAL_TAG_ORDER`] instead of keeping the manifest's order.
+    canonical_order: bool,
+}
 
-    // read and parse the manifest ensuring that the parent directory in the manifest's path exists
-    let manifest_data = util::read_file("manifest", manifest_path)?;
-    let the_manifest = toml::from_slice::<Manifest>(&manifest_data)
-        .map_err(|e| ManifestParseError(manifest_path, e))?;
+/// Parses a list of segments with one `TAG=PATH` line per segment into a manifest with zero header
+/// fields and no ticket. Empty lines and lines starting with `#` are skipped. Returns the line number
+/// and a description of the problem for a malformed line.
+fn parse_segment_list(data: &[u8]) -> Result<Manifest, (usize, String)> {
+    let mut manifest = Manifest::default();
 
-    // create the output file
-    let input_dir = manifest_path.parent();
-    let out_file_path = util::qualify_path_or_default_if_needed(out_path, input_dir, "ftab.bin");
-    let mut out_file = util::create_file("output file", &out_file_path, overwrite, silent)?;
+    let data = String::from_utf8_lossy(data);
+    for (index, line) in data.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
 
-    debug!("Writing ftab to {}.", out_file_path.display());
+        let (tag, path) = line
+            .split_once('=')
+            .ok_or_else(|| (index + 1, "expected TAG=PATH".to_owned()))?;
+        let tag = parse_tag(tag.trim()).map_err(|e| (index + 1, e))?;
+        let path = path.trim();
+        if path.is_empty() {
+            return Err((index + 1, format!("no path for segment with tag {}", tag)));
+        }
+
+        manifest.segments.push(SegmentDesc {
+            path: PathBuf::from(path),
+            tag,
+            unk: 0,
+            padding: None,
+            expected_len: None,
+            alias_of: None,
+        });
+    }
+
+    Ok(manifest)
+}
 
-    // make a builder from the manifest and build the ftab file
-    let builder = Builder::with_manifest(&the_manifest, input_dir)?;
-    builder.write_to(&mut out_file).map_err(|error| {
-        FileOpError::make_write("output file", out_file_path.to_path_buf(), error)
-    })?;
+/// The default name of the packed file used when neither an output path nor a template is
+/// specified.
+const DEFAULT_OUTPUT_NAME: &str = "ftab.bin";
 
-    info!("Done.");
+/// Expands an output file name template replacing `{label}` with the manifest's label or `ftab`
+/// when it has none, `{segments}` with the segments count and `{stem}` with the manifest file name
+/// without the extension. Other text is kept as is.
+fn expand_output_template(template: &str, manifest: &Manifest, manifest_path: &Path) -> String {
+    let stem = manifest_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
 
-    Ok(())
+    template
+        .replace("{label}", manifest.label.as_deref().unwrap_or("ftab"))
+        .replace("{segments}", &manifest.segments.len().to_string())
+        .replace("{stem}", &stem)
 }
 
-fn main() {
-    let matches = command!()
-        .propagate_version(true)
-        .subcommand_required(true)
-        .arg_required_else_help(true)
-        .arg(arg!(print_header: -H --print_header).help(
-            "Prints fields of the ftab file header that are neither offsets nor magic \
-                    (currently all are unknown and ignored).",
+/// Compares a built 'ftab' file with the reference file reporting the first difference.
+fn compare_output<'a>(built: &[u8], reference_path: &'a Path) -> Result<(), PackError<'a>> {
+    use PackError::*;
+
+    let reference = util::read_file("reference file", reference_path)?;
+
+    if let Some(offset) = built.iter().zip(reference.iter()).position(|(a, b)| a != b) {
+        Err(OutputMismatch(reference_path, offset))
+    } else if built.len() != reference.len() {
+        Err(OutputLengthMismatch(
+            reference_path,
+            built.len(),
+            reference.len(),
         ))
-        .arg(
-            arg!(log_level: -l --log_level <LEVEL>)
-                .default_value("WARN")
-                .help(
-                    "Configures the log level for the tool. Available log levels are: NONE \
-                    (disables logging entirely), TRACE, DEBUG, INFO, WARN and ERROR.",
-                ),
-        )
-        .arg(
-            arg!(silent: -s --silent).help(
-                "Makes all user prompts take their default action instead of being displayed.",
+    } else {
+        println!("Built file matches {}.", reference_path.display());
+
+        Ok(())
+    }
+}
+
+/// The outcome of a successful `pack` operation.
+#[derive(Clone, Debug)]
+struct PackSummary {
+    /// The path of the written file or `None` when the output was only compared to a file.
+    out_path: Option<PathBuf>,
+    /// The number of segments in the built file.
+    segments: usize,
+    /// The length of the built file.
+    bytes: u64,
+}
+
+impl PackSummary {
+    /// Logs the summary.
+    fn log(&self) {
+        match self.out_path.as_ref() {
+            Some(out_path) => info!(
+                target: SUCCESS_LOG_TARGET,
+                "Done, wrote {} segment(s) and {} byte(s) to {}.",
+                self.segments,
+                self.bytes,
+                out_path.display()
             ),
-        )
-        .subcommand(
-            Command::new("unpack")
-                .arg(arg!(overwrite: -o --overwrite).help(
-                    "Overwrite files instead of stopping when a file exists in the output \
-                        directory.",
-                ))
-                .arg(
-                    arg!(create_parent_dirs: -p --create_parent_dirs).help(
-                        "Create parent directories when the output directory does not exist.",
-                    ),
-                )
-                .arg(
-                    arg!(in_file: <PATH>)
-                        .value_parser(value_parser!(PathBuf))
-                        .help("Path to the ftab file to be unpacked."),
-                )
-                .arg(
-                    arg!(out_dir: [OUT_DIR])
-                        .value_parser(value_parser!(PathBuf))
-                        .help(
-                            "Path to the directory where the unpacked files will be written. The \
-                            default is the current working directory.",
-                        ),
-                )
-                .about("Unpacks a ftab file into a directory."),
-        )
-        .subcommand(
-            Command::new("pack")
-                .arg(arg!(overwrite: -o --overwrite).help(
-                    "Overwrites the output file instead of stopping when the file exists at the \
-                    specified path.",
-                ))
-                .arg(
-                    arg!(manifest: <MANIFEST_PATH>)
-                        .value_parser(value_parser!(PathBuf))
-                        .help("Path to the manifest describing the desired ftab file."),
-                )
-                .arg(
-                    arg!(out_file: [OUT_PATH])
-                        .value_parser(value_parser!(PathBuf))
-                        .help("Destination path where the created ftab file should be written."),
-                )
-                .about("Creates a ftab file from a manifest."),
-        )
-        .get_matches();
+            None => info!(
+                target: SUCCESS_LOG_TARGET,
+                "Done, the output of {} segment(s) and {} byte(s) matches.",
+                self.segments,
+                self.bytes
+            ),
+        }
+    }
+}
 
-    let log_level: String = matches.get_one::<String>("log_level").unwrap().to_string();
-    let log_level = match log_level.as_str() {
-        "NONE" | "none" => LevelFilter::Off,
-        "TRACE" | "trace" => LevelFilter::Trace,
-        "DEBUG" | "debug" => LevelFilter::Debug,
-        "INFO" | "info" => LevelFilter::Info,
-        "WARN" | "warn" => LevelFilter::Warn,
-        "ERROR" | "error" => LevelFilter::Error,
-        _ => LevelFilter::Warn,
+fn do_pack<'a>(
+    manifest_path: &'a Path,
+    out_path: Option<&'a Path>,
+    compare_to: Option<&'a Path>,
+    options: PackOptions,
+) -> Result<Vec<PackSummary>, PackError<'a>> {
+    use PackError::*;
+
+    let PackOptions {
+        overwrite,
+        silent,
+        verify_after_write,
+        remove_invalid,
+        pad_ticket,
+        ticket_before_data,
+        symlink_policy,
+        strict_manifest,
+        output_template,
+        atomic,
+        exclude_ticket,
+        checksum_manifest,
+        unk_overrides,
+        segments_from,
+        deterministic,
+        canonical_order,
+    } = options;
+
+    // read and parse the manifest ensuring that the parent directory in the manifest's path exists
+    let manifest_data = util::read_file("manifest", manifest_path)?;
+
+    // the manifest may also be stored in a bundle along with the files it references
+    let bundle = if !segments_from && Bundle::is_bundle(&manifest_data) {
+        debug!("Reading bundle at {}.", manifest_path.display());
+
+        let bundle =
+            Bundle::parse(&manifest_data).map_err(|e| BundleParseError(manifest_path, e))?;
+        Some(bundle)
+    } else {
+        None
+    };
+    let manifest_data = match bundle.as_ref() {
+        Some(bundle) => bundle
+            .get(MANIFEST_NAME)
+            .ok_or(NoManifestInBundle(manifest_path))?,
+        None => &manifest_data,
     };
-    let print_header = matches.get_flag("print_header");
-    let silent = matches.get_flag("silent");
 
-    SimpleLogger::new().with_level(log_level).init().unwrap();
+    let mut the_manifest = if segments_from {
+        parse_segment_list(manifest_data)
+            .map_err(|(line, e)| SegmentListParseError(manifest_path, line, e))?
+    } else if bundle.is_none()
+        && ManifestFormat::from_path(manifest_path) == Some(ManifestFormat::Json)
+    {
+        parse_json_manifest(manifest_data, strict_manifest)
+            .map_err(|e| JsonManifestParseError(manifest_path, e))?
+    } else if strict_manifest {
+        Manifest::from_toml_strict(manifest_data)
+            .map_err(|e| ManifestParseError(manifest_path, e))?
+    } else {
+        toml::from_slice::<Manifest>(manifest_data)
+            .map_err(|e| ManifestParseError(manifest_path, e))?
+    };
 
-    match matches.subcommand() {
-        Some(("unpack", sub_matches)) => {
-            let in_file: PathBuf = sub_matches.get_one::<PathBuf>("in_file").unwrap().clone();
-            let out_dir: Option<PathBuf> =
-                sub_matches.get_one::<PathBuf>("out_dir").map(Clone::clone);
-            let overwrite = sub_matches.get_flag("overwrite");
-            let create_parent_dirs = sub_matches.get_flag("create_parent_dirs");
+    // values from the command line win over the ones from the manifest
+    for &(index, value) in unk_overrides.iter() {
+        let unk = the_manifest.unk_mut(index).unwrap();
+        debug!("Overriding unk_{} {:#x} with {:#x}.", index, unk, value);
+        *unk = value;
+    }
 
-            if let Err(e) = do_unpack(
-                &in_file,
-                out_dir.as_deref(),
-                overwrite,
-                create_parent_dirs,
-                print_header,
-                silent,
-            ) {
-                error!("{}", e);
+    // custom padding bytes, e.g. the ones recorded from the original file by `unpack`, are replaced
+    // with zero padding to a 4-byte boundary
+    if deterministic {
+        for segment in the_manifest.segments.iter_mut() {
+            if segment.padding.take().is_some() {
+                debug!(
+                    "Ignoring the padding of segment with tag {} from the manifest.",
+                    segment.tag
+                );
             }
         }
-        Some(("pack", sub_matches)) => {
-            let manifest_path = sub_matches.get_one::<PathBuf>("manifest").unwrap();
-            let out_file = sub_matches
-                .get_one::<PathBuf>("out_file")
-                .map(PathBuf::as_path);
-            let overwrite = sub_matches.get_flag("overwrite");
+    }
 
-            if let Err(e) = do_pack(manifest_path, out_file, overwrite, silent) {
-                error!("{}", e);
-            }
+    if canonical_order {
+        debug!("Sorting the segments in the canonical order.");
+        the_manifest.sort_canonical();
+    }
+
+    // dropping the ticket from the manifest keeps the builder from loading it
+    if exclude_ticket && the_manifest.ticket.take().is_some() {
+        debug!("Excluding the ticket referenced by the manifest.");
+    }
+
+    // make a builder from a manifest, unless the whole file is built in memory anyway, the contents
+    // of the segments are only copied from their files while the output is written
+    let input_dir = manifest_path.parent();
+    let make_builder = |manifest: &Manifest, streamed: bool| -> Result<Builder, PackError<'a>> {
+        let mut builder = match bundle.as_ref() {
+            Some(bundle) => {
+                Builder::with_manifest_loader(manifest, |name, path| bundle.read_file(name, path))?
+            }
+            None if streamed => Builder::with_manifest_streamed(manifest, |name, path| {
+                util::check_manifest_file(name, path, input_dir, symlink_policy)?;
+                Ok(util::qualify_path_if_needed(path, input_dir).into_owned())
+            })?,
+            None => Builder::with_manifest_loader(manifest, |name, path| {
+                util::read_manifest_file(name, path, input_dir, symlink_policy)
+            })?,
+        };
+        // the lengths of the segments in a deterministic build have to match the manifest, so a
+        // mismatch fails the build instead of only being reported
+        if deterministic {
+            let lengths = manifest.segments.iter().zip(builder.segment_lengths());
+            for (segment, len) in lengths {
+                match segment.expected_len {
+                    Some(expected_len) if expected_len as usize != len => {
+                        return Err(SegmentLengthMismatch(
+                            manifest_path,
+                            segment.tag,
+                            len,
+                            expected_len,
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        builder.set_pad_ticket(pad_ticket);
+        if ticket_before_data {
+            builder.set_ticket_placement(TicketPlacement::BeforeData);
+        }
+
+        Ok(builder)
+    };
+    let write_options = WriteOptions {
+        overwrite,
+        silent,
+        verify_after_write,
+        remove_invalid,
+        atomic,
+        checksum_manifest,
+    };
+
+    // a multi-output manifest builds a file for each output from the shared segments, the output
+    // path is then the directory the files are written to
+    if !the_manifest.outputs.is_empty() {
+        if compare_to.is_some() {
+            return Err(CompareMultipleOutputs(manifest_path));
+        }
+        if output_template.is_some() {
+            warn!("Output template is ignored for a manifest with multiple outputs.");
+        }
+
+        // resolve all outputs first so that nothing is written when one of them is invalid
+        let outputs = the_manifest
+            .outputs
+            .iter()
+            .map(|output| {
+                the_manifest
+                    .output_manifest(output)
+                    .map(|mut manifest| {
+                        if canonical_order {
+                            manifest.sort_canonical();
+                        }
+                        (output, manifest)
+                    })
+                    .map_err(|tag| UndefinedOutputTag(manifest_path, output.name.clone(), tag))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let out_dir = out_path.or(input_dir);
+        let mut summaries = Vec::with_capacity(outputs.len());
+        for (output, manifest) in outputs.iter() {
+            debug!("Building output {}.", output.name.display());
+
+            let builder = make_builder(manifest, true)?;
+            let out_file_path = util::qualify_path_if_needed(&output.name, out_dir);
+            let written = write_output(&builder, &out_file_path, write_options)?;
+            summaries.push(PackSummary {
+                out_path: Some(out_file_path.into_owned()),
+                segments: manifest.segments.len(),
+                bytes: written,
+            });
+        }
+
+        return Ok(summaries);
+    }
+
+    // when comparing, the file is built in memory and nothing is written
+    if let Some(reference_path) = compare_to {
+        let built = make_builder(&the_manifest, false)?.to_vec();
+        compare_output(&built, reference_path)?;
+
+        return Ok(vec![PackSummary {
+            out_path: None,
+            segments: the_manifest.segments.len(),
+            bytes: built.len() as u64,
+        }]);
+    }
+
+    // create the output file and build the ftab file
+    let default_name = match output_template {
+        Some(template) => expand_output_template(&template, &the_manifest, manifest_path),
+        None => DEFAULT_OUTPUT_NAME.to_owned(),
+    };
+    let out_file_path = util::qualify_path_or_default_if_needed(out_path, input_dir, &default_name);
+    let builder = make_builder(&the_manifest, true)?;
+    let written = write_output(&builder, &out_file_path, write_options)?;
+
+    Ok(vec![PackSummary {
+        out_path: Some(out_file_path.into_owned()),
+        segments: the_manifest.segments.len(),
+        bytes: written,
+    }])
+}
+
+/// Options controlling how the `pack` operation writes a built file.
+#[derive(Copy, Clone, Debug)]
+struct WriteOptions {
+    /// Overwrite an existing file without asking.
+    overwrite: bool,
+    /// Take the default action instead of displaying prompts.
+    silent: bool,
+    /// Read the written file back and check it.
+    verify_after_write: bool,
+    /// Remove the written file when the check fails.
+    remove_invalid: bool,
+    /// Write through a temporary file renamed over the output file.
+    atomic: bool,
+    /// Write a `sha256sum` compatible checksum file next to the output file.
+    checksum_manifest: bool,
+}
+
+/// Computes the SHA-256 digest of a written file reading it in blocks.
+fn file_sha256(name: &'static str, path: &Path) -> Result<[u8; 32], Box<FileOpError>> {
+    let mut file = File::open(path)
+        .map_err(|error| FileOpError::make_open(name, path.to_path_buf(), error))?;
+    let mut hasher = Sha256::new();
+    let mut block = vec![0; 0x10000];
+    loop {
+        let len = file
+            .read(&mut block)
+            .map_err(|error| FileOpError::make_read(name, path.to_path_buf(), error))?;
+        if len == 0 {
+            return Ok(hasher.finalize());
+        }
+        hasher.update(&block[..len]);
+    }
+}
+
+/// Writes a built 'ftab' file to the specified path and returns the number of bytes written.
+fn write_output<'a>(
+    builder: &Builder,
+    out_file_path: &Path,
+    options: WriteOptions,
+) -> Result<u64, PackError<'a>> {
+    let WriteOptions {
+        overwrite,
+        silent,
+        verify_after_write,
+        remove_invalid,
+        atomic,
+        checksum_manifest,
+    } = options;
+
+    debug!("Writing ftab to {}.", out_file_path.display());
+
+    // the contents of the segments are copied into the file one after another and the header is
+    // written last, so the file is never held in memory
+    let write = |file: &mut File| -> io::Result<u64> {
+        let mut writer = BufWriter::new(file);
+        let written = builder.write_to_seek(&mut writer)?;
+        writer.flush()?;
+        Ok(written)
+    };
+    let written = if atomic {
+        let mut written = 0;
+        util::save_file_atomic_with("output file", out_file_path, overwrite, silent, |file| {
+            written = write(file)?;
+            Ok(())
+        })?;
+        written
+    } else {
+        let mut out_file = util::create_file("output file", out_file_path, overwrite, silent)?;
+        write(&mut out_file).map_err(|error| {
+            FileOpError::make_write("output file", out_file_path.to_path_buf(), error)
+        })?
+    };
+
+    debug!("Wrote {} bytes.", written);
+
+    if verify_after_write {
+        if let Err(e) = verify_output(out_file_path, &builder.header()) {
+            if remove_invalid {
+                match fs::remove_file(out_file_path) {
+                    Ok(()) => info!("Removed invalid file at {}.", out_file_path.display()),
+                    Err(e) => warn!(
+                        "Failed to remove invalid file at {}: {}",
+                        out_file_path.display(),
+                        e
+                    ),
+                }
+            }
+
+            return Err(e);
+        }
+    }
+
+    if checksum_manifest {
+        // the header is written after the contents, so the digest is computed from the file
+        let digest = file_sha256("output file", out_file_path)?;
+        write_checksum_file(out_file_path, &digest, overwrite, silent)?;
+    }
+
+    Ok(written)
+}
+
+/// Writes a SHA-256 digest of a file to `<path>.sha256` in the `sha256sum` format, i.e. the hex
+/// digest, two spaces and the file name, so that `sha256sum -c` can check it from the directory of
+/// the file.
+fn write_checksum_file(
+    path: &Path,
+    digest: &[u8; 32],
+    overwrite: bool,
+    silent: bool,
+) -> Result<(), Box<FileOpError>> {
+    let file_name = path.file_name().unwrap_or(path.as_os_str());
+    let line = format!("{}  {}\n", hex::encode(digest), file_name.to_string_lossy());
+
+    let mut checksum_path = path.as_os_str().to_owned();
+    checksum_path.push(".sha256");
+
+    debug!(
+        "Writing checksum to {}.",
+        Path::new(&checksum_path).display()
+    );
+
+    util::save_file(
+        "checksum file",
+        Path::new(&checksum_path),
+        line.as_bytes(),
+        overwrite,
+        silent,
+    )
+}
+
+/// A uniquely named temporary directory used by the `repack` operation which is removed along with
+/// its contents when dropped.
+struct RepackWorkDir {
+    path: PathBuf,
+    manifest_path: PathBuf,
+}
+
+impl RepackWorkDir {
+    /// Creates the directory under the system's temporary directory retrying with another name
+    /// when a directory with the same name exists.
+    fn create() -> Result<Self, RepackError<'static>> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.subsec_nanos())
+            .unwrap_or(0);
+
+        let mut attempt = 0;
+        loop {
+            let path = env::temp_dir().join(format!(
+                "ftabutil-repack-{}-{:08x}-{}",
+                process::id(),
+                nanos,
+                attempt
+            ));
+
+            match fs::create_dir(&path) {
+                Ok(()) => {
+                    let manifest_path = path.join(MANIFEST_NAME);
+                    return Ok(Self {
+                        path,
+                        manifest_path,
+                    });
+                }
+                Err(error) if error.kind() == io::ErrorKind::AlreadyExists && attempt < 16 => {
+                    attempt += 1
+                }
+                Err(error) => return Err(RepackError::FailedToCreateTempDir(path, error)),
+            }
+        }
+    }
+}
+
+impl Drop for RepackWorkDir {
+    fn drop(&mut self) {
+        match fs::remove_dir_all(&self.path) {
+            Ok(()) => debug!("Removed temporary directory at {}.", self.path.display()),
+            Err(e) => warn!(
+                "Failed to remove temporary directory at {}: {}",
+                self.path.display(),
+                e
+            ),
+        }
+    }
+}
+
+/// Runs the edit command of the `repack` operation passing it the directory as the last argument.
+fn run_edit_command(command: &str, dir: &Path) -> Result<(), RepackError<'static>> {
+    use RepackError::*;
+
+    #[cfg(windows)]
+    let mut child = process::Command::new("cmd");
+    #[cfg(windows)]
+    child.arg("/C").arg(command).arg(dir);
+
+    // The command is run by the shell, so it may contain arguments, pipes and so on. The directory
+    // is appended to its arguments.
+    #[cfg(not(windows))]
+    let mut child = process::Command::new("sh");
+    #[cfg(not(windows))]
+    child
+        .arg("-c")
+        .arg(format!("{} \"$@\"", command))
+        .arg("sh")
+        .arg(dir);
+
+    debug!("Running `{}` on {}.", command, dir.display());
+
+    let status = child
+        .status()
+        .map_err(|e| ExecFailed(command.to_owned(), e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(CommandFailed(command.to_owned(), status))
+    }
+}
+
+/// Unpacks a 'ftab' file into the work directory, runs the edit command on the directory and
+/// packs the directory back into the file at `out_path`.
+fn do_repack<'a>(
+    work_dir: &'a RepackWorkDir,
+    in_file: &'a Path,
+    command: &str,
+    out_path: &'a Path,
+    overwrite: bool,
+    silent: bool,
+    input: InputOptions,
+) -> Result<(), RepackError<'a>> {
+    use RepackError::*;
+
+    let unpack_options = UnpackOptions {
+        silent,
+        input,
+        ..Default::default()
+    };
+    do_unpack(in_file, Some(&work_dir.path), None, unpack_options)
+        .map_err(UnpackFailed)?
+        .log();
+
+    run_edit_command(command, &work_dir.path)?;
+
+    let pack_options = PackOptions {
+        overwrite,
+        silent,
+        ..Default::default()
+    };
+    do_pack(&work_dir.manifest_path, Some(out_path), None, pack_options)
+        .map_err(PackFailed)?
+        .iter()
+        .for_each(PackSummary::log);
+
+    Ok(())
+}
+
+/// Rewrites a 'ftab' file replacing its ticket with the contents of the file at `ticket_path` or
+/// removing the ticket when `ticket_path` is `None`.
+fn do_rewrite_ticket<'a>(
+    in_file: &'a Path,
+    ticket_path: Option<&'a Path>,
+    out_path: &'a Path,
+    overwrite: bool,
+    silent: bool,
+    input: InputOptions,
+) -> Result<(), RewriteError<'a>> {
+    use RewriteError::*;
+
+    let data = input.read(in_file)?;
+    let parser = input
+        .parse(&data)
+        .map_err(|e| HeaderParseError(in_file, e))?;
+    let mut builder = Builder::with_parser(&parser)?;
+
+    match ticket_path {
+        Some(ticket_path) => {
+            let ticket = util::read_file("ticket", ticket_path)?;
+            debug!("Setting a ticket of {} bytes.", ticket.len());
+            builder.set_ticket(Some(ticket));
+        }
+        None => {
+            if parser.ticket().is_none() {
+                warn!("File at {} doesn't contain a ticket.", in_file.display());
+            }
+            builder.set_ticket(None);
+        }
+    }
+
+    let mut out_file = util::create_file("output file", out_path, overwrite, silent)?;
+
+    debug!("Writing ftab to {}.", out_path.display());
+
+    let written = builder
+        .write_to_counted(&mut out_file)
+        .map_err(|error| FileOpError::make_write("output file", out_path.to_path_buf(), error))?;
+
+    debug!("Wrote {} bytes.", written);
+    info!(target: SUCCESS_LOG_TARGET, "Done.");
+
+    Ok(())
+}
+
+/// Rewrites a 'ftab' file with a canonical layout keeping its header fields, segments and ticket,
+/// see [`Builder::with_parser_normalized`] for what is changed. The output path may be the input
+/// file itself, which is read in full before it is rewritten.
+fn do_normalize<'a>(
+    in_file: &'a Path,
+    out_path: &'a Path,
+    overwrite: bool,
+    silent: bool,
+    input: InputOptions,
+) -> Result<(), RewriteError<'a>> {
+    use RewriteError::*;
+
+    let data = input.read(in_file)?;
+    let parser = input
+        .parse(&data)
+        .map_err(|e| HeaderParseError(in_file, e))?;
+    let builder = Builder::with_parser_normalized(&parser)?;
+
+    let mut out_file = util::create_file("output file", out_path, overwrite, silent)?;
+
+    debug!("Writing ftab to {}.", out_path.display());
+
+    let written = builder
+        .write_to_counted(&mut out_file)
+        .map_err(|error| FileOpError::make_write("output file", out_path.to_path_buf(), error))?;
+
+    info!("Normalized {} bytes into {} bytes.", data.len(), written);
+    info!(target: SUCCESS_LOG_TARGET, "Done.");
+
+    Ok(())
+}
+
+/// Extracts the contents of the first segment with the specified tag from a 'ftab' file located at
+/// `offset` in the input file. The contents are written to `out_path`, to the standard output when
+/// it is `-` or to a file named after the tag in the current directory when it is `None`.
+fn do_extract<'a>(
+    in_file: &'a Path,
+    offset: usize,
+    tag: Tag,
+    out_path: Option<&Path>,
+    overwrite: bool,
+    silent: bool,
+    input: InputOptions,
+) -> Result<(), ExtractError<'a>> {
+    use ExtractError::*;
+
+    let data = input.read(in_file)?;
+    if offset
+        .checked_add(HEADER_LEN)
+        .map_or(true, |end| end > data.len())
+    {
+        return Err(OffsetOutOfBounds(in_file, offset, data.len()));
+    }
+    let parser = input
+        .parse_at(&data, offset)
+        .map_err(|e| HeaderParseError(in_file, e))?;
+
+    let mut segments_parser = input.segments(&parser);
+    let mut found = None;
+    while let Some(segment) = segments_parser.next_segment()? {
+        if segment.tag != tag.0 {
+            continue;
+        }
+        if found.is_some() {
+            warn!(
+                "File at {} contains more than one segment with tag {}, extracting the first one.",
+                in_file.display(),
+                tag
+            );
+            break;
+        }
+        found = Some(segment);
+    }
+    let segment = found.ok_or(TagNotFound(in_file, tag))?;
+
+    debug!(
+        "Segment with tag {} is at offset {:#x} of the ftab file, length is {:#x}.",
+        tag,
+        segment.offset,
+        segment.data.len()
+    );
+
+    let default_path = filename_for_tag(tag.0);
+    let out_path = out_path.unwrap_or(&default_path);
+    if out_path == Path::new("-") {
+        io::stdout()
+            .write_all(segment.data)
+            .map_err(|error| FileOpError::make_write("segment", out_path.to_path_buf(), error))?;
+    } else {
+        util::save_file("segment", out_path, segment.data, overwrite, silent)?;
+    }
+
+    info!(target: SUCCESS_LOG_TARGET, "Done.");
+
+    Ok(())
+}
+
+/// The operations the `batch` subcommand can run for each listed file.
+#[derive(Copy, Clone, Debug)]
+enum BatchOperation {
+    Info,
+    Verify,
+    Unpack,
+}
+
+/// Returns the names of the directories the files of a batch are unpacked into. Each file is
+/// unpacked into a directory named after its file stem, which is prefixed with the index of the file
+/// in the list when several files share the stem.
+///
+/// # Errors
+/// Returns the indices of two files that would still be unpacked into the same directory along with
+/// its name, e.g. when the stem of one of the files looks like a prefixed stem of another file.
+fn batch_dir_names(paths: &[PathBuf]) -> Result<Vec<OsString>, (usize, usize, OsString)> {
+    let stem = |path: &PathBuf| path.file_stem().unwrap_or(path.as_os_str()).to_os_string();
+
+    let mut stem_counts = HashMap::new();
+    for path in paths.iter() {
+        *stem_counts.entry(stem(path)).or_insert(0) += 1;
+    }
+
+    let mut names = Vec::with_capacity(paths.len());
+    let mut indices = HashMap::new();
+    for (index, path) in paths.iter().enumerate() {
+        let stem = stem(path);
+        let name = if stem_counts[&stem] > 1 {
+            let mut name = OsString::from(format!("{}-", index));
+            name.push(&stem);
+            name
+        } else {
+            stem
+        };
+
+        if let Some(&other) = indices.get(&name) {
+            return Err((other, index, name));
+        }
+        indices.insert(name.clone(), index);
+        names.push(name);
+    }
+
+    Ok(names)
+}
+
+/// Reads a list of 'ftab' file paths, one per line, and runs an operation for each of them with the
+/// default options. Empty lines and lines starting with `#` are skipped and relative paths are
+/// relative to the directory of the list. A failure for one file is reported and the remaining files
+/// are still processed.
+///
+/// Files are unpacked into directories named by [`batch_dir_names`] inside `out_dir` or the current
+/// directory, which is created when missing.
+fn do_batch<'a>(
+    list_path: &'a Path,
+    operation: BatchOperation,
+    out_dir: Option<&Path>,
+    overwrite: bool,
+    silent: bool,
+    input: InputOptions,
+) -> Result<(), BatchError<'a>> {
+    use BatchError::*;
+
+    let list = util::read_file("file list", list_path)?;
+    let list = String::from_utf8_lossy(&list);
+    let list_dir = list_path.parent();
+    let paths: Vec<PathBuf> = list
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| util::qualify_path_if_needed(line, list_dir).into_owned())
+        .collect();
+
+    // Every file is checked to get its own directory before anything is unpacked.
+    let dir_names = match operation {
+        BatchOperation::Unpack => batch_dir_names(&paths).map_err(|(first, second, name)| {
+            let dir = util::qualify_path_if_needed(&name, out_dir).into_owned();
+            OutputDirCollision(paths[first].clone(), paths[second].clone(), dir)
+        })?,
+        _ => Vec::new(),
+    };
+
+    let mut failed = 0;
+    for (index, path) in paths.iter().enumerate() {
+        info!("Processing file at {}.", path.display());
+
+        let result = match operation {
+            BatchOperation::Info => do_info(path, false, false, input).map_err(|e| e.to_string()),
+            BatchOperation::Verify => {
+                do_verify(path, OutputFormat::Text, false, false, None, None, input)
+                    .map_err(|e| e.to_string())
+            }
+            BatchOperation::Unpack => {
+                let unpack_dir = util::qualify_path_if_needed(&dir_names[index], out_dir);
+                let options = UnpackOptions {
+                    overwrite,
+                    create_parent_dirs: true,
+                    silent,
+                    input,
+                    ..Default::default()
+                };
+                do_unpack(path, Some(&unpack_dir), None, options)
+                    .map(|summary| summary.log())
+                    .map_err(|e| e.to_string())
+            }
+        };
+        if let Err(e) = result {
+            error!("{}", e);
+            failed += 1;
+        }
+    }
+
+    if failed != 0 {
+        return Err(FilesFailed(list_path, failed, paths.len()));
+    }
+
+    info!(
+        target: SUCCESS_LOG_TARGET,
+        "Done, processed {} file(s).",
+        paths.len()
+    );
+
+    Ok(())
+}
+
+/// Parses a 'ftab' file and rebuilds it in memory the specified number of times checking that every
+/// rebuilt file is identical to the first one and prints the throughput of both operations.
+fn do_selftest<'a>(
+    in_file: &'a Path,
+    iterations: usize,
+    input: InputOptions,
+) -> Result<(), SelftestError<'a>> {
+    use SelftestError::*;
+
+    let data = input.read(in_file)?;
+    let mut first: Option<Vec<u8>> = None;
+    let mut parse_time = Duration::ZERO;
+    let mut build_time = Duration::ZERO;
+
+    for iteration in 0..iterations {
+        let start = Instant::now();
+        let parser = input
+            .parse(&data)
+            .map_err(|e| HeaderParseError(in_file, e))?;
+        let builder = Builder::with_parser(&parser)?;
+        parse_time += start.elapsed();
+
+        let start = Instant::now();
+        let rebuilt = builder.to_vec();
+        build_time += start.elapsed();
+
+        match first.as_ref() {
+            Some(first) if *first != rebuilt => return Err(RebuildMismatch(iteration)),
+            Some(_) => {}
+            None => first = Some(rebuilt),
+        }
+    }
+
+    let throughput = |time: Duration| {
+        let megabytes = (data.len() * iterations) as f64 / (1024.0 * 1024.0);
+        megabytes / time.as_secs_f64().max(f64::EPSILON)
+    };
+    println!("iterations: {}", iterations);
+    println!(
+        "parse: {:.1} MB/s ({:?})",
+        throughput(parse_time),
+        parse_time
+    );
+    println!(
+        "build: {:.1} MB/s ({:?})",
+        throughput(build_time),
+        build_time
+    );
+    if let Some(first) = first {
+        println!(
+            "rebuilt file is {}identical to the input",
+            if first == data { "" } else { "not " }
+        );
+    }
+
+    Ok(())
+}
+
+/// Prints a map of a 'ftab' file listing the ranges of the header, the segment list, the contents of
+/// the segments, the ticket and the bytes between them in the order of their offsets.
+fn print_map(parser: &Parser) {
+    let data_start = HEADER_LEN + parser.segments_slice().len() * SEGMENT_HEADER_LEN;
+    let mut regions = Vec::with_capacity(parser.segments_slice().len() + 1);
+    let mut segments_parser = parser.segments();
+    while segments_parser.count() != 0 {
+        match segments_parser.next_segment() {
+            Ok(Some(segment)) => regions.push((
+                segment.offset,
+                segment.offset + segment.data.len(),
+                format!("segment {}", paint(Color::Tag, Tag(segment.tag))),
+            )),
+            Ok(None) => break,
+            Err(e) => println!("{}", paint(Color::Warning, e)),
+        }
+    }
+    if let Some(range) = parser.ticket_range() {
+        regions.push((range.start, range.end, "ticket".to_owned()));
+    }
+    regions.sort_by_key(|&(start, end, _)| (start, end));
+
+    let print_range = |start: usize, end: usize, description: &str| {
+        println!(
+            "{:#010x}..{:#010x}  {:>10}  {}",
+            paint(Color::Offset, start),
+            paint(Color::Offset, end),
+            end - start,
+            description
+        );
+    };
+
+    print_range(0, HEADER_LEN, "header");
+    print_range(
+        HEADER_LEN,
+        data_start,
+        &format!("segment list ({} entries)", parser.segments_slice().len()),
+    );
+
+    let mut prev_end = data_start;
+    for (start, end, description) in regions.iter() {
+        if *start > prev_end {
+            // Anything longer than the alignment padding is not referenced by anything.
+            let padding_len = alignment_padding(prev_end);
+            let gap = if *start - prev_end <= padding_len {
+                "padding"
+            } else {
+                "unreferenced"
+            };
+            print_range(prev_end, *start, gap);
+        }
+
+        if *start < prev_end {
+            print_range(*start, *end, &format!("{} (overlaps)", description));
+        } else {
+            print_range(*start, *end, description);
+        }
+        prev_end = prev_end.max(*end);
+    }
+
+    if prev_end < parser.file_len() {
+        print_range(prev_end, parser.file_len(), "trailing data");
+    }
+}
+
+fn do_info<'a>(
+    in_file: &'a Path,
+    count_only: bool,
+    map: bool,
+    input: InputOptions,
+) -> Result<(), InfoError<'a>> {
+    use InfoError::*;
+
+    // The segments count is available in the header so there's no need to read the whole file.
+    if count_only {
+        let data = input.read_prefix(in_file, HEADER_LEN)?;
+        let count = count_segments(&data).map_err(|e| HeaderParseError(in_file, e))?;
+
+        println!("{}", count);
+
+        return Ok(());
+    }
+
+    let data = input.read(in_file)?;
+    let parser = input
+        .parse(&data)
+        .map_err(|e| HeaderParseError(in_file, e))?;
+
+    do_print_header(&parser);
+
+    match parser.ticket() {
+        Some(ticket) => println!("ticket length: {}", paint(Color::Offset, ticket.len())),
+        None => println!("ticket: none"),
+    }
+
+    match parser.segments().count() {
+        0 => println!("segments count: 0 (file contains no segments)"),
+        count => println!("segments count: {}", paint(Color::Offset, count)),
+    }
+
+    if map {
+        println!();
+        print_map(&parser);
+    }
+
+    Ok(())
+}
+
+/// Formats of manifests supported by the `convert` and `pack` operations.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum ManifestFormat {
+    Toml,
+    Json,
+}
+
+impl ManifestFormat {
+    /// Determines the format of a manifest from the extension of its path.
+    fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "toml" => Some(ManifestFormat::Toml),
+            "json" => Some(ManifestFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Converts a TOML value into a JSON value. Datetimes are converted into strings.
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(value) => Value::Integer(value),
+        toml::Value::Float(value) => Value::Float(value),
+        toml::Value::Boolean(value) => Value::Bool(value),
+        toml::Value::Datetime(datetime) => Value::String(datetime.to_string()),
+        toml::Value::Array(values) => Value::Array(values.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| (key.into(), toml_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// Makes a JSON object with the keys from `fields` first in the order of `fields` and any other
+/// keys after them.
+fn ordered_object(mut entries: Vec<(String, Value)>, fields: &[&str]) -> Value {
+    entries.sort_by_key(|(key, _)| {
+        fields
+            .iter()
+            .position(|field| field == key)
+            .unwrap_or(fields.len())
+    });
+
+    Value::Object(
+        entries
+            .into_iter()
+            .map(|(key, value)| (key.into(), value))
+            .collect(),
+    )
+}
+
+/// Converts a manifest into a JSON value with fields in the same order as in TOML manifests.
+fn manifest_to_json(manifest: &Manifest) -> Value {
+    let toml::Value::Table(table) = toml::Value::try_from(manifest).unwrap() else {
+        unreachable!("manifests are serialized as tables");
+    };
+
+    let entries = table
+        .into_iter()
+        .map(|(key, value)| {
+            let element_fields = match key.as_str() {
+                "segments" => SEGMENT_FIELDS,
+                "outputs" => OUTPUT_FIELDS,
+                _ => return (key, toml_to_json(value)),
+            };
+            let toml::Value::Array(elements) = value else {
+                return (key, toml_to_json(value));
+            };
+
+            let elements = elements
+                .into_iter()
+                .map(|element| match element {
+                    toml::Value::Table(table) => {
+                        let entries = table
+                            .into_iter()
+                            .map(|(key, value)| (key, toml_to_json(value)))
+                            .collect();
+                        ordered_object(entries, element_fields)
+                    }
+                    element => toml_to_json(element),
+                })
+                .collect();
+            (key, Value::Array(elements))
+        })
+        .collect();
+
+    ordered_object(entries, MANIFEST_FIELDS)
+}
+
+/// Parses a JSON manifest. Unknown keys are rejected the same way [`Manifest::from_toml_strict`]
+/// rejects them when `strict` is set.
+fn parse_json_manifest(data: &[u8], strict: bool) -> Result<Manifest, String> {
+    let s = std::str::from_utf8(data).map_err(|e| e.to_string())?;
+    let value = json::parse(s).and_then(json_to_toml)?;
+
+    if strict {
+        Manifest::from_value_strict(value).map_err(|e| e.to_string())
+    } else {
+        value.try_into().map_err(|e: toml::de::Error| e.to_string())
+    }
+}
+
+/// Converts a JSON value into a TOML value. Fails on `null`s which TOML can't represent.
+fn json_to_toml(value: Value) -> Result<toml::Value, String> {
+    Ok(match value {
+        Value::Null => return Err("null values are not supported".to_owned()),
+        Value::Bool(value) => toml::Value::Boolean(value),
+        Value::Integer(value) => toml::Value::Integer(value),
+        Value::Float(value) => toml::Value::Float(value),
+        Value::String(s) => toml::Value::String(s),
+        Value::Array(values) => toml::Value::Array(
+            values
+                .into_iter()
+                .map(json_to_toml)
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Object(fields) => toml::Value::Table(
+            fields
+                .into_iter()
+                .map(|(key, value)| Ok((key.into_owned(), json_to_toml(value)?)))
+                .collect::<Result<_, String>>()?,
+        ),
+    })
+}
+
+/// Converts a manifest between the TOML and JSON formats determined by the extensions of the
+/// paths. The manifest is deserialized into a [`Manifest`] in between, so the output is validated
+/// and contains exactly the fields a [`Manifest`] has.
+fn do_convert<'a>(
+    in_path: &'a Path,
+    out_path: &'a Path,
+    overwrite: bool,
+    silent: bool,
+) -> Result<(), ConvertError<'a>> {
+    use ConvertError::*;
+
+    let in_format = ManifestFormat::from_path(in_path).ok_or(UnknownFormat(in_path))?;
+    let out_format = ManifestFormat::from_path(out_path).ok_or(UnknownFormat(out_path))?;
+
+    let data = util::read_file("manifest", in_path)?;
+    let the_manifest: Manifest = match in_format {
+        ManifestFormat::Toml => toml::from_slice(&data).map_err(|e| e.to_string()),
+        ManifestFormat::Json => parse_json_manifest(&data, false),
+    }
+    .map_err(|e| ManifestParseError(in_path, e))?;
+
+    let out_data = match out_format {
+        ManifestFormat::Toml => the_manifest.to_toml(),
+        ManifestFormat::Json => format!("{}\n", manifest_to_json(&the_manifest)).into_bytes(),
+    };
+    util::save_file("manifest", out_path, &out_data, overwrite, silent)?;
+
+    Ok(())
+}
+
+/// Converts a list of diagnostics into a JSON array.
+fn diagnostics_to_json(diagnostics: &[Diagnostic], hints: bool) -> Value {
+    let diagnostics = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let mut fields = vec![
+                (
+                    "kind".into(),
+                    Value::String(diagnostic.kind.name().to_owned()),
+                ),
+                (
+                    "tag".into(),
+                    diagnostic
+                        .tag
+                        .map(|tag| Value::String(tag.to_string()))
+                        .unwrap_or(Value::Null),
+                ),
+                ("message".into(), Value::String(diagnostic.message.clone())),
+            ];
+            if hints {
+                let hint = diagnostic.hint.clone().map(Value::String);
+                fields.push(("hint".into(), hint.unwrap_or(Value::Null)));
+            }
+
+            Value::Object(fields)
+        })
+        .collect();
+
+    Value::Array(diagnostics)
+}
+
+/// The format of the output of the `verify` operation.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum OutputFormat {
+    /// Lines of text for humans.
+    Text,
+    /// Single-line JSON for machines.
+    Json,
+    /// Indented JSON.
+    PrettyJson,
+}
+
+fn do_verify<'a>(
+    in_file: &'a Path,
+    format: OutputFormat,
+    hints: bool,
+    checksum: bool,
+    expected_checksum: Option<u32>,
+    alignment: Option<usize>,
+    input: InputOptions,
+) -> Result<(), VerifyError<'a>> {
+    use VerifyError::*;
+
+    let data = input.read(in_file)?;
+    let parser = input
+        .parse(&data)
+        .map_err(|e| HeaderParseError(in_file, e))?;
+    let mut diagnostics = verify(&parser);
+    if let Some(expected) = expected_checksum {
+        diagnostics.extend(check_metadata_checksum(&parser, expected));
+    }
+    if let Some(alignment) = alignment {
+        diagnostics.extend(check_alignment(&parser, alignment));
+    }
+
+    if checksum && format == OutputFormat::Text {
+        println!(
+            "{}: metadata CRC-32 {:#010x}",
+            in_file.display(),
+            metadata_crc32(&parser)
+        );
+    }
+
+    if format == OutputFormat::PrettyJson {
+        println!("{:#}", diagnostics_to_json(&diagnostics, hints));
+    } else if format == OutputFormat::Json {
+        println!("{}", diagnostics_to_json(&diagnostics, hints));
+    } else if diagnostics.is_empty() {
+        println!("{}: OK", in_file.display());
+    } else {
+        for diagnostic in diagnostics.iter() {
+            println!(
+                "{}: {}",
+                in_file.display(),
+                paint(Color::Warning, diagnostic)
+            );
+            if let Some(hint) = diagnostic.hint.as_ref().filter(|_| hints) {
+                println!("{}:   hint: {}", in_file.display(), hint);
+            }
+        }
+    }
+
+    if diagnostics.is_empty() {
+        Ok(())
+    } else {
+        Err(ProblemsFound(in_file, diagnostics.len()))
+    }
+}
+
+/// Checks a manifest without building anything. All problems found in the manifest itself and in the
+/// files it references are printed rather than just the first one.
+fn do_validate_manifest<'a>(
+    manifest_path: &'a Path,
+    symlink_policy: SymlinkPolicy,
+    sandbox: bool,
+    strict_manifest: bool,
+) -> Result<(), ValidateError<'a>> {
+    use ValidateError::*;
+
+    let manifest_data = util::read_file("manifest", manifest_path)?;
+    let bundle = if Bundle::is_bundle(&manifest_data) {
+        debug!("Reading bundle at {}.", manifest_path.display());
+
+        let bundle =
+            Bundle::parse(&manifest_data).map_err(|e| BundleParseError(manifest_path, e))?;
+        Some(bundle)
+    } else {
+        None
+    };
+    let manifest_data = match bundle.as_ref() {
+        Some(bundle) => bundle
+            .get(MANIFEST_NAME)
+            .ok_or(NoManifestInBundle(manifest_path))?,
+        None => &manifest_data,
+    };
+    // the manifest is parsed the same way `pack` parses it
+    let manifest = if bundle.is_none()
+        && ManifestFormat::from_path(manifest_path) == Some(ManifestFormat::Json)
+    {
+        parse_json_manifest(manifest_data, strict_manifest)
+            .map_err(|e| JsonManifestParseError(manifest_path, e))?
+    } else if strict_manifest {
+        Manifest::from_toml_strict(manifest_data)
+            .map_err(|e| ManifestParseError(manifest_path, e))?
+    } else {
+        toml::from_slice::<Manifest>(manifest_data)
+            .map_err(|e| ManifestParseError(manifest_path, e))?
+    };
+
+    let mut problems = manifest.validate(sandbox);
+
+    // files are only opened, a bundle is checked to contain them
+    let input_dir = manifest_path.parent();
+    let mut check_file = |name: &'static str, path: &Path| {
+        let result = match bundle.as_ref() {
+            Some(bundle) => bundle.read_file(name, path).map(|_| ()),
+            None => util::check_manifest_file(name, path, input_dir, symlink_policy),
+        };
+        if let Err(e) = result {
+            problems.push(e.to_string());
+        }
+    };
+    if let Some(ticket) = manifest.ticket.as_deref() {
+        check_file("ticket", ticket);
+    }
+    for segment in manifest.segments.iter() {
+        check_file("segment", &segment.path);
+    }
+
+    if problems.is_empty() {
+        println!("{}: OK", manifest_path.display());
+        Ok(())
+    } else {
+        for problem in problems.iter() {
+            println!(
+                "{}: {}",
+                manifest_path.display(),
+                paint(Color::Warning, problem)
+            );
+        }
+        Err(ProblemsFound(manifest_path, problems.len()))
+    }
+}
+
+/// The environment variable which specifies the log level when the `--log_level` option is absent.
+const LOG_LEVEL_ENV_VAR: &str = "FTABUTIL_LOG";
+
+/// Converts a log level name into a [`LevelFilter`] falling back to [`LevelFilter::Warn`] for
+/// unknown names.
+fn parse_log_level(log_level: &str) -> LevelFilter {
+    match log_level {
+        "NONE" | "none" => LevelFilter::Off,
+        "TRACE" | "trace" => LevelFilter::Trace,
+        "DEBUG" | "debug" => LevelFilter::Debug,
+        "INFO" | "info" => LevelFilter::Info,
+        "WARN" | "warn" => LevelFilter::Warn,
+        "ERROR" | "error" => LevelFilter::Error,
+        _ => LevelFilter::Warn,
+    }
+}
+
+/// Parses an offset which is either a decimal number or a hexadecimal number with the `0x` prefix.
+fn parse_offset(s: &str) -> Result<usize, String> {
+    let s = s.trim();
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .map_err(|_| format!("'{}' is not a valid offset", s))
+}
+
+/// Parses a tag pattern, see [`TagPattern`] for the syntax.
+fn parse_tag_pattern(s: &str) -> Result<TagPattern, String> {
+    TagPattern::parse(s).ok_or_else(|| {
+        format!(
+            "'{}' is not a valid tag pattern, expected up to 4 characters or a 0x-prefixed hex \
+            string of up to 4 bytes optionally followed by '*'",
+            s
+        )
+    })
+}
+
+/// Parses a single tag written the same way as a [`TagPattern`] without `*`.
+fn parse_tag(s: &str) -> Result<Tag, String> {
+    TagPattern::parse(s)
+        .and_then(|pattern| pattern.tag())
+        .ok_or_else(|| {
+            format!(
+                "'{}' is not a valid tag, expected up to 4 characters or a 0x-prefixed hex string \
+                of up to 4 bytes",
+                s
+            )
+        })
+}
+
+/// Parses a file extension with an optional leading dot which must not contain path separators.
+fn parse_extension(s: &str) -> Result<String, String> {
+    let extension = s.strip_prefix('.').unwrap_or(s);
+    if extension.contains(std::path::is_separator) {
+        Err(format!(
+            "'{}' is not a valid extension, it must not contain path separators",
+            s
+        ))
+    } else {
+        Ok(extension.to_owned())
+    }
+}
+
+/// Parses an override of an unknown header field written as `N=VALUE` where `N` is the index of the
+/// field from 0 to 6 and `VALUE` is either decimal or hexadecimal with the `0x` prefix.
+fn parse_unk_override(s: &str) -> Result<(usize, u32), String> {
+    let invalid = || {
+        format!(
+            "'{}' is not a valid override, expected N=VALUE where N is from 0 to 6",
+            s
+        )
+    };
+
+    let (index, value) = s.split_once('=').ok_or_else(invalid)?;
+    let index: usize = index.trim().parse().map_err(|_| invalid())?;
+    if index > 6 {
+        return Err(invalid());
+    }
+    let value = parse_offset(value)
+        .ok()
+        .and_then(|value| u32::try_from(value).ok())
+        .ok_or_else(|| format!("'{}' is not a valid 32-bit value", value.trim()))?;
+
+    Ok((index, value))
+}
+
+/// Parses a non-zero alignment.
+fn parse_alignment(s: &str) -> Result<usize, String> {
+    match parse_offset(s) {
+        Ok(0) => Err("alignment must not be zero".to_owned()),
+        result => result.map_err(|_| format!("'{}' is not a valid alignment", s.trim())),
+    }
+}
+
+/// Parses a CRC-32 checksum written as a hexadecimal number with an optional `0x` prefix.
+fn parse_crc32(s: &str) -> Result<u32, String> {
+    let s = s.trim();
+    let hex = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    u32::from_str_radix(hex, 16).map_err(|_| format!("'{}' is not a valid CRC-32 checksum", s))
+}
+
+/// Parses a size which is either a plain byte count or a number followed by a binary unit suffix,
+/// e.g. `512K`, `1M` or `2GiB`.
+fn parse_size(s: &str) -> Result<usize, String> {
+    let s = s.trim();
+    let digits_end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (digits, suffix) = s.split_at(digits_end);
+    let value: usize = digits
+        .parse()
+        .map_err(|_| format!("'{}' is not a valid size", s))?;
+
+    let shift = match suffix.to_ascii_uppercase().as_str() {
+        "" | "B" => 0,
+        "K" | "KB" | "KIB" => 10,
+        "M" | "MB" | "MIB" => 20,
+        "G" | "GB" | "GIB" => 30,
+        _ => return Err(format!("unknown size suffix '{}'", suffix)),
+    };
+
+    value
+        .checked_mul(1 << shift)
+        .ok_or_else(|| format!("size '{}' is too large", s))
+}
+
+/// A filter selecting segments by the length of their contents.
+#[derive(Copy, Clone, Default, Debug)]
+struct SizeFilter {
+    min: Option<usize>,
+    max: Option<usize>,
+}
+
+impl SizeFilter {
+    /// Returns the arguments used to configure the filter.
+    fn args() -> [Arg; 2] {
+        [
+            arg!(min_size: --min_size <SIZE>)
+                .value_parser(parse_size)
+                .help("Only include segments at least this large, e.g. 512K or 1M."),
+            arg!(max_size: --max_size <SIZE>)
+                .value_parser(parse_size)
+                .help("Only include segments at most this large, e.g. 512K or 1M."),
+        ]
+    }
+
+    /// Creates a filter from the arguments returned by [`SizeFilter::args`].
+    fn from_matches(matches: &ArgMatches) -> Self {
+        Self {
+            min: matches.get_one::<usize>("min_size").copied(),
+            max: matches.get_one::<usize>("max_size").copied(),
+        }
+    }
+
+    /// Checks if a segment with contents of the specified length passes the filter.
+    fn matches(&self, len: usize) -> bool {
+        self.min.map_or(true, |min| len >= min) && self.max.map_or(true, |max| len <= max)
+    }
+}
+
+fn do_list<'a>(
+    in_file: &'a Path,
+    size_filter: SizeFilter,
+    data_hash: bool,
+    input: InputOptions,
+) -> Result<(), InspectError<'a>> {
+    use InspectError::*;
+
+    let data = input.read(in_file)?;
+    let parser = input
+        .parse(&data)
+        .map_err(|e| HeaderParseError(in_file, e))?;
+
+    if parser.segments_slice().is_empty() {
+        println!("file contains no segments");
+        return Ok(());
+    }
+
+    let mut segments_parser = input.segments(&parser);
+    let mut index = 0;
+    while let Some(segment) = segments_parser.next_segment()? {
+        if size_filter.matches(segment.data.len()) {
+            print!(
+                "{:>4}  {:<10}  {:#010x}  {:>10}  {:#x}",
+                index,
+                paint(Color::Tag, Tag(segment.tag)),
+                paint(Color::Offset, segment.offset),
+                paint(Color::Offset, segment.data.len()),
+                segment.unk
+            );
+            if data_hash {
+                print!("  {}", hex::encode(&sha256(segment.data)[..4]));
+            }
+            println!();
+        }
+
+        index += 1;
+    }
+
+    Ok(())
+}
+
+fn do_tags<'a>(in_file: &'a Path, null: bool, input: InputOptions) -> Result<(), InspectError<'a>> {
+    use InspectError::*;
+
+    let data = input.read(in_file)?;
+    let parser = input
+        .parse(&data)
+        .map_err(|e| HeaderParseError(in_file, e))?;
+
+    // Only the tags are needed so the segment list entries aren't validated.
+    let separator = if null { '\0' } else { '\n' };
+    for entry in parser.segments_slice() {
+        let tag: [u8; 4] = entry[..4].try_into().unwrap();
+        print!("{}{}", Tag(tag), separator);
+    }
+
+    Ok(())
+}
+
+/// Formats a byte count using binary units, e.g. `1.5 MiB`.
+fn format_size(size: usize) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if size < 1024 {
+        return format!("{} B", size);
+    }
+
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+fn do_stat<'a>(
+    in_file: &'a Path,
+    ascending: bool,
+    raw_bytes: bool,
+    size_filter: SizeFilter,
+    input: InputOptions,
+) -> Result<(), InspectError<'a>> {
+    use InspectError::*;
+
+    let data = input.read(in_file)?;
+    let parser = input
+        .parse(&data)
+        .map_err(|e| HeaderParseError(in_file, e))?;
+
+    if parser.segments_slice().is_empty() {
+        println!("file contains no segments");
+        return Ok(());
+    }
+
+    let mut segments_parser = parser.segments();
+    let mut sizes = Vec::with_capacity(segments_parser.count());
+    while let Some(segment) = segments_parser.next_segment()? {
+        if size_filter.matches(segment.data.len()) {
+            sizes.push((Tag(segment.tag), segment.data.len()));
+        }
+    }
+
+    if ascending {
+        sizes.sort_by_key(|&(_, size)| size);
+    } else {
+        sizes.sort_by_key(|&(_, size)| std::cmp::Reverse(size));
+    }
+
+    let format = |size: usize| {
+        if raw_bytes {
+            size.to_string()
+        } else {
+            format_size(size)
+        }
+    };
+
+    for (tag, size) in sizes.iter() {
+        println!(
+            "{:>12}  {}",
+            paint(Color::Offset, format(*size)),
+            paint(Color::Tag, tag)
+        );
+    }
+
+    let total = sizes.iter().map(|(_, size)| size).sum();
+    println!("{:>12}  total", paint(Color::Offset, format(total)));
+
+    Ok(())
+}
+
+/// Logs the error returned by an operation and returns whether the operation succeeded.
+fn report<E: fmt::Display>(result: Result<(), E>) -> bool {
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            error!("{}", e);
+            false
+        }
+    }
+}
+
+/// Creates the `--in_place` flag of subcommands rewriting an existing ftab file.
+fn in_place_arg() -> Arg {
+    arg!(in_place: --in_place)
+        .conflicts_with("out_file")
+        .help("Rewrites the input file instead of writing the result to a separate output file.")
+}
+
+/// Creates the output file argument of subcommands rewriting an existing ftab file which is
+/// required unless the input file is rewritten in place.
+fn out_file_arg(paths: ExpandedPathParser) -> Arg {
+    arg!(out_file: [OUT_FILE])
+        .value_parser(paths)
+        .required_unless_present("in_place")
+        .help("Path to the output file. Pass --in_place instead to rewrite the input file.")
+}
+
+/// Returns the output path of a subcommand rewriting an existing ftab file along with whether an
+/// existing file may be overwritten without asking. Rewriting the input file in place is requested
+/// explicitly, so no confirmation is needed in this case.
+fn rewrite_out_path<'a>(sub_matches: &'a ArgMatches, in_file: &'a Path) -> (&'a Path, bool) {
+    let overwrite = sub_matches.get_flag("overwrite");
+    match sub_matches.get_one::<PathBuf>("out_file") {
+        Some(out_file) => (out_file, overwrite),
+        None => (in_file, true),
+    }
+}
+
+/// Builds the command line interface. Paths are expanded only when `expand_paths` is set, since
+/// arguments passed through a shell have already been expanded by it.
+fn build_cli(expand_paths: bool) -> Command {
+    let paths = ExpandedPathParser(expand_paths);
+
+    command!()
+        .propagate_version(true)
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .arg(arg!(print_header: -H --print_header).help(
+            "Prints fields of the ftab file header that are neither offsets nor magic \
+                    (currently all are unknown and ignored).",
+        ))
+        .arg(arg!(log_level: -l --log_level <LEVEL>).help(
+            "Configures the log level for the tool. Available log levels are: NONE \
+                    (disables logging entirely), TRACE, DEBUG, INFO, WARN and ERROR. When not \
+                    specified, the value of the FTABUTIL_LOG environment variable is used, \
+                    otherwise the default is WARN.",
+        ))
+        .arg(
+            arg!(input_format: --input_format <FORMAT>)
+                .value_parser(["auto", "raw", "gzip"])
+                .default_value("auto")
+                .help(
+                    "Configures how input ftab files are read. In the auto mode gzip-compressed \
+                    files are detected and decompressed while other compressed files are rejected \
+                    with an error, in the raw mode files are always parsed as is and in the gzip \
+                    mode files are always decompressed.",
+                ),
+        )
+        .arg(
+            arg!(max_segments: --max_segments [COUNT])
+                .value_parser(value_parser!(usize))
+                .num_args(0..=1)
+                .require_equals(true)
+                .help(format!(
+                    "Rejects input ftab files declaring more segments than this, which is useful \
+                    when processing untrusted files. When passed without a value, the limit is \
+                    {}. By default the segments count is not limited.",
+                    DEFAULT_MAX_SEGMENTS
+                )),
+        )
+        .arg(arg!(skip_empty_tags: --skip_empty_tags).help(
+            "Treats segment list entries with an all-zero tag and a zero length as padding and \
+            omits them when listing, extracting and unpacking. By default these are kept.",
+        ))
+        .arg(arg!(expand_paths: --expand_paths).help(
+            "Expands a leading ~ and $NAME or ${NAME} environment variable references in paths \
+            passed on the command line, which is useful when the tool is invoked without a shell. \
+            By default paths are used as is.",
+        ))
+        .arg(arg!(json_pretty: --json_pretty).help(
+            "Prints JSON output indented. This is the default when stdout is a terminal.",
+        ))
+        .arg(
+            arg!(json_compact: --json_compact)
+                .conflicts_with("json_pretty")
+                .help(
+                    "Prints JSON output on a single line. This is the default when stdout is not \
+                    a terminal.",
+                ),
+        )
+        .arg(arg!(quiet_success: --quiet_success).help(
+            "Suppresses informational messages reporting success like saved files and the final \
+            \"Done.\" while still logging warnings and errors.",
+        ))
+        .arg(
+            arg!(silent: -s --silent).help(
+                "Makes all user prompts take their default action instead of being displayed.",
+            ),
+        )
+        .subcommand(
+            Command::new("unpack")
+                .arg(arg!(overwrite: -o --overwrite).help(
+                    "Overwrite files instead of stopping when a file exists in the output \
+                        directory.",
+                ))
+                .args(SizeFilter::args())
+                .arg(
+                    arg!(only: --only <PATTERN>)
+                        .value_parser(parse_tag_pattern)
+                        .action(ArgAction::Append)
+                        .help(
+                            "Only unpack segments with tags matching the pattern, may be passed \
+                            multiple times. A pattern is a tag like rkos or a hex string like \
+                            0x726b6f73, a trailing * matches any remaining bytes, e.g. rk* or \
+                            0x726b*.",
+                        ),
+                )
+                .arg(
+                    arg!(offset: --offset <OFFSET>)
+                        .value_parser(parse_offset)
+                        .help(
+                            "The offset of the ftab file in the input file, either decimal or \
+                            hexadecimal with the 0x prefix. Useful when the ftab is embedded into \
+                            another container.",
+                        ),
+                )
+                .arg(arg!(sidecar: --sidecar).help(
+                    "Writes a <FILE>.meta TOML file next to each segment containing its tag, \
+                    unk field, offset and length.",
+                ))
+                .arg(
+                    arg!(extension: --extension <EXT>)
+                        .value_parser(parse_extension)
+                        .conflicts_with("header_manifest")
+                        .help(
+                            "The extension of the segment files instead of bin, with or without \
+                            the leading dot. An empty extension writes files without one.",
+                        ),
+                )
+                .arg(
+                    arg!(segment_per_dir: --segment_per_dir)
+                        .conflicts_with("header_manifest")
+                        .help(
+                            "Writes each segment into its own subdirectory named after the tag as \
+                            <TAG>/data.bin. With --sidecar, the metadata is written to \
+                            <TAG>/meta.toml.",
+                        ),
+                )
+                .arg(
+                    arg!(record_lengths: --record_lengths)
+                        .conflicts_with("header_manifest")
+                        .help(
+                            "Records the length of each segment as expected_len in the manifest, \
+                            so that packing warns when a segment file changes its length.",
+                        ),
+                )
+                .arg(arg!(progress: --progress).help(
+                    "Draws the progress in bytes and segments on stderr when it is a terminal.",
+                ))
+                .arg(
+                    arg!(header_manifest: --header_manifest)
+                        .conflicts_with_all(["emit_rust", "ticket_out", "sidecar", "only"])
+                        .help(
+                            "Only writes a manifest with the header fields and no segments. When the \
+                            file has a ticket, the ticket is written too and the file is read up to \
+                            the end of the ticket, otherwise just the header is read. The segments \
+                            are never parsed or written.",
+                        ),
+                )
+                .arg(
+                    arg!(ticket_out: --ticket_out <PATH>)
+                        .value_parser(paths)
+                        .help(
+                            "Writes the ticket to the specified path instead of the output \
+                            directory or to stdout when the path is -. The manifest refers to the \
+                            ticket by its absolute path or omits it when it is written to stdout.",
+                        ),
+                )
+                .arg(arg!(emit_rust: --emit_rust).help(
+                    "Prints a Rust function building the unpacked file with the ftabutil crate. \
+                    The function reads the unpacked files from a directory passed to it unless \
+                    --inline is passed.",
+                ))
+                .arg(
+                    arg!(inline: --inline)
+                        .requires("emit_rust")
+                        .help("Embeds the contents of the unpacked files into the Rust function."),
+                )
+                .arg(
+                    arg!(require_empty: --require_empty)
+                        .conflicts_with_all(["skip_existing", "bundle"])
+                        .help(
+                            "Fails when the output directory exists and is not empty instead of \
+                            warning about it.",
+                        ),
+                )
+                .arg(arg!(skip_existing: --skip_existing).help(
+                    "Skip writing segments and the ticket when a file of the same size already \
+                        exists in the output directory, e.g. to resume an interrupted unpack.",
+                ))
+                .arg(
+                    arg!(create_parent_dirs: -p --create_parent_dirs).help(
+                        "Create parent directories when the output directory does not exist.",
+                    ),
+                )
+                .arg(
+                    arg!(in_file: <PATH>)
+                        .value_parser(paths)
+                        .help("Path to the ftab file to be unpacked."),
+                )
+                .arg(
+                    arg!(out_dir: [OUT_DIR])
+                        .value_parser(paths)
+                        .help(
+                            "Path to the directory where the unpacked files will be written. The \
+                            default is the current working directory.",
+                        ),
+                )
+                .arg(
+                    arg!(output_dir: -O --output_dir <OUT_DIR>)
+                        .value_parser(paths)
+                        .conflicts_with_all(["out_dir", "bundle"])
+                        .help("Same as the OUT_DIR argument."),
+                )
+                .arg(
+                    arg!(bundle: -b --bundle <BUNDLE_PATH>)
+                        .value_parser(paths)
+                        .conflicts_with("out_dir")
+                        .help(
+                            "Write the manifest and all unpacked files into a single bundle file \
+                            at the specified path instead of a directory.",
+                        ),
+                )
+                .about("Unpacks a ftab file into a directory."),
+        )
+        .subcommand(
+            Command::new("info")
+                .arg(
+                    arg!(count: -c --count)
+                        .help("Prints only the segments count reading just the file header."),
+                )
+                .arg(
+                    arg!(map: -m --map).conflicts_with("count").help(
+                        "Also prints a map of the file listing the ranges of the header, the \
+                        segment list, the segments, the ticket, padding and unreferenced bytes.",
+                    ),
+                )
+                .arg(
+                    arg!(in_file: <PATH>)
+                        .value_parser(paths)
+                        .help("Path to the ftab file to be inspected."),
+                )
+                .about("Prints a summary of a ftab file."),
+        )
+        .subcommand(
+            Command::new("verify")
+                .arg(
+                    arg!(json: --json)
+                        .help("Prints the problems found as a JSON array of objects."),
+                )
+                .arg(arg!(offset_hints: --offset_hints).help(
+                    "For out of bounds segments, reports which base offsets would make the \
+                    segment fit to help identify files using a different offset convention.",
+                ))
+                .arg(
+                    arg!(segment_align_check: --segment_align_check [ALIGNMENT])
+                        .value_parser(parse_alignment)
+                        .require_equals(true)
+                        .help(format!(
+                            "Reports segments whose contents don't start on a boundary of the \
+                            specified number of bytes, e.g. --segment_align_check=16. Defaults to \
+                            {} which matches files produced by pack.",
+                            SEGMENT_ALIGNMENT
+                        )),
+                )
+                .arg(arg!(checksum: --checksum).help(
+                    "Prints the CRC-32 checksum of the header and the segment list, which can be \
+                    used to detect corruption of the file's metadata.",
+                ))
+                .arg(
+                    arg!(expect: --expect <CRC>)
+                        .value_parser(parse_crc32)
+                        .help(
+                            "Reports a problem in case the CRC-32 checksum of the header and the \
+                            segment list differs from the specified hexadecimal value.",
+                        ),
+                )
+                .arg(
+                    arg!(in_file: <PATH>)
+                        .value_parser(paths)
+                        .help("Path to the ftab file to be verified."),
+                )
+                .about("Checks a ftab file for invalid ranges, overlaps and other problems."),
+        )
+        .subcommand(
+            Command::new("convert")
+                .arg(arg!(overwrite: -o --overwrite).help(
+                    "Overwrites the output manifest instead of stopping when the file exists.",
+                ))
+                .arg(
+                    arg!(in_manifest: <IN_MANIFEST>)
+                        .value_parser(paths)
+                        .help("Path to the manifest to be converted."),
+                )
+                .arg(
+                    arg!(out_manifest: <OUT_MANIFEST>)
+                        .value_parser(paths)
+                        .help("Path to the converted manifest."),
+                )
+                .about(
+                    "Converts a manifest between the TOML and JSON formats which are determined \
+                    by the .toml and .json extensions of the paths.",
+                ),
+        )
+        .subcommand(
+            Command::new("batch")
+                .arg(arg!(overwrite: -o --overwrite).help(
+                    "Overwrites existing files when unpacking instead of stopping.",
+                ))
+                .arg(
+                    arg!(out_dir: --out_dir <DIR>)
+                        .value_parser(paths)
+                        .help(
+                            "The directory containing a directory for each unpacked file. The \
+                            directories are named after the files without extensions, prefixed \
+                            with the index of the file in the list and a dash when several files \
+                            share a name. The default is the current directory.",
+                        ),
+                )
+                .arg(
+                    arg!(operation: <OPERATION>)
+                        .value_parser(["info", "verify", "unpack"])
+                        .help("The operation to run for each listed file."),
+                )
+                .arg(
+                    arg!(file_list: <FILE_LIST>)
+                        .value_parser(paths)
+                        .help(
+                            "Path to a text file listing the ftab files, one per line. Empty lines \
+                            and lines starting with # are skipped, relative paths are relative to \
+                            the directory of the list.",
+                        ),
+                )
+                .about(
+                    "Runs info, verify or unpack with the default options for each file from a \
+                    list continuing after failures and fails when any of the files failed.",
+                ),
+        )
+        .subcommand(
+            Command::new("selftest")
+                .hide(true)
+                .arg(
+                    arg!(iterations: -n --iterations <N>)
+                        .value_parser(value_parser!(usize))
+                        .default_value("100")
+                        .help("The number of times the file is parsed and rebuilt."),
+                )
+                .arg(
+                    arg!(in_file: <PATH>)
+                        .value_parser(paths)
+                        .help("Path to the ftab file to be parsed and rebuilt."),
+                )
+                .about(
+                    "Parses and rebuilds a ftab file in memory repeatedly checking that the \
+                    output is stable and reporting the throughput.",
+                ),
+        )
+        .subcommand(
+            Command::new("list")
+                .args(SizeFilter::args())
+                .arg(arg!(segment_data_hash: --segment_data_hash).help(
+                    "Adds a column with the first 4 bytes of the SHA-256 hash of the contents of \
+                    each segment printed as 8 lowercase hex digits. Segments with different \
+                    hashes have different contents.",
+                ))
+                .arg(
+                    arg!(in_file: <PATH>)
+                        .value_parser(paths)
+                        .help("Path to the ftab file to be inspected."),
+                )
+                .about(
+                    "Lists segments of a ftab file printing the index, tag, offset, length and \
+                    unk field of each one.",
+                ),
+        )
+        .subcommand(
+            Command::new("tags")
+                .arg(arg!(null: -z --null).help("Separates tags with NUL characters instead of newlines."))
+                .arg(
+                    arg!(in_file: <PATH>)
+                        .value_parser(paths)
+                        .help("Path to the ftab file to be inspected."),
+                )
+                .about("Prints the tags of all segments of a ftab file, one per line."),
+        )
+        .subcommand(
+            Command::new("stat")
+                .arg(arg!(asc: --asc).help("Sort segments from the smallest to the largest."))
+                .args(SizeFilter::args())
+                .arg(
+                    arg!(bytes: --bytes)
+                        .help("Print sizes in bytes instead of human-readable units."),
+                )
+                .arg(
+                    arg!(in_file: <PATH>)
+                        .value_parser(paths)
+                        .help("Path to the ftab file to be inspected."),
+                )
+                .about("Prints sizes of segments of a ftab file sorted from the largest."),
+        )
+        .subcommand(
+            Command::new("repack")
+                .arg(arg!(overwrite: -o --overwrite).help(
+                    "Overwrites the output file instead of stopping when the file exists at the \
+                    specified path.",
+                ))
+                .arg(arg!(exec: --exec <COMMAND>).required(true).help(
+                    "The shell command run on the unpacked files. The path to the directory \
+                    containing the files and the manifest is appended to its arguments.",
+                ))
+                .arg(
+                    arg!(in_file: <PATH>)
+                        .value_parser(paths)
+                        .help("Path to the ftab file to be repacked."),
+                )
+                .arg(
+                    arg!(out_file: [OUT_FILE])
+                        .value_parser(paths)
+                        .help("Path to the output file. The default is to rewrite the input file."),
+                )
+                .about(
+                    "Unpacks a ftab file into a temporary directory, runs a command on it and \
+                    packs the directory back.",
+                ),
+        )
+        .subcommand(
+            Command::new("strip_ticket")
+                .arg(arg!(overwrite: -o --overwrite).help(
+                    "Overwrites the output file instead of stopping when the file exists at the \
+                    specified path.",
+                ))
+                .arg(
+                    arg!(in_file: <PATH>)
+                        .value_parser(paths)
+                        .help("Path to the ftab file to remove the ticket from."),
+                )
+                .arg(in_place_arg())
+                .arg(out_file_arg(paths))
+                .about("Rebuilds a ftab file without the ticket keeping all segments intact."),
+        )
+        .subcommand(
+            Command::new("validate_manifest")
+                .about(
+                    "Checks that a manifest can be used to build a 'ftab' file without building \
+                    it. Every problem found is reported.",
+                )
+                .arg(
+                    arg!(manifest_path: <MANIFEST_PATH>)
+                        .value_parser(paths)
+                        .help("Path to the manifest file or a bundle containing one."),
+                )
+                .arg(arg!(follow_symlinks: --follow_symlinks).help(
+                    "Follows symbolic links in paths of files referenced by the manifest. By \
+                    default files are reported when any component of their path from the \
+                    manifest is a symbolic link.",
+                ))
+                .arg(arg!(sandbox: --sandbox).help(
+                    "Reports paths in the manifest that are absolute or contain '..' components \
+                    as they may point outside the manifest's directory.",
+                ))
+                .arg(
+                    arg!(strict_manifest: --strict_manifest)
+                        .visible_alias("strict")
+                        .help(
+                            "Fails when the manifest contains keys that don't correspond to any \
+                            field like pack does with --strict_manifest. By default these are \
+                            ignored the same way pack ignores them.",
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("extract")
+                .arg(arg!(overwrite: -o --overwrite).help(
+                    "Overwrites the output file instead of stopping when the file exists at the \
+                    specified path.",
+                ))
+                .arg(
+                    arg!(offset: --offset <OFFSET>)
+                        .value_parser(parse_offset)
+                        .help(
+                            "The offset of the ftab file in the input file, either decimal or \
+                            hexadecimal with the 0x prefix. The default is zero.",
+                        ),
+                )
+                .arg(
+                    arg!(in_file: <PATH>)
+                        .value_parser(paths)
+                        .help("Path to the ftab file or a file containing one."),
+                )
+                .arg(arg!(tag: <TAG>).value_parser(parse_tag).help(
+                    "The tag of the segment to extract, either up to 4 characters or a 0x-prefixed \
+                    hex string.",
+                ))
+                .arg(
+                    arg!(out_file: [OUT_FILE])
+                        .value_parser(paths)
+                        .help(
+                            "Path to the output file or - for the standard output. The default is \
+                            the file name unpack would use in the current directory.",
+                        ),
+                )
+                .about("Extracts the contents of a single segment from a ftab file."),
+        )
+        .subcommand(
+            Command::new("normalize")
+                .arg(arg!(overwrite: -o --overwrite).help(
+                    "Overwrites the output file instead of stopping when the file exists at the \
+                    specified path.",
+                ))
+                .arg(
+                    arg!(in_file: <PATH>)
+                        .value_parser(paths)
+                        .help("Path to the ftab file to normalize."),
+                )
+                .arg(in_place_arg())
+                .arg(out_file_arg(paths))
+                .about(
+                    "Rebuilds a ftab file with a canonical layout: segments are stored in the \
+                    segment list order without gaps, padded with zeroes to a 4-byte boundary, the \
+                    ticket follows the last segment and trailing bytes are dropped. Header fields, \
+                    tags, unknown fields, segment contents and the ticket are kept intact.",
+                ),
+        )
+        .subcommand(
+            Command::new("set_ticket")
+                .arg(arg!(overwrite: -o --overwrite).help(
+                    "Overwrites the output file instead of stopping when the file exists at the \
+                    specified path.",
+                ))
+                .arg(
+                    arg!(in_file: <PATH>)
+                        .value_parser(paths)
+                        .help("Path to the ftab file to set the ticket of."),
+                )
+                .arg(
+                    arg!(ticket: <TICKET_PATH>)
+                        .value_parser(paths)
+                        .help("Path to the DER-encoded ticket to be included."),
+                )
+                .arg(in_place_arg())
+                .arg(out_file_arg(paths))
+                .about("Rebuilds a ftab file adding or replacing the ticket."),
+        )
+        .subcommand(
+            Command::new("pack")
+                .arg(arg!(overwrite: -o --overwrite).help(
+                    "Overwrites the output file instead of stopping when the file exists at the \
+                    specified path.",
+                ))
+                .arg(
+                    arg!(set_unk: --set_unk <ASSIGNMENT>)
+                        .value_parser(parse_unk_override)
+                        .action(ArgAction::Append)
+                        .help(
+                            "Overrides a header field from the manifest as N=VALUE where N is the \
+                            index of an unk_N field from 0 to 6 and VALUE is either decimal or \
+                            hexadecimal with the 0x prefix, may be passed multiple times. Values \
+                            from the command line win over the ones from the manifest.",
+                        ),
+                )
+                .arg(arg!(checksum_manifest: --checksum_manifest).help(
+                    "Writes the SHA-256 digest of the output file to a file with the .sha256 \
+                    extension appended next to it in the format of sha256sum.",
+                ))
+                .arg(
+                    arg!(deterministic: --deterministic)
+                        .conflicts_with("set_unk")
+                        .help(
+                            "Replaces the padding bytes from the manifest with zero padding to a \
+                            4-byte boundary and fails when a segment file is not as long as the \
+                            expected_len field of its segment. Can't be combined with --set_unk, \
+                            so all unk fields come from the manifest.",
+                        ),
+                )
+                .arg(arg!(canonical_order: --canonical_order).help(
+                    "Places the segments in the order rkos, rrko, rkrn instead of the order of \
+                    the manifest. Segments with other tags follow in the manifest's order and an \
+                    alias is never placed before the segment it refers to.",
+                ))
+                .arg(arg!(exclude_ticket: --exclude_ticket).help(
+                    "Builds the file without a ticket even if the manifest references one. The \
+                    ticket file is not read.",
+                ))
+                .arg(arg!(pad_ticket: --pad_ticket).help(
+                    "Pads the ticket with zeroes so that it starts on a 4-byte boundary. By \
+                    default the ticket immediately follows the last segment.",
+                ))
+                .arg(
+                    arg!(ticket_before_data: --ticket_before_data)
+                        .conflicts_with("pad_ticket")
+                        .help(
+                            "Places the ticket right after the segment list instead of after the \
+                            contents of the segments.",
+                        ),
+                )
+                .arg(arg!(verify_after_write: --verify_after_write).help(
+                    "Reads the output file back after writing it and checks that it parses \
+                    correctly.",
+                ))
+                .arg(
+                    arg!(remove_invalid: --remove_invalid)
+                        .requires("verify_after_write")
+                        .help("Removes the output file when verification after writing fails."),
+                )
+                .arg(arg!(follow_symlinks: --follow_symlinks).help(
+                    "Follows symbolic links in paths of files referenced by the manifest. By \
+                    default files are not read when any component of their path from the \
+                    manifest is a symbolic link.",
+                ))
+                .arg(
+                    arg!(output_template: --output_template <TEMPLATE>).help(
+                            "The name of the output file used when no output path is specified. \
+                            {label} is replaced with the label field of the manifest, {segments} \
+                            with the segments count and {stem} with the manifest file name without \
+                            the extension.",
+                    ),
+                )
+                .arg(arg!(atomic: --atomic).help(
+                    "Writes the output file into a temporary file in the same directory first and \
+                    then renames it over the output path, so an interrupted write never leaves a \
+                    partially written file.",
+                ))
+                .arg(arg!(strict_manifest: --strict_manifest).help(
+                    "Fails when the manifest contains keys that don't correspond to any field, \
+                    e.g. misspelled optional fields which are ignored by default.",
+                ))
+                .arg(arg!(print_path: --print_path).help(
+                    "Prints the path of the written file to stdout on success, one line per file \
+                    for a manifest with multiple outputs. Log messages are written to stderr, so \
+                    stdout only contains the paths.",
+                ))
+                .arg(
+                    arg!(compare_to: --compare_to <REFERENCE_PATH>)
+                        .value_parser(paths)
+                        .conflicts_with_all(["out_file", "verify_after_write", "print_path"])
+                        .help(
+                            "Builds the ftab file in memory and compares it with the reference file \
+                            reporting the first differing offset instead of writing the output. Exits with \
+                            status 1 when the files differ.",
+                        ),
+                )
+                .arg(
+                    arg!(manifest: <MANIFEST_PATH>)
+                        .value_parser(paths)
+                        .help("Path to the manifest or a bundle describing the desired ftab file."),
+                )
+                .arg(
+                    arg!(segments_from: --segments_from)
+                        .conflicts_with("strict_manifest")
+                        .help(
+                            "Reads MANIFEST_PATH as a list of segments with a TAG=PATH line per \
+                            segment instead of a manifest. Empty lines and lines starting with # \
+                            are skipped, the header fields are zero unless overridden with \
+                            --set_unk and no ticket is included.",
+                        ),
+                )
+                .arg(
+                    arg!(out_file: [OUT_PATH])
+                        .value_parser(paths)
+                        .help(
+                            "Destination path where the created ftab file should be written. For \
+                            a manifest with multiple outputs, the directory where the outputs \
+                            are written, the default is the manifest's directory.",
+                        ),
+                )
+                .about("Creates a ftab file from a manifest."),
+        )
+}
+
+fn main() {
+    let matches = build_cli(false).get_matches();
+    // Whether paths are expanded is only known after parsing, so the arguments are parsed again
+    // with expansion enabled when it was requested.
+    let matches = if matches.get_flag("expand_paths") {
+        build_cli(true).get_matches()
+    } else {
+        matches
+    };
+
+    // The command line option takes priority over the environment variable.
+    let log_level = match matches.get_one::<String>("log_level") {
+        Some(log_level) => parse_log_level(log_level),
+        None => env::var(LOG_LEVEL_ENV_VAR)
+            .map(|log_level| parse_log_level(&log_level))
+            .unwrap_or(LevelFilter::Warn),
+    };
+    let print_header = matches.get_flag("print_header");
+    let silent = matches.get_flag("silent");
+    let json_pretty = matches.get_flag("json_pretty")
+        || (!matches.get_flag("json_compact") && io::stdout().is_terminal());
+    let input = InputOptions {
+        format: match matches.get_one::<String>("input_format").unwrap().as_str() {
+            "raw" => InputFormat::Raw,
+            "gzip" => InputFormat::Gzip,
+            _ => InputFormat::Auto,
+        },
+        // the flag without a value applies the default limit
+        max_segments: match matches.get_one::<usize>("max_segments") {
+            Some(&max_segments) => max_segments,
+            None if matches.contains_id("max_segments") => DEFAULT_MAX_SEGMENTS,
+            None => usize::MAX,
+        },
+        skip_empty: matches.get_flag("skip_empty_tags"),
+    };
+
+    let mut logger = SimpleLogger::new().with_level(log_level);
+    if matches.get_flag("quiet_success") {
+        logger = logger.with_module_level(SUCCESS_LOG_TARGET, LevelFilter::Off);
+    }
+    logger.init().unwrap();
+
+    let succeeded = match matches.subcommand() {
+        Some(("unpack", sub_matches)) => {
+            let in_file: PathBuf = sub_matches.get_one::<PathBuf>("in_file").unwrap().clone();
+            let out_dir: Option<PathBuf> = sub_matches
+                .get_one::<PathBuf>("out_dir")
+                .or_else(|| sub_matches.get_one::<PathBuf>("output_dir"))
+                .cloned();
+            let bundle_path = sub_matches.get_one::<PathBuf>("bundle");
+            let options = UnpackOptions {
+                overwrite: sub_matches.get_flag("overwrite"),
+                create_parent_dirs: sub_matches.get_flag("create_parent_dirs"),
+                print_header,
+                silent,
+                skip_existing: sub_matches.get_flag("skip_existing"),
+                input,
+                size_filter: SizeFilter::from_matches(sub_matches),
+                tag_filter: sub_matches
+                    .get_many::<TagPattern>("only")
+                    .map(|patterns| patterns.cloned().collect())
+                    .unwrap_or_default(),
+                sidecar: sub_matches.get_flag("sidecar"),
+                offset: sub_matches.get_one::<usize>("offset").copied().unwrap_or(0),
+                require_empty: sub_matches.get_flag("require_empty"),
+                emit_rust: sub_matches.get_flag("emit_rust"),
+                inline: sub_matches.get_flag("inline"),
+                ticket_out: sub_matches.get_one::<PathBuf>("ticket_out").cloned(),
+                header_manifest: sub_matches.get_flag("header_manifest"),
+                show_progress: sub_matches.get_flag("progress"),
+                extension: sub_matches.get_one::<String>("extension").cloned(),
+                segment_per_dir: sub_matches.get_flag("segment_per_dir"),
+                record_lengths: sub_matches.get_flag("record_lengths"),
+            };
+
+            report(
+                do_unpack(
+                    &in_file,
+                    out_dir.as_deref(),
+                    bundle_path.map(PathBuf::as_path),
+                    options,
+                )
+                .map(|summary| summary.log()),
+            )
+        }
+        Some(("pack", sub_matches)) => {
+            let manifest_path = sub_matches.get_one::<PathBuf>("manifest").unwrap();
+            let out_file = sub_matches
+                .get_one::<PathBuf>("out_file")
+                .map(PathBuf::as_path);
+            let options = PackOptions {
+                overwrite: sub_matches.get_flag("overwrite"),
+                silent,
+                verify_after_write: sub_matches.get_flag("verify_after_write"),
+                remove_invalid: sub_matches.get_flag("remove_invalid"),
+                pad_ticket: sub_matches.get_flag("pad_ticket"),
+                ticket_before_data: sub_matches.get_flag("ticket_before_data"),
+                symlink_policy: if sub_matches.get_flag("follow_symlinks") {
+                    SymlinkPolicy::Follow
+                } else {
+                    SymlinkPolicy::Reject
+                },
+                strict_manifest: sub_matches.get_flag("strict_manifest"),
+                output_template: sub_matches.get_one::<String>("output_template").cloned(),
+                atomic: sub_matches.get_flag("atomic"),
+                exclude_ticket: sub_matches.get_flag("exclude_ticket"),
+                checksum_manifest: sub_matches.get_flag("checksum_manifest"),
+                unk_overrides: sub_matches
+                    .get_many::<(usize, u32)>("set_unk")
+                    .map(|overrides| overrides.copied().collect())
+                    .unwrap_or_default(),
+                segments_from: sub_matches.get_flag("segments_from"),
+                deterministic: sub_matches.get_flag("deterministic"),
+                canonical_order: sub_matches.get_flag("canonical_order"),
+            };
+
+            let compare_to = sub_matches
+                .get_one::<PathBuf>("compare_to")
+                .map(PathBuf::as_path);
+
+            let print_path = sub_matches.get_flag("print_path");
+
+            report(
+                do_pack(manifest_path, out_file, compare_to, options).map(|summaries| {
+                    for summary in summaries.iter() {
+                        summary.log();
+                        if let Some(out_path) = summary.out_path.as_ref().filter(|_| print_path) {
+                            println!("{}", out_path.display());
+                        }
+                    }
+                }),
+            )
+        }
+        Some(("info", sub_matches)) => {
+            let in_file = sub_matches.get_one::<PathBuf>("in_file").unwrap();
+            let count_only = sub_matches.get_flag("count");
+            let map = sub_matches.get_flag("map");
+
+            report(do_info(in_file, count_only, map, input))
+        }
+        Some(("verify", sub_matches)) => {
+            let in_file = sub_matches.get_one::<PathBuf>("in_file").unwrap();
+
+            let hints = sub_matches.get_flag("offset_hints");
+            let checksum = sub_matches.get_flag("checksum");
+            let expected_checksum = sub_matches.get_one::<u32>("expect").copied();
+            let alignment = sub_matches.contains_id("segment_align_check").then(|| {
+                sub_matches
+                    .get_one::<usize>("segment_align_check")
+                    .copied()
+                    .unwrap_or(SEGMENT_ALIGNMENT)
+            });
+
+            let format = match (sub_matches.get_flag("json"), json_pretty) {
+                (false, _) => OutputFormat::Text,
+                (true, false) => OutputFormat::Json,
+                (true, true) => OutputFormat::PrettyJson,
+            };
+
+            report(do_verify(
+                in_file,
+                format,
+                hints,
+                checksum,
+                expected_checksum,
+                alignment,
+                input,
+            ))
+        }
+        Some(("stat", sub_matches)) => {
+            let in_file = sub_matches.get_one::<PathBuf>("in_file").unwrap();
+            let ascending = sub_matches.get_flag("asc");
+            let raw_bytes = sub_matches.get_flag("bytes");
+            let size_filter = SizeFilter::from_matches(sub_matches);
+
+            report(do_stat(in_file, ascending, raw_bytes, size_filter, input))
+        }
+        Some(("tags", sub_matches)) => {
+            let in_file = sub_matches.get_one::<PathBuf>("in_file").unwrap();
+            let null = sub_matches.get_flag("null");
+
+            report(do_tags(in_file, null, input))
+        }
+        Some(("convert", sub_matches)) => {
+            let in_path = sub_matches.get_one::<PathBuf>("in_manifest").unwrap();
+            let out_path = sub_matches.get_one::<PathBuf>("out_manifest").unwrap();
+            let overwrite = sub_matches.get_flag("overwrite");
+
+            report(do_convert(in_path, out_path, overwrite, silent))
+        }
+        Some(("batch", sub_matches)) => {
+            let file_list = sub_matches.get_one::<PathBuf>("file_list").unwrap();
+            let operation = match sub_matches.get_one::<String>("operation").unwrap().as_str() {
+                "info" => BatchOperation::Info,
+                "verify" => BatchOperation::Verify,
+                "unpack" => BatchOperation::Unpack,
+                _ => unreachable!(),
+            };
+            let out_dir = sub_matches
+                .get_one::<PathBuf>("out_dir")
+                .map(PathBuf::as_path);
+            let overwrite = sub_matches.get_flag("overwrite");
+
+            report(do_batch(
+                file_list, operation, out_dir, overwrite, silent, input,
+            ))
+        }
+        Some(("selftest", sub_matches)) => {
+            let in_file = sub_matches.get_one::<PathBuf>("in_file").unwrap();
+            let iterations = *sub_matches.get_one::<usize>("iterations").unwrap();
+
+            report(do_selftest(in_file, iterations, input))
+        }
+        Some(("list", sub_matches)) => {
+            let in_file = sub_matches.get_one::<PathBuf>("in_file").unwrap();
+            let size_filter = SizeFilter::from_matches(sub_matches);
+            let data_hash = sub_matches.get_flag("segment_data_hash");
+
+            report(do_list(in_file, size_filter, data_hash, input))
+        }
+        Some(("repack", sub_matches)) => {
+            let in_file = sub_matches.get_one::<PathBuf>("in_file").unwrap();
+            let command = sub_matches.get_one::<String>("exec").unwrap();
+            let out_file = sub_matches
+                .get_one::<PathBuf>("out_file")
+                .map(PathBuf::as_path);
+            let overwrite = sub_matches.get_flag("overwrite");
+
+            // Relative output paths are resolved against the manifest's directory, so make the
+            // path absolute to keep it relative to the current directory. The output is written
+            // over the input file when no output path is specified.
+            let out_path = out_file.unwrap_or(in_file);
+            let out_path = env::current_dir()
+                .map(|dir| dir.join(out_path))
+                .unwrap_or_else(|_| out_path.to_path_buf());
+
+            // The temporary directory is removed when the work directory is dropped, whether
+            // the operation succeeds or not.
+            match RepackWorkDir::create() {
+                Ok(work_dir) => report(do_repack(
+                    &work_dir, in_file, command, &out_path, overwrite, silent, input,
+                )),
+                Err(e) => report(Err(e)),
+            }
+        }
+        Some(("validate_manifest", sub_matches)) => {
+            let manifest_path = sub_matches.get_one::<PathBuf>("manifest_path").unwrap();
+            let symlink_policy = if sub_matches.get_flag("follow_symlinks") {
+                SymlinkPolicy::Follow
+            } else {
+                SymlinkPolicy::Reject
+            };
+            let sandbox = sub_matches.get_flag("sandbox");
+
+            let strict_manifest = sub_matches.get_flag("strict_manifest");
+
+            report(do_validate_manifest(
+                manifest_path,
+                symlink_policy,
+                sandbox,
+                strict_manifest,
+            ))
+        }
+        Some(("extract", sub_matches)) => {
+            let in_file = sub_matches.get_one::<PathBuf>("in_file").unwrap();
+            let offset = sub_matches.get_one::<usize>("offset").copied().unwrap_or(0);
+            let tag = *sub_matches.get_one::<Tag>("tag").unwrap();
+            let out_file = sub_matches
+                .get_one::<PathBuf>("out_file")
+                .map(PathBuf::as_path);
+            let overwrite = sub_matches.get_flag("overwrite");
+
+            report(do_extract(
+                in_file, offset, tag, out_file, overwrite, silent, input,
+            ))
+        }
+        Some(("normalize", sub_matches)) => {
+            let in_file = sub_matches.get_one::<PathBuf>("in_file").unwrap();
+            let (out_file, overwrite) = rewrite_out_path(sub_matches, in_file);
+
+            report(do_normalize(in_file, out_file, overwrite, silent, input))
+        }
+        Some((name @ ("strip_ticket" | "set_ticket"), sub_matches)) => {
+            let in_file = sub_matches.get_one::<PathBuf>("in_file").unwrap();
+            let ticket = if name == "set_ticket" {
+                sub_matches
+                    .get_one::<PathBuf>("ticket")
+                    .map(PathBuf::as_path)
+            } else {
+                None
+            };
+            let (out_file, overwrite) = rewrite_out_path(sub_matches, in_file);
+
+            report(do_rewrite_ticket(
+                in_file, ticket, out_file, overwrite, silent, input,
+            ))
         }
         Some(_) | None => unreachable!(),
+    };
+
+    if !succeeded {
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ftabutil::manifest::OutputDesc;
+
+    /// Creates an empty directory for a test under the system's temporary directory.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("ftabutil-test-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    /// Returns a 'ftab' file consisting of just a header declaring no segments and no ticket.
+    fn empty_ftab() -> Vec<u8> {
+        let mut bytes = vec![0; HEADER_LEN];
+        bytes[32..40].copy_from_slice(b"rkosftab");
+
+        bytes
+    }
+
+    #[test]
+    fn paths_expanded_only_when_requested() {
+        let in_file = |expand_paths: bool| {
+            let matches = build_cli(expand_paths)
+                .try_get_matches_from(["ftabutil", "info", "${PATH}/ftab.bin"])
+                .unwrap();
+            let (_, sub_matches) = matches.subcommand().unwrap();
+            sub_matches.get_one::<PathBuf>("in_file").unwrap().clone()
+        };
+
+        assert_eq!(in_file(false), Path::new("${PATH}/ftab.bin"));
+        assert_eq!(
+            in_file(true),
+            Path::new(&env::var("PATH").unwrap()).join("ftab.bin")
+        );
+    }
+
+    #[test]
+    fn repack_work_dirs_are_unique() {
+        let first = RepackWorkDir::create().unwrap();
+        let second = RepackWorkDir::create().unwrap();
+        assert_ne!(first.path, second.path);
+        assert!(first.path.is_dir() && second.path.is_dir());
+
+        let path = first.path.clone();
+        drop(first);
+        assert!(!path.exists());
+        assert!(second.path.is_dir());
+    }
+
+    #[test]
+    fn zero_segments() {
+        let dir = test_dir("zero-segments");
+        let in_file = dir.join("empty.bin");
+        fs::write(&in_file, empty_ftab()).unwrap();
+        let input = InputOptions::default();
+
+        assert!(do_info(&in_file, false, true, input).is_ok());
+        assert!(do_list(&in_file, SizeFilter::default(), false, input).is_ok());
+        let verified = do_verify(
+            &in_file,
+            OutputFormat::Text,
+            false,
+            false,
+            None,
+            None,
+            input,
+        );
+        assert!(verified.is_ok());
+
+        let out_dir = dir.join("out");
+        let summary = do_unpack(&in_file, Some(&out_dir), None, UnpackOptions::default()).unwrap();
+        assert_eq!(summary.segments_written, 0);
+
+        let manifest_path = out_dir.join(MANIFEST_NAME);
+        let manifest = Manifest::from_toml_strict(&fs::read(&manifest_path).unwrap()).unwrap();
+        assert!(manifest.segments.is_empty());
+        assert_eq!(manifest.ticket, None);
+
+        let out_file = dir.join("packed.bin");
+        do_pack(
+            &manifest_path,
+            Some(&out_file),
+            None,
+            PackOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(fs::read(&out_file).unwrap(), empty_ftab());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn json_manifest_field_order() {
+        let manifest = Manifest {
+            label: Some("dev".to_owned()),
+            ticket: Some(PathBuf::from(TICKET_FILENAME)),
+            segments: vec![SegmentDesc {
+                path: PathBuf::from("rkos.bin"),
+                tag: Tag(*b"rkos"),
+                unk: 0,
+                padding: Some(vec![0xff]),
+                expected_len: Some(4),
+                alias_of: None,
+            }],
+            outputs: vec![OutputDesc {
+                name: PathBuf::from("ftab.bin"),
+                segments: vec![Tag(*b"rkos")],
+            }],
+            ..Default::default()
+        };
+        let value = manifest_to_json(&manifest);
+
+        let Value::Object(fields) = &value else {
+            panic!("expected an object, got {:?}", value);
+        };
+        let keys: Vec<_> = fields.iter().map(|(key, _)| key.as_ref()).collect();
+        assert_eq!(keys, MANIFEST_FIELDS);
+
+        let json = value.to_string();
+        assert!(
+            json.contains(r#""segments":[{"path":"rkos.bin","tag":"rkos","unk":0,"padding":"ff","expected_len":4}]"#),
+            "{}",
+            json
+        );
+        assert!(
+            json.contains(r#""outputs":[{"name":"ftab.bin","segments":["rkos"]}]"#),
+            "{}",
+            json
+        );
+
+        let parsed = parse_json_manifest(json.as_bytes(), true).unwrap();
+        assert_eq!(parsed.to_toml(), manifest.to_toml());
+
+        let misspelled = json.replace("\"unk_0\"", "\"unk_x\"");
+        assert!(parse_json_manifest(misspelled.as_bytes(), false).is_err());
+        let misspelled = json.replace("\"padding\"", "\"paddng\"");
+        assert!(parse_json_manifest(misspelled.as_bytes(), false).is_ok());
+        assert!(parse_json_manifest(misspelled.as_bytes(), true).is_err());
+    }
+
+    #[test]
+    fn url_inputs_are_rejected() {
+        let input = InputOptions::default();
+        for url in [
+            "http://example.com/ftab.bin",
+            "https://example.com/ftab.bin",
+        ] {
+            let error = input.read(Path::new(url)).unwrap_err();
+            assert_eq!(error.error.kind(), IoErrorKind::Unsupported);
+            let error = input.read_prefix(Path::new(url), 4).unwrap_err();
+            assert_eq!(error.error.kind(), IoErrorKind::Unsupported);
+        }
+
+        // a local path that merely contains a URL is still read as a file
+        let error = input
+            .read(Path::new("missing/http://ftab.bin"))
+            .unwrap_err();
+        assert_eq!(error.error.kind(), IoErrorKind::NotFound);
+    }
+
+    #[test]
+    fn validate_json_manifest() {
+        let dir = test_dir("validate-json-manifest");
+        fs::write(dir.join("rkos.bin"), b"rkos").unwrap();
+        let manifest_path = dir.join("manifest.json");
+        fs::write(
+            &manifest_path,
+            r#"{"unk_0":0,"unk_1":0,"unk_2":0,"unk_3":0,"unk_4":0,"unk_5":0,"unk_6":0,
+            "segments":[{"path":"rkos.bin","tag":"rkos","paddng":"ff"}]}"#,
+        )
+        .unwrap();
+
+        // unknown keys are only rejected in the strict mode, the same way pack rejects them
+        do_validate_manifest(&manifest_path, SymlinkPolicy::Reject, false, false).unwrap();
+        let error =
+            do_validate_manifest(&manifest_path, SymlinkPolicy::Reject, false, true).unwrap_err();
+        assert!(matches!(error, ValidateError::JsonManifestParseError(..)));
+
+        // the same manifest in TOML isn't parsed as JSON
+        let toml_path = dir.join("manifest.toml");
+        fs::write(
+            &toml_path,
+            "unk_0 = 0\nunk_1 = 0\nunk_2 = 0\nunk_3 = 0\nunk_4 = 0\nunk_5 = 0\nunk_6 = 0\n\
+            [[segments]]\npath = \"rkos.bin\"\ntag = \"rkos\"\npaddng = \"ff\"\n",
+        )
+        .unwrap();
+        do_validate_manifest(&toml_path, SymlinkPolicy::Reject, false, false).unwrap();
+        let error =
+            do_validate_manifest(&toml_path, SymlinkPolicy::Reject, false, true).unwrap_err();
+        assert!(matches!(error, ValidateError::ManifestParseError(..)));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn pack_streams_segments_into_output() {
+        let dir = test_dir("pack-streams-segments");
+        fs::write(dir.join("rkos.bin"), b"abc").unwrap();
+        fs::write(dir.join("rkrn.bin"), b"defgh").unwrap();
+        fs::write(dir.join("ticket.der"), b"TICKET").unwrap();
+        let manifest_path = dir.join(MANIFEST_NAME);
+        fs::write(
+            &manifest_path,
+            "unk_0 = 0\nunk_1 = 0\nunk_2 = 0\nunk_3 = 0\nunk_4 = 0\nunk_5 = 0\nunk_6 = 0\n\
+            ticket = \"ticket.der\"\n\
+            [[segments]]\npath = \"rkos.bin\"\ntag = \"rkos\"\n\
+            [[segments]]\npath = \"rkrn.bin\"\ntag = \"rkrn\"\n",
+        )
+        .unwrap();
+
+        let manifest = Manifest::from_toml_strict(&fs::read(&manifest_path).unwrap()).unwrap();
+        let expected = Builder::with_manifest(&manifest, Some(&dir))
+            .unwrap()
+            .to_vec();
+
+        for atomic in [false, true] {
+            let out_path = dir.join(format!("atomic-{}.bin", atomic));
+            let options = PackOptions {
+                atomic,
+                checksum_manifest: true,
+                ..Default::default()
+            };
+            let summaries = do_pack(&manifest_path, Some(&out_path), None, options).unwrap();
+            assert_eq!(summaries[0].bytes, expected.len() as u64);
+            assert_eq!(fs::read(&out_path).unwrap(), expected);
+
+            // the digest is computed from the file after the header is written
+            let mut checksum_path = out_path.clone().into_os_string();
+            checksum_path.push(".sha256");
+            let checksum = fs::read_to_string(checksum_path).unwrap();
+            assert!(checksum.starts_with(&hex::encode(sha256(&expected))));
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn deterministic_pack() {
+        let dir = test_dir("deterministic-pack");
+        fs::write(dir.join("rkos.bin"), b"abc").unwrap();
+        fs::write(dir.join("rkrn.bin"), b"defgh").unwrap();
+        let header =
+            "unk_0 = 1\nunk_1 = 0\nunk_2 = 0\nunk_3 = 0\nunk_4 = 0\nunk_5 = 0\nunk_6 = 0\n";
+
+        // the same segments with recorded padding bytes and spelled out unk fields, and without
+        let recorded = dir.join("recorded.toml");
+        fs::write(
+            &recorded,
+            format!(
+                "{}[[segments]]\npath = \"rkos.bin\"\ntag = \"rkos\"\nunk = 0x0\n\
+                padding = \"ffeedd\"\nexpected_len = 3\n\
+                [[segments]]\npath = \"rkrn.bin\"\ntag = \"rkrn\"\nunk = 0\n\
+                padding = \"0102\"\n",
+                header
+            ),
+        )
+        .unwrap();
+        let plain = dir.join("plain.toml");
+        fs::write(
+            &plain,
+            format!(
+                "{}[[segments]]\npath = \"rkos.bin\"\ntag = \"rkos\"\n\
+                [[segments]]\npath = \"rkrn.bin\"\ntag = \"rkrn\"\n",
+                header
+            ),
+        )
+        .unwrap();
+
+        fn pack<'a>(
+            manifest_path: &'a Path,
+            out_path: &'a Path,
+            deterministic: bool,
+        ) -> Result<Vec<u8>, PackError<'a>> {
+            let options = PackOptions {
+                overwrite: true,
+                deterministic,
+                ..Default::default()
+            };
+            do_pack(manifest_path, Some(out_path), None, options)?;
+
+            Ok(fs::read(out_path).unwrap())
+        }
+        let out_path = dir.join("out.bin");
+        let pack = |manifest_path, deterministic| pack(manifest_path, &out_path, deterministic);
+
+        let plain_bytes = pack(&plain, true).unwrap();
+        assert_eq!(pack(&recorded, true).unwrap(), plain_bytes);
+        assert_eq!(pack(&plain, false).unwrap(), plain_bytes);
+        assert_ne!(pack(&recorded, false).unwrap(), plain_bytes);
+
+        // a segment file of an unexpected length is only reported by default
+        fs::write(dir.join("rkos.bin"), b"abcd").unwrap();
+        pack(&recorded, false).unwrap();
+        let error = pack(&recorded, true).unwrap_err();
+        assert!(matches!(
+            error,
+            PackError::SegmentLengthMismatch(_, Tag(tag), 4, 3) if tag == *b"rkos"
+        ));
+
+        // unk fields can only come from the manifest
+        let matches = build_cli(false).try_get_matches_from([
+            "ftabutil",
+            "pack",
+            "--deterministic",
+            "--set_unk",
+            "0=2",
+            "manifest.toml",
+        ]);
+        assert!(matches.is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn canonical_order_pack() {
+        let dir = test_dir("canonical-order-pack");
+        for name in ["zzzz", "rkrn", "rkos", "rrko"] {
+            fs::write(dir.join(format!("{}.bin", name)), name).unwrap();
+        }
+
+        // a shuffled manifest with a segment of an unknown tag and an alias of the rkrn segment
+        let manifest_path = dir.join("manifest.toml");
+        fs::write(
+            &manifest_path,
+            "unk_0 = 0\nunk_1 = 0\nunk_2 = 0\nunk_3 = 0\nunk_4 = 0\nunk_5 = 0\nunk_6 = 0\n\
+            [[segments]]\npath = \"zzzz.bin\"\ntag = \"zzzz\"\n\
+            [[segments]]\npath = \"rkrn.bin\"\ntag = \"rkrn\"\n\
+            [[segments]]\npath = \"rkrn.bin\"\ntag = \"rkos\"\nalias_of = \"rkrn\"\n\
+            [[segments]]\npath = \"rrko.bin\"\ntag = \"rrko\"\n",
+        )
+        .unwrap();
+        let out_path = dir.join("out.bin");
+
+        let pack = |canonical_order| {
+            let options = PackOptions {
+                overwrite: true,
+                canonical_order,
+                ..Default::default()
+            };
+            do_pack(&manifest_path, Some(&out_path), None, options).unwrap();
+
+            let data = fs::read(&out_path).unwrap();
+            let parser = Parser::parse(&data).unwrap();
+            let mut segments_parser = parser.segments();
+            let mut segments = Vec::new();
+            while let Some(segment) = segments_parser.next_segment().unwrap() {
+                segments.push((Tag(segment.tag).to_string(), segment.data.to_vec()));
+            }
+
+            segments
+        };
+        let tags = |segments: &[(String, Vec<u8>)]| {
+            segments
+                .iter()
+                .map(|(tag, _)| tag.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(tags(&pack(false)), ["zzzz", "rkrn", "rkos", "rrko"]);
+        let sorted = pack(true);
+        assert_eq!(tags(&sorted), ["rrko", "rkrn", "rkos", "zzzz"]);
+        assert_eq!(sorted[2].1, b"rkrn");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn header_manifest_writes_ticket() {
+        let dir = test_dir("header-manifest");
+        let in_file = dir.join("ftab.bin");
+        let mut bytes = empty_ftab();
+        bytes[16..20].copy_from_slice(&(HEADER_LEN as u32).to_le_bytes());
+        bytes[20..24].copy_from_slice(&6u32.to_le_bytes());
+        bytes.extend_from_slice(b"TICKET");
+        fs::write(&in_file, &bytes).unwrap();
+
+        let out_dir = dir.join("out");
+        let options = UnpackOptions {
+            header_manifest: true,
+            ..Default::default()
+        };
+        do_unpack(&in_file, Some(&out_dir), None, options.clone()).unwrap();
+
+        let manifest = fs::read(out_dir.join(MANIFEST_NAME)).unwrap();
+        let manifest = Manifest::from_toml_strict(&manifest).unwrap();
+        assert_eq!(manifest.ticket.as_deref(), Some(Path::new(TICKET_FILENAME)));
+        assert!(manifest.segments.is_empty());
+        assert_eq!(fs::read(out_dir.join(TICKET_FILENAME)).unwrap(), b"TICKET");
+
+        // a ticket past the end of the file is reported instead of being referenced
+        fs::write(&in_file, &bytes[..bytes.len() - 1]).unwrap();
+        let out_dir = dir.join("truncated");
+        let error = do_unpack(&in_file, Some(&out_dir), None, options).unwrap_err();
+        assert!(matches!(
+            error,
+            UnpackError::HeaderParseError(_, ParseError::TicketPastEof { .. })
+        ));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn batch_dir_names_are_unique() {
+        let paths: Vec<_> = ["a.bin", "b.bin", "x/a.ftab", "c", "y/c"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        let names = batch_dir_names(&paths).unwrap();
+        assert_eq!(names, ["0-a", "b", "2-a", "3-c", "4-c"]);
+
+        // the same file listed twice is unpacked twice
+        let paths = [PathBuf::from("a.bin"), PathBuf::from("a.bin")];
+        assert_eq!(batch_dir_names(&paths).unwrap(), ["0-a", "1-a"]);
+
+        let paths = [
+            PathBuf::from("1-a.bin"),
+            PathBuf::from("x/a.bin"),
+            PathBuf::from("y/a.bin"),
+        ];
+        assert_eq!(batch_dir_names(&paths), Err((0, 1, OsString::from("1-a"))));
     }
 }
@@ -1,9 +1,49 @@
-use crate::parser::Parser;
+use crate::{
+    format::{alignment_padding, FtabHeader, HEADER_LEN, SEGMENT_HEADER_LEN},
+    parser::{ParsedSegment, Parser},
+};
 use serde::{
     de::{self, Unexpected, Visitor},
     Deserialize, Deserializer, Serialize, Serializer,
 };
-use std::{fmt::Formatter, path::PathBuf};
+use std::{
+    collections::HashMap,
+    fmt::{self, Formatter},
+    path::{Component, Path, PathBuf},
+};
+
+/// The default name of the file containing the APTicket.
+pub const TICKET_FILENAME: &str = "ApImg4Ticket.der";
+
+/// The extension of the files containing the contents of segments used by [`filename_for_tag`].
+pub const DEFAULT_SEGMENT_EXTENSION: &str = "bin";
+
+/// Returns the default name of the file containing the contents of a segment with the specified tag.
+/// This is `<tag>.bin` for tags consisting of ASCII alphanumeric characters and `tag_<hex>.bin`
+/// otherwise.
+pub fn filename_for_tag(tag: [u8; 4]) -> PathBuf {
+    filename_for_tag_with_extension(tag, DEFAULT_SEGMENT_EXTENSION)
+}
+
+/// Returns the name of the file containing the contents of a segment with the specified tag the same
+/// way [`filename_for_tag`] does but with the specified extension instead of `bin`. The name has no
+/// extension when `extension` is empty.
+pub fn filename_for_tag_with_extension(tag: [u8; 4], extension: &str) -> PathBuf {
+    let tag = Tag(tag);
+    let mut filename = match tag.as_str() {
+        Some(tag_str) => tag_str.to_owned(),
+        None => format!("tag_{}", tag.to_hex()),
+    };
+    if !extension.is_empty() {
+        filename.push('.');
+        filename.push_str(extension);
+    }
+
+    let mut path = PathBuf::new();
+    path.push(filename);
+
+    path
+}
 
 pub struct TagVisitor;
 
@@ -147,9 +187,111 @@ impl<'de> Visitor<'de> for TagVisitor {
     }
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+/// A 4-byte segment tag.
+///
+/// Tags are ordered by their bytes which is the same as ordering them by the big-endian integers
+/// returned by [`Tag::to_u32`], i.e. the integers tags are serialized as when they aren't strings.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
 pub struct Tag(pub [u8; 4]);
 
+impl Tag {
+    /// Returns the tag interpreted as a big-endian integer, e.g. `0x726b6f73` for `rkos`.
+    pub fn to_u32(self) -> u32 {
+        u32::from_be_bytes(self.0)
+    }
+
+    /// Returns the tag as a string when all its bytes are ASCII alphanumeric characters and `None`
+    /// otherwise. Such tags are displayed, serialized and used in file names as strings.
+    pub fn as_str(&self) -> Option<&str> {
+        if self.0.iter().all(u8::is_ascii_alphanumeric) {
+            Some(std::str::from_utf8(&self.0).unwrap())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the bytes of the tag encoded as a lowercase hex string, e.g. `726b6f73`.
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.0)
+    }
+}
+
+impl fmt::Display for Tag {
+    /// Displays the tag as a string when [`Tag::as_str`] returns one and as a hexadecimal
+    /// big-endian integer otherwise.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.as_str() {
+            Some(s) => f.pad(s),
+            None => f.pad(&format!("{:#010x}", self.to_u32())),
+        }
+    }
+}
+
+/// The order segments are placed in by [`Manifest::sort_canonical`], highest priority first.
+///
+/// This is the order of the segments in the files ftabutil was tested with: the `rkos` segment,
+/// the `rrko` segment and then the `rkrn` segment. It's a convention of this tool, so segments with
+/// tags missing from the list are placed after all listed ones.
+pub const CANONICAL_TAG_ORDER: &[Tag] = &[Tag(*b"rkos"), Tag(*b"rrko"), Tag(*b"rkrn")];
+
+/// A pattern matching segment tags by their raw bytes.
+///
+/// A pattern is either a string of up to 4 characters, e.g. `rkos`, or a hex string with the `0x`
+/// prefix encoding up to 4 bytes, e.g. `0x726b6f73`. A pattern ending with `*` matches any tag
+/// starting with the bytes before the `*`, e.g. both `rk*` and `0x726b*` match `rkos` and `rkrn`,
+/// while `*` alone matches every tag. A pattern without `*` matches a single tag, where patterns
+/// shorter than 4 bytes are padded with zeroes the same way tags in manifests are.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct TagPattern {
+    prefix: Vec<u8>,
+    wildcard: bool,
+}
+
+impl TagPattern {
+    /// Parses a pattern using the syntax described in the [`TagPattern`] docs. Returns `None` in
+    /// case the pattern is malformed or longer than 4 bytes.
+    pub fn parse(pattern: &str) -> Option<Self> {
+        let (pattern, wildcard) = match pattern.strip_suffix('*') {
+            Some(prefix) => (prefix, true),
+            None => (pattern, false),
+        };
+
+        let prefix = match pattern
+            .strip_prefix("0x")
+            .or_else(|| pattern.strip_prefix("0X"))
+        {
+            Some(hex) => hex::decode(hex).ok()?,
+            None => pattern.as_bytes().to_vec(),
+        };
+        if prefix.len() > 4 || (!wildcard && prefix.is_empty()) {
+            return None;
+        }
+
+        Some(Self { prefix, wildcard })
+    }
+
+    /// Returns the single tag matched by a pattern without `*` and `None` for wildcard patterns.
+    pub fn tag(&self) -> Option<Tag> {
+        if self.wildcard {
+            return None;
+        }
+
+        let mut tag = [0; 4];
+        tag[..self.prefix.len()].copy_from_slice(&self.prefix);
+        Some(Tag(tag))
+    }
+
+    /// Checks if the pattern matches a tag.
+    pub fn matches(&self, tag: &[u8; 4]) -> bool {
+        if self.wildcard {
+            tag.starts_with(&self.prefix)
+        } else {
+            tag[..self.prefix.len()] == self.prefix[..]
+                && tag[self.prefix.len()..].iter().all(|&b| b == 0)
+        }
+    }
+}
+
 impl<'de> Deserialize<'de> for Tag {
     fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
         deserializer.deserialize_any(TagVisitor)
@@ -158,23 +300,160 @@ impl<'de> Deserialize<'de> for Tag {
 
 impl Serialize for Tag {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        if self.0.iter().all(|x| x.is_ascii_alphanumeric()) {
-            let s = std::str::from_utf8(&self.0[..]).unwrap();
-            serializer.serialize_str(s)
-        } else {
-            serializer.serialize_u32(u32::from_be_bytes(self.0))
+        match self.as_str() {
+            Some(s) => serializer.serialize_str(s),
+            None => serializer.serialize_u32(self.to_u32()),
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// (De)serializes optional byte strings as hex strings.
+mod hex_bytes {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        bytes: &Option<Vec<u8>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match bytes {
+            Some(bytes) => serializer.serialize_str(&hex::encode(bytes)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<u8>>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|s| hex::decode(s).map_err(de::Error::custom))
+            .transpose()
+    }
+}
+
+/// Names of the fields of [`SegmentDesc`] accepted in manifests in declaration order.
+pub const SEGMENT_FIELDS: &[&str] = &["path", "tag", "unk", "padding", "expected_len", "alias_of"];
+
+/// Names of the fields of [`OutputDesc`] accepted in manifests in declaration order.
+pub const OUTPUT_FIELDS: &[&str] = &["name", "segments"];
+
+/// Names of the fields of [`Manifest`] accepted in manifests in declaration order.
+pub const MANIFEST_FIELDS: &[&str] = &[
+    "unk_0", "unk_1", "unk_2", "unk_3", "unk_4", "unk_5", "unk_6", "label", "ticket", "segments",
+    "outputs",
+];
+
+/// Checks that a TOML table only contains keys from the list of known fields.
+fn check_known_fields(
+    table: &toml::value::Table,
+    fields: &[&str],
+    location: &str,
+) -> Result<(), toml::de::Error> {
+    match table.keys().find(|key| !fields.contains(&key.as_str())) {
+        Some(key) => Err(de::Error::custom(format!(
+            "unknown field `{}` {}, expected one of {}",
+            key,
+            location,
+            fields.join(", ")
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Describes a single segment. Fields are serialized in declaration order, i.e. `path`, `tag`, `unk`
+/// and then the optional `padding`, `expected_len` and `alias_of`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SegmentDesc {
     pub path: PathBuf,
     pub tag: Tag,
+    #[serde(default)]
     pub unk: u32,
+    /// Bytes preceding the segment's data in the file. When absent, the segment is padded with
+    /// zeroes to a 4-byte boundary.
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "hex_bytes")]
+    pub padding: Option<Vec<u8>>,
+    /// The length the segment's contents are expected to have. When present, a warning is logged
+    /// when building a file with contents of a different length.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_len: Option<u32>,
+    /// The tag of an earlier segment whose contents this segment shares. When present, the segment
+    /// list entry of this segment points at the same range as the entry of the first earlier
+    /// segment with this tag and `path` and `padding` are ignored. Building fails when no earlier
+    /// segment has this tag.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias_of: Option<Tag>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl SegmentDesc {
+    /// Creates a [`SegmentDesc`] for a parsed segment with the default file name returned by
+    /// [`filename_for_tag`]. `prev_end` is the end offset of the previous segment or the end of the
+    /// segment list for the first segment and is used to record the padding preceding the segment.
+    /// The `expected_len` field is left empty.
+    pub fn with_parsed_segment(parser: &Parser, prev_end: usize, segment: &ParsedSegment) -> Self {
+        Self {
+            path: filename_for_tag(segment.tag),
+            tag: Tag(segment.tag),
+            unk: segment.unk,
+            padding: padding_for_segment(parser, prev_end, segment),
+            expected_len: None,
+            alias_of: None,
+        }
+    }
+
+    /// Creates a [`SegmentDesc`] for a parsed segment which points at the same range as the segment
+    /// described by `target`. The description references the same file as `target`.
+    pub fn alias(target: &SegmentDesc, segment: &ParsedSegment) -> Self {
+        Self {
+            path: target.path.clone(),
+            tag: Tag(segment.tag),
+            unk: segment.unk,
+            padding: None,
+            expected_len: target.expected_len,
+            alias_of: Some(target.tag),
+        }
+    }
+}
+
+/// Returns the bytes between the end of the previous segment and the start of the segment's data in
+/// case these differ from the zero padding the builder would produce and `None` otherwise.
+fn padding_for_segment(
+    parser: &Parser,
+    prev_end: usize,
+    segment: &ParsedSegment,
+) -> Option<Vec<u8>> {
+    // Segments that don't follow the previous one can't be reproduced with padding anyway.
+    let padding = parser.bytes_at(prev_end..segment.offset)?;
+    let default_len = alignment_padding(prev_end);
+
+    if padding.len() == default_len && padding.iter().all(|&b| b == 0) {
+        None
+    } else {
+        trace!(
+            "Preserving {} padding bytes before segment with tag {}.",
+            padding.len(),
+            segment.tag.escape_ascii()
+        );
+
+        Some(padding.to_vec())
+    }
+}
+
+/// Describes one of several 'ftab' files built from a multi-output manifest.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OutputDesc {
+    /// The path of the built file, relative paths are relative to the output directory.
+    pub name: PathBuf,
+    /// The tags of the segments from the manifest's `segments` array included in the file, in the
+    /// order these appear in the file.
+    pub segments: Vec<Tag>,
+}
+
+/// Describes a 'ftab' file. Fields are serialized in declaration order, i.e. `unk_0` to `unk_6`, the
+/// optional `label` and `ticket`, the `segments` array in the order the segments appear in the file
+/// and then the optional `outputs` array.
+///
+/// When `outputs` is not empty, the manifest describes several files sharing the header fields, the
+/// ticket and the pool of segments defined by `segments`, see [`Manifest::output_manifest`].
+#[derive(Default, Debug, Serialize, Deserialize)]
 pub struct Manifest {
     pub unk_0: u32,
     pub unk_1: u32,
@@ -183,11 +462,41 @@ pub struct Manifest {
     pub unk_4: u32,
     pub unk_5: u32,
     pub unk_6: u32,
+    /// A free-form label describing the file. It isn't stored in the 'ftab' file and may be used
+    /// to name the packed file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub ticket: Option<PathBuf>,
     pub segments: Vec<SegmentDesc>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<OutputDesc>,
 }
 
 impl Manifest {
+    /// Creates a [`Manifest`] with the header fields of a 'ftab' file and no segments. When the
+    /// header declares a ticket, the manifest references it as [`TICKET_FILENAME`].
+    ///
+    /// Only the header is needed, so this is the cheapest way to describe a file, e.g. when taking
+    /// an inventory of a large number of files.
+    pub fn with_header(header: &FtabHeader) -> Manifest {
+        let has_ticket = header.ticket_offset != 0 || header.ticket_len != 0;
+
+        Manifest {
+            unk_0: header.unk_0,
+            unk_1: header.unk_1,
+            unk_2: header.unk_2,
+            unk_3: header.unk_3,
+            unk_4: header.unk_4,
+            unk_5: header.unk_5,
+            unk_6: header.unk_6,
+            label: None,
+            ticket: has_ticket.then(|| PathBuf::from(TICKET_FILENAME)),
+            segments: Vec::new(),
+            outputs: Vec::new(),
+        }
+    }
+
     pub fn with_parser(parser: &Parser) -> Manifest {
         Manifest {
             unk_0: parser.unk_0(),
@@ -197,8 +506,460 @@ impl Manifest {
             unk_4: parser.unk_4(),
             unk_5: parser.unk_5(),
             unk_6: parser.unk_6(),
+            label: None,
             ticket: None,
             segments: Vec::new(),
+            outputs: Vec::new(),
+        }
+    }
+
+    /// Creates a [`Manifest`] describing a parsed 'ftab' file without writing anything. Unlike
+    /// [`Manifest::with_parser`], the segments are described using [`SegmentDesc::with_parsed_segment`]
+    /// and the ticket, if present, is referenced as [`TICKET_FILENAME`].
+    pub fn from_parser_and_segments<'a, I>(parser: &Parser<'a>, segments: I) -> Manifest
+    where
+        I: IntoIterator<Item = ParsedSegment<'a>>,
+    {
+        let mut manifest = Self::with_parser(parser);
+        manifest.ticket = parser.ticket().map(|_| PathBuf::from(TICKET_FILENAME));
+
+        // Segments pointing at the exact same range as an earlier one are described as aliases.
+        let mut ranges = HashMap::new();
+        let mut prev_end = HEADER_LEN + parser.segments_slice().len() * SEGMENT_HEADER_LEN;
+        for segment in segments {
+            let range = (segment.offset, segment.data.len());
+            let desc = match ranges.get(&range) {
+                Some(&index) => SegmentDesc::alias(&manifest.segments[index], &segment),
+                None => {
+                    ranges.insert(range, manifest.segments.len());
+                    let desc = SegmentDesc::with_parsed_segment(parser, prev_end, &segment);
+                    prev_end = segment.offset + segment.data.len();
+                    desc
+                }
+            };
+            manifest.segments.push(desc);
         }
+
+        manifest
+    }
+
+    /// Checks the manifest for problems that don't depend on the referenced files and returns their
+    /// descriptions. These are segments sharing a tag, aliases of segments that don't precede them,
+    /// outputs referencing undefined tags and outputs sharing a name.
+    ///
+    /// With `confine_paths`, paths that are absolute or contain `..` components are reported too,
+    /// since these may point outside the manifest's directory.
+    pub fn validate(&self, confine_paths: bool) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        let mut check_path = |what: String, path: &Path| {
+            if confine_paths
+                && (path.is_absolute()
+                    || path
+                        .components()
+                        .any(|component| component == Component::ParentDir))
+            {
+                problems.push(format!(
+                    "{} path {} points outside the manifest's directory",
+                    what,
+                    path.display()
+                ));
+            }
+        };
+        if let Some(ticket) = self.ticket.as_deref() {
+            check_path("ticket".to_owned(), ticket);
+        }
+        for (index, segment) in self.segments.iter().enumerate() {
+            check_path(format!("segment {}", index), &segment.path);
+        }
+
+        for (index, segment) in self.segments.iter().enumerate() {
+            let earlier = &self.segments[..index];
+            if earlier.iter().any(|prev| prev.tag == segment.tag) {
+                problems.push(format!(
+                    "segment {} has tag {} which is already used by an earlier segment",
+                    index, segment.tag
+                ));
+            }
+            if let Some(alias_of) = segment.alias_of {
+                if !earlier.iter().any(|prev| prev.tag == alias_of) {
+                    problems.push(format!(
+                        "segment {} is an alias of tag {} which no earlier segment has",
+                        index, alias_of
+                    ));
+                }
+            }
+        }
+
+        for (index, output) in self.outputs.iter().enumerate() {
+            if self.outputs[..index]
+                .iter()
+                .any(|prev| prev.name == output.name)
+            {
+                problems.push(format!(
+                    "output {} has name {} which is already used by an earlier output",
+                    index,
+                    output.name.display()
+                ));
+            }
+            for tag in output.segments.iter() {
+                if !self.segments.iter().any(|segment| segment.tag == *tag) {
+                    problems.push(format!(
+                        "output {} references an undefined segment with tag {}",
+                        index, tag
+                    ));
+                }
+            }
+        }
+
+        problems
+    }
+
+    /// Returns a mutable reference to the `unk_<index>` header field or `None` when `index` is
+    /// greater than 6.
+    pub fn unk_mut(&mut self, index: usize) -> Option<&mut u32> {
+        match index {
+            0 => Some(&mut self.unk_0),
+            1 => Some(&mut self.unk_1),
+            2 => Some(&mut self.unk_2),
+            3 => Some(&mut self.unk_3),
+            4 => Some(&mut self.unk_4),
+            5 => Some(&mut self.unk_5),
+            6 => Some(&mut self.unk_6),
+            _ => None,
+        }
+    }
+
+    /// Reorders the segments by the position of their tags in [`CANONICAL_TAG_ORDER`]. The sort is
+    /// stable, so segments with the same tag and segments with tags missing from the list keep
+    /// their order from the manifest. An alias is never moved before the segment it refers to, it's
+    /// placed right after it instead if the alias would go first.
+    pub fn sort_canonical(&mut self) {
+        let mut keys: Vec<usize> = Vec::with_capacity(self.segments.len());
+        for segment in self.segments.iter() {
+            let mut key = CANONICAL_TAG_ORDER
+                .iter()
+                .position(|&tag| tag == segment.tag)
+                .unwrap_or(CANONICAL_TAG_ORDER.len());
+            if let Some(alias_of) = segment.alias_of {
+                let target = self.segments.iter().position(|prev| prev.tag == alias_of);
+                if let Some(target_key) = target.and_then(|index| keys.get(index)) {
+                    key = key.max(*target_key);
+                }
+            }
+            keys.push(key);
+        }
+
+        let mut keyed: Vec<(usize, SegmentDesc)> =
+            keys.into_iter().zip(self.segments.drain(..)).collect();
+        keyed.sort_by_key(|(key, _)| *key);
+        self.segments = keyed.into_iter().map(|(_, segment)| segment).collect();
+    }
+
+    /// Creates a [`Manifest`] describing a single output of a multi-output manifest. The header
+    /// fields, the label and the ticket are copied and the segments are the first segments with the
+    /// tags listed by the output. Aliases of segments not preceding them in the output read their
+    /// contents from their own files instead.
+    ///
+    /// # Errors
+    /// Returns the first tag listed by the output which doesn't correspond to any segment.
+    pub fn output_manifest(&self, output: &OutputDesc) -> Result<Manifest, Tag> {
+        let mut segments: Vec<SegmentDesc> = Vec::with_capacity(output.segments.len());
+        for &tag in output.segments.iter() {
+            let mut segment = self
+                .segments
+                .iter()
+                .find(|segment| segment.tag == tag)
+                .cloned()
+                .ok_or(tag)?;
+            if let Some(alias_of) = segment.alias_of {
+                if !segments.iter().any(|prev| prev.tag == alias_of) {
+                    segment.alias_of = None;
+                }
+            }
+            segments.push(segment);
+        }
+
+        Ok(Manifest {
+            unk_0: self.unk_0,
+            unk_1: self.unk_1,
+            unk_2: self.unk_2,
+            unk_3: self.unk_3,
+            unk_4: self.unk_4,
+            unk_5: self.unk_5,
+            unk_6: self.unk_6,
+            label: self.label.clone(),
+            ticket: self.ticket.clone(),
+            segments,
+            outputs: Vec::new(),
+        })
+    }
+
+    /// Serializes the manifest into TOML. The output only depends on the contents of the manifest
+    /// and fields always appear in the order documented on [`Manifest`] and [`SegmentDesc`], so
+    /// manifests of the same file are byte-for-byte identical across runs.
+    pub fn to_toml(&self) -> Vec<u8> {
+        toml::to_vec(self).unwrap()
+    }
+
+    /// Deserializes a manifest from TOML rejecting keys that don't correspond to any field of the
+    /// manifest or its segments. Unknown keys are silently ignored when deserializing a manifest
+    /// using serde directly, so a misspelled optional field would otherwise have no effect.
+    ///
+    /// # Errors
+    /// Returns an error naming the first unknown key in case there is one and the same errors as
+    /// `toml::from_slice` otherwise.
+    pub fn from_toml_strict(data: &[u8]) -> Result<Manifest, toml::de::Error> {
+        Self::from_value_strict(toml::from_slice(data)?)
+    }
+
+    /// Deserializes a manifest from a TOML value rejecting unknown keys the same way
+    /// [`Manifest::from_toml_strict`] does. Useful for manifests converted from other formats.
+    ///
+    /// # Errors
+    /// Returns an error naming the first unknown key in case there is one and the same errors as
+    /// `toml::Value::try_into` otherwise.
+    pub fn from_value_strict(value: toml::Value) -> Result<Manifest, toml::de::Error> {
+        if let Some(table) = value.as_table() {
+            check_known_fields(table, MANIFEST_FIELDS, "in the manifest")?;
+
+            let segments = table.get("segments").and_then(toml::Value::as_array);
+            for (index, segment) in segments.into_iter().flatten().enumerate() {
+                if let Some(segment) = segment.as_table() {
+                    let location = format!("in segment {}", index);
+                    check_known_fields(segment, SEGMENT_FIELDS, &location)?;
+                }
+            }
+
+            let outputs = table.get("outputs").and_then(toml::Value::as_array);
+            for (index, output) in outputs.into_iter().flatten().enumerate() {
+                if let Some(output) = output.as_table() {
+                    let location = format!("in output {}", index);
+                    check_known_fields(output, OUTPUT_FIELDS, &location)?;
+                }
+            }
+        }
+
+        value.try_into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tag_order_matches_integer_order() {
+        let mut tags = [
+            Tag(*b"rkrn"),
+            Tag([0xff, 0, 0, 0]),
+            Tag(*b"rkos"),
+            Tag([0, 0, 0, 1]),
+            Tag([0, 0, 1, 0]),
+            Tag(*b"RKOS"),
+            Tag([0; 4]),
+            Tag([0x7f, 0xff, 0xff, 0xff]),
+            Tag([0x80, 0, 0, 0]),
+        ];
+        let mut integers: Vec<_> = tags.iter().map(|tag| tag.to_u32()).collect();
+        tags.sort();
+        integers.sort();
+
+        assert_eq!(
+            tags.iter().map(|tag| tag.to_u32()).collect::<Vec<_>>(),
+            integers
+        );
+        assert_eq!(tags[0], Tag([0; 4]));
+        assert_eq!(tags[tags.len() - 1], Tag([0xff, 0, 0, 0]));
+        assert!(Tag(*b"rkos") < Tag(*b"rkrn"));
+        assert!(Tag(*b"RKOS") < Tag(*b"rkos"));
+    }
+
+    #[test]
+    fn sort_canonical_keeps_aliases_after_targets() {
+        let segment = |tag: &[u8; 4], alias_of: Option<&[u8; 4]>| SegmentDesc {
+            path: PathBuf::from(format!("{}.bin", String::from_utf8_lossy(tag))),
+            tag: Tag(*tag),
+            unk: 0,
+            padding: None,
+            expected_len: None,
+            alias_of: alias_of.map(|target| Tag(*target)),
+        };
+        let mut manifest = Manifest {
+            segments: vec![
+                segment(b"zzzz", None),
+                segment(b"rkrn", None),
+                segment(b"rkos", Some(b"rkrn")),
+                segment(b"aaaa", None),
+                segment(b"rrko", None),
+            ],
+            ..Default::default()
+        };
+        manifest.sort_canonical();
+
+        let tags: Vec<_> = manifest
+            .segments
+            .iter()
+            .map(|segment| segment.tag.to_string())
+            .collect();
+        assert_eq!(tags, ["rrko", "rkrn", "rkos", "zzzz", "aaaa"]);
+        assert!(manifest.validate(false).is_empty());
+    }
+
+    #[test]
+    fn tag_as_str_boundaries() {
+        assert_eq!(Tag(*b"rkos").as_str(), Some("rkos"));
+        assert_eq!(Tag(*b"09AZ").as_str(), Some("09AZ"));
+        assert_eq!(Tag(*b"azaz").as_str(), Some("azaz"));
+
+        // tags are strings only when every byte is alphanumeric, even when it's printable
+        for byte in [
+            0x1f, 0x20, 0x7e, 0x7f, b'/', b':', b'@', b'[', b'`', b'{', 0x80, 0xff,
+        ] {
+            let tag = Tag([b'r', b'k', b'o', byte]);
+            assert_eq!(tag.as_str(), None, "byte {:#04x}", byte);
+            assert_eq!(tag.to_string(), format!("{:#010x}", tag.to_u32()));
+        }
+
+        let tag = Tag(*b"rk o");
+        assert_eq!(tag.to_hex(), "726b206f");
+        assert_eq!(tag.to_string(), "0x726b206f");
+        assert_eq!(filename_for_tag(tag.0), Path::new("tag_726b206f.bin"));
+        assert_eq!(filename_for_tag(*b"rkos"), Path::new("rkos.bin"));
+    }
+
+    /// Returns the sorted keys of a TOML table.
+    fn sorted_keys(table: &toml::Value) -> Vec<&str> {
+        let mut keys: Vec<_> = table
+            .as_table()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        keys.sort_unstable();
+
+        keys
+    }
+
+    /// Returns a sorted copy of a list of field names.
+    fn sorted_fields<'a>(fields: &[&'a str]) -> Vec<&'a str> {
+        let mut fields = fields.to_vec();
+        fields.sort_unstable();
+
+        fields
+    }
+
+    #[test]
+    fn known_fields_match_serialized_fields() {
+        let manifest = Manifest {
+            label: Some("dev".to_owned()),
+            ticket: Some(PathBuf::from(TICKET_FILENAME)),
+            segments: vec![SegmentDesc {
+                path: PathBuf::from("rkos.bin"),
+                tag: Tag(*b"rkos"),
+                unk: 1,
+                padding: Some(vec![0xff]),
+                expected_len: Some(4),
+                alias_of: Some(Tag(*b"rkrn")),
+            }],
+            outputs: vec![OutputDesc {
+                name: PathBuf::from("ftab.bin"),
+                segments: vec![Tag(*b"rkos")],
+            }],
+            ..Default::default()
+        };
+        let value = toml::Value::try_from(&manifest).unwrap();
+
+        assert_eq!(sorted_keys(&value), sorted_fields(MANIFEST_FIELDS));
+        assert_eq!(
+            sorted_keys(&value["segments"][0]),
+            sorted_fields(SEGMENT_FIELDS)
+        );
+        assert_eq!(
+            sorted_keys(&value["outputs"][0]),
+            sorted_fields(OUTPUT_FIELDS)
+        );
+
+        let serialized = manifest.to_toml();
+        assert!(Manifest::from_toml_strict(&serialized).is_ok());
+    }
+
+    #[test]
+    fn strict_manifest_rejects_misspelled_fields() {
+        let manifest =
+            b"unk_0 = 0\nunk_1 = 0\nunk_2 = 0\nunk_3 = 0\nunk_4 = 0\nunk_5 = 0\nunk_6 = 0\n";
+
+        let mut data = manifest.to_vec();
+        data.extend_from_slice(b"segmnts = []\n");
+        let error = Manifest::from_toml_strict(&data).unwrap_err().to_string();
+        assert!(
+            error.contains("unknown field `segmnts` in the manifest, expected one of unk_0"),
+            "{}",
+            error
+        );
+        // serde alone only reports the missing `segments` field
+        let error = toml::from_slice::<Manifest>(&data).unwrap_err().to_string();
+        assert!(error.contains("missing field `segments`"), "{}", error);
+
+        let mut data = manifest.to_vec();
+        data.extend_from_slice(
+            b"[[segments]]\npath = \"rkos.bin\"\ntag = \"rkos\"\npaddng = \"00\"\n",
+        );
+        let error = Manifest::from_toml_strict(&data).unwrap_err().to_string();
+        assert!(
+            error.contains("unknown field `paddng` in segment 0, expected one of path, tag"),
+            "{}",
+            error
+        );
+        let lenient = toml::from_slice::<Manifest>(&data).unwrap();
+        assert_eq!(lenient.segments[0].padding, None);
+
+        let mut data = manifest.to_vec();
+        data.extend_from_slice(b"segments = []\n[[outputs]]\nname = \"a.bin\"\nsegment = []\n");
+        let error = Manifest::from_toml_strict(&data).unwrap_err().to_string();
+        assert!(
+            error.contains("unknown field `segment` in output 0, expected one of name, segments"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn minimal_segment() {
+        let value: toml::Value =
+            toml::from_str(r#"segment = { tag = "rkrn", path = "rkrn.bin" }"#).unwrap();
+        let segment: SegmentDesc = value["segment"].clone().try_into().unwrap();
+
+        assert_eq!(segment.tag, Tag(*b"rkrn"));
+        assert_eq!(segment.path, Path::new("rkrn.bin"));
+        assert_eq!(segment.unk, 0);
+        assert_eq!(segment.padding, None);
+        assert_eq!(segment.expected_len, None);
+        assert_eq!(segment.alias_of, None);
+    }
+
+    #[test]
+    fn inline_segments() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+            unk_0 = 0
+            unk_1 = 0
+            unk_2 = 0
+            unk_3 = 0
+            unk_4 = 0
+            unk_5 = 0
+            unk_6 = 0
+            segments = [
+                { tag = "rkrn", path = "rkrn.bin" },
+                { tag = 0x726b6f73, path = "rkos.bin", unk = 1 },
+            ]
+            "#,
+        )
+        .unwrap();
+
+        let segments = &manifest.segments;
+        assert_eq!(segments.len(), 2);
+        assert_eq!((segments[0].tag, segments[0].unk), (Tag(*b"rkrn"), 0));
+        assert_eq!((segments[1].tag, segments[1].unk), (Tag(*b"rkos"), 1));
     }
 }
@@ -1,10 +1,11 @@
 //! Provides the [`Parser`] and [`SegmentParser`] structures that can be used to parse in-memory
 //! 'ftab' files.
 
-/// Provides [`ParseError`] and [`OobSegmentError`] that describe errors which may occur in
-/// [`Parser::parse`] and [`SegmentParser::next_segment`] methods.
+/// Provides [`ParseError`], [`OobSegmentError`] and [`TicketOverlapError`] that describe errors
+/// which may occur in [`Parser::parse`], [`SegmentParser::next_segment`] and
+/// [`Parser::check_ticket_overlap`] methods.
 pub mod error {
-    use std::{error::Error, fmt};
+    use std::{error::Error, fmt, ops::Range};
     use thiserror::Error;
 
     /// An error which may occur when parsing the 'ftab' file header.
@@ -25,10 +26,53 @@ pub mod error {
         /// Returned when the end of the segments list exceeds past the end of a file.
         #[error("segments list is larger than the space available in the file")]
         OobSegmentsList,
-        /// Returned when the range of a ticket exceeds past the end of a file or overlaps with
-        /// either the header or the segment list.
-        #[error("ticket range in file is out of bounds")]
-        OobTicket,
+        /// Returned when the ticket starts inside the 'ftab' header.
+        #[error(
+            "ticket at offset {offset:#x} with length {len:#x} overlaps the header ending at \
+            {:#x}",
+            crate::format::HEADER_LEN
+        )]
+        TicketOverlapsHeader {
+            /// The ticket offset from the header.
+            offset: usize,
+            /// The ticket length from the header.
+            len: usize,
+        },
+        /// Returned when the ticket starts inside the segment list.
+        #[error(
+            "ticket at offset {offset:#x} with length {len:#x} overlaps the segment list ending \
+            at {segments_end:#x}"
+        )]
+        TicketOverlapsSegmentList {
+            /// The ticket offset from the header.
+            offset: usize,
+            /// The ticket length from the header.
+            len: usize,
+            /// The offset of the end of the segment list.
+            segments_end: usize,
+        },
+        /// Returned when the range of the ticket extends past the end of a file.
+        #[error(
+            "ticket at offset {offset:#x} with length {len:#x} extends past the end of the file \
+            at {file_len:#x}"
+        )]
+        TicketPastEof {
+            /// The ticket offset from the header.
+            offset: usize,
+            /// The ticket length from the header.
+            len: usize,
+            /// The length of the file.
+            file_len: usize,
+        },
+        /// Returned when the segments count from the 'ftab' header exceeds the limit passed to
+        /// [`Parser::parse_with_max_segments`](../struct.Parser.html#method.parse_with_max_segments).
+        #[error("file declares {count} segments which is more than the limit of {max}")]
+        TooManySegments {
+            /// The segments count from the header.
+            count: u32,
+            /// The maximum allowed segments count.
+            max: usize,
+        },
     }
 
     /// Returned when the range of a 'ftab' file segment specified in its segment list entry exceeds
@@ -36,26 +80,66 @@ pub mod error {
     #[derive(Debug)]
     #[non_exhaustive]
     pub struct OobSegmentError {
+        /// The index of the entry in the segment list.
+        pub index: usize,
         /// The tag specified in the segment list entry.
         pub tag: [u8; 4],
+        /// The offset specified in the segment list entry.
+        pub offset: usize,
+        /// The length specified in the segment list entry.
+        pub len: usize,
     }
 
     impl fmt::Display for OobSegmentError {
         fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
             write!(
                 f,
-                "segment with tag {} is out of bounds",
+                "segment #{} (tag {}) is out of bounds",
+                self.index,
                 self.tag.escape_ascii()
             )
         }
     }
 
     impl Error for OobSegmentError {}
+
+    /// Returned when the range of the ticket intersects with the contents of a 'ftab' file segment.
+    #[derive(Debug)]
+    #[non_exhaustive]
+    pub struct TicketOverlapError {
+        /// The tag specified in the segment list entry of the overlapping segment.
+        pub tag: [u8; 4],
+        /// The range of file offsets shared by the ticket and the segment.
+        pub overlap: Range<usize>,
+    }
+
+    impl fmt::Display for TicketOverlapError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(
+                f,
+                "ticket overlaps segment with tag {} at bytes {:#x}..{:#x}",
+                self.tag.escape_ascii(),
+                self.overlap.start,
+                self.overlap.end
+            )
+        }
+    }
+
+    impl Error for TicketOverlapError {}
 }
 
-use crate::format::*;
-pub use error::{OobSegmentError, ParseError};
-use std::slice;
+use crate::{format::*, manifest::Tag};
+pub use error::{OobSegmentError, ParseError, TicketOverlapError};
+use std::{
+    mem,
+    ops::{Range, RangeInclusive},
+    slice,
+};
+
+/// A limit on the segments count suitable for parsing untrusted files with
+/// [`Parser::parse_with_max_segments`]. Real 'ftab' files contain a few dozen segments at most, so
+/// this is generous.
+pub const DEFAULT_MAX_SEGMENTS: usize = 4096;
 
 /// Reads a 32-bit little-endian integer from the start of a byte slice and returns a tuple of the
 /// slice's tail and the integer.
@@ -84,6 +168,11 @@ fn match_magic(bytes: &[u8]) -> Result<&[u8], ParseError> {
 
 /// Takes a subslice of a slice by a relative offset and length. The absolute offset in the slice is
 /// determined by subtracting `slice_offset` from `offset`.
+///
+/// Returns `None` when `offset` is below `slice_offset` or the range doesn't fit into the slice. A
+/// range may end exactly at the end of the slice, so a zero-length range starting right at the end
+/// is accepted while one starting past the end is not. The length is compared with the remaining
+/// length of the slice rather than added to the offset, so huge lengths can't overflow.
 fn cut_subslice(slice: &[u8], offset: usize, len: usize, slice_offset: usize) -> Option<&[u8]> {
     let offset = offset.checked_sub(slice_offset)?;
     if offset <= slice.len() && (slice.len() - offset) >= len {
@@ -93,11 +182,82 @@ fn cut_subslice(slice: &[u8], offset: usize, len: usize, slice_offset: usize) ->
     }
 }
 
+/// Reads just the segments count from the 'ftab' file header.
+///
+/// Only the magic value is validated and no other header fields are parsed, so this is the
+/// cheapest way to find out how many segments a file has, e.g. when collecting statistics over a
+/// large number of files. The count is not validated against the length of the file.
+///
+/// # Errors
+/// This function will return either [`ParseError::TooShort`] or [`ParseError::UnknownMagic`] in
+/// case the provided slice does not start with a 'ftab' header.
+///
+/// [`ParseError::TooShort`]: error/enum.ParseError.html#variant.TooShort
+/// [`ParseError::UnknownMagic`]: error/enum.ParseError.html#variant.UnknownMagic
+pub fn count_segments(bytes: &[u8]) -> Result<u32, ParseError> {
+    if bytes.len() < HEADER_LEN {
+        return Err(ParseError::TooShort);
+    }
+
+    let bytes = match_magic(&bytes[mem::offset_of!(FtabHeader, magic)..])?;
+    let (_, segments_count) = get_u32_le(bytes);
+
+    Ok(segments_count)
+}
+
+/// Parses just the 'ftab' file header without validating the ranges of the segment list and the
+/// ticket.
+///
+/// Only the first [`HEADER_LEN`] bytes of a file are required, so this may be used to quickly peek
+/// at the header without loading the whole file. The returned header doesn't borrow `bytes`, which
+/// makes it suitable for tools that only collect metadata and don't keep the file contents around.
+///
+/// # Errors
+/// This function will return either [`ParseError::TooShort`] or [`ParseError::UnknownMagic`] in
+/// case the provided slice does not start with a 'ftab' header.
+///
+/// [`ParseError::TooShort`]: error/enum.ParseError.html#variant.TooShort
+/// [`ParseError::UnknownMagic`]: error/enum.ParseError.html#variant.UnknownMagic
+pub fn peek_header(bytes: &[u8]) -> Result<FtabHeader, ParseError> {
+    if bytes.len() < HEADER_LEN {
+        return Err(ParseError::TooShort);
+    }
+
+    // Parse the header's fields.
+    let (bytes, unk_0) = get_u32_le(bytes);
+    let (bytes, unk_1) = get_u32_le(bytes);
+    let (bytes, unk_2) = get_u32_le(bytes);
+    let (bytes, unk_3) = get_u32_le(bytes);
+    let (bytes, ticket_offset) = get_u32_le(bytes);
+    let (bytes, ticket_len) = get_u32_le(bytes);
+    let (bytes, unk_4) = get_u32_le(bytes);
+    let (bytes, unk_5) = get_u32_le(bytes);
+    let bytes = match_magic(bytes)?;
+    let (bytes, segments_count) = get_u32_le(bytes);
+    let (_, unk_6) = get_u32_le(bytes);
+
+    Ok(FtabHeader {
+        unk_0,
+        unk_1,
+        unk_2,
+        unk_3,
+        ticket_offset,
+        ticket_len,
+        unk_4,
+        unk_5,
+        magic: *b"rkosftab",
+        segments_count,
+        unk_6,
+    })
+}
+
 /// A parser that can be used to parse the 'ftab' file header and produce a [`SegmentsParser`].
 #[derive(Clone, Debug)]
 pub struct Parser<'a> {
+    metadata: &'a [u8],
     ticket: Option<&'a [u8]>,
-    segments: &'a [[u8; 16]],
+    ticket_offset: usize,
+    segments: &'a [[u8; SEGMENT_HEADER_LEN]],
     tail: &'a [u8],
     unk_0: u32,
     unk_1: u32,
@@ -118,26 +278,37 @@ impl<'a> Parser<'a> {
     ///
     /// [`ParseError`]: error/enum.ParseError.html
     pub fn parse(bytes: &'a [u8]) -> Result<Self, ParseError> {
-        if bytes.len() < HEADER_LEN {
-            return Err(ParseError::TooShort);
-        }
+        Self::parse_with_max_segments(bytes, usize::MAX)
+    }
 
-        // Parse the header's fields.
-        let (bytes, unk_0) = get_u32_le(bytes);
-        let (bytes, unk_1) = get_u32_le(bytes);
-        let (bytes, unk_2) = get_u32_le(bytes);
-        let (bytes, unk_3) = get_u32_le(bytes);
-        let (bytes, ticket_offset) = get_u32_le(bytes);
-        let (bytes, ticket_len) = get_u32_le(bytes);
-        let (bytes, unk_4) = get_u32_le(bytes);
-        let (bytes, unk_5) = get_u32_le(bytes);
-        let bytes = match_magic(bytes)?;
-        let (bytes, segments_cnt) = get_u32_le(bytes);
-        let (tail, unk_6) = get_u32_le(bytes);
+    /// Parse the provided byte slice as a 'ftab' file rejecting files which declare more than
+    /// `max_segments` segments. [`Parser::parse`] doesn't limit the segments count, see
+    /// [`DEFAULT_MAX_SEGMENTS`] for a limit suitable for untrusted files.
+    ///
+    /// The segment list is always validated to fit into the file, but a limit protects code
+    /// processing the segments from spending unreasonable time on crafted files.
+    ///
+    /// # Errors
+    /// This method will return [`ParseError::TooManySegments`] in case the limit is exceeded and
+    /// the same errors as [`Parser::parse`] otherwise.
+    ///
+    /// [`ParseError::TooManySegments`]: error/enum.ParseError.html#variant.TooManySegments
+    pub fn parse_with_max_segments(
+        bytes: &'a [u8],
+        max_segments: usize,
+    ) -> Result<Self, ParseError> {
+        let header = peek_header(bytes)?;
+        let tail = &bytes[HEADER_LEN..];
 
         // Calculate the lengths of the segments list and validate that it doesn't overflow and is
         // in bounds.
-        let segments_cnt: usize = segments_cnt.try_into().unwrap();
+        let segments_cnt: usize = header.segments_count.try_into().unwrap();
+        if segments_cnt > max_segments {
+            return Err(ParseError::TooManySegments {
+                count: header.segments_count,
+                max: max_segments,
+            });
+        }
         let segments_len = segments_cnt
             .checked_mul(SEGMENT_HEADER_LEN)
             .ok_or(ParseError::OverflowingSegmentsLength)?;
@@ -155,6 +326,11 @@ impl<'a> Parser<'a> {
         let tail = &tail[segments_len..];
 
         // Ticket may or may not be present.
+        let FtabHeader {
+            ticket_offset,
+            ticket_len,
+            ..
+        } = header;
         let ticket = if ticket_offset != 0 || ticket_len != 0 {
             debug!(
                 "Ticket offset is {:#x}, length is {:#x}.",
@@ -164,11 +340,33 @@ impl<'a> Parser<'a> {
             let ticket_offset: usize = ticket_offset.try_into().unwrap();
             let ticket_len: usize = ticket_len.try_into().unwrap();
 
-            // Ensure that ticket's range is in bounds and also doesn't overflow.
-            let ticket = cut_subslice(tail, ticket_offset, ticket_len, HEADER_LEN + segments_len)
-                .ok_or(ParseError::OobTicket)?;
-
-            Some(ticket)
+            // Check the ticket's range against each region of the file separately so that the
+            // error tells which one it intersects.
+            let segments_end = HEADER_LEN + segments_len;
+            if ticket_offset < HEADER_LEN {
+                return Err(ParseError::TicketOverlapsHeader {
+                    offset: ticket_offset,
+                    len: ticket_len,
+                });
+            }
+            if ticket_offset < segments_end {
+                return Err(ParseError::TicketOverlapsSegmentList {
+                    offset: ticket_offset,
+                    len: ticket_len,
+                    segments_end,
+                });
+            }
+            let ticket_end = ticket_offset
+                .checked_add(ticket_len)
+                .filter(|&end| end <= bytes.len())
+                .ok_or(ParseError::TicketPastEof {
+                    offset: ticket_offset,
+                    len: ticket_len,
+                    file_len: bytes.len(),
+                })?;
+            let ticket = &bytes[ticket_offset..ticket_end];
+
+            Some((ticket, ticket_offset))
         } else {
             debug!("Ticket is not present.");
 
@@ -176,19 +374,55 @@ impl<'a> Parser<'a> {
         };
 
         Ok(Self {
-            ticket,
+            metadata: &bytes[..HEADER_LEN + segments_len],
+            ticket: ticket.map(|(ticket, _)| ticket),
+            ticket_offset: ticket.map(|(_, offset)| offset).unwrap_or(0),
             segments,
             tail,
-            unk_0,
-            unk_1,
-            unk_2,
-            unk_3,
-            unk_4,
-            unk_5,
-            unk_6,
+            unk_0: header.unk_0,
+            unk_1: header.unk_1,
+            unk_2: header.unk_2,
+            unk_3: header.unk_3,
+            unk_4: header.unk_4,
+            unk_5: header.unk_5,
+            unk_6: header.unk_6,
         })
     }
 
+    /// Parse a 'ftab' file embedded into a larger container starting at the specified offset of the
+    /// provided byte slice.
+    ///
+    /// Offsets stored in a 'ftab' file are relative to its start, so all offsets returned by the
+    /// parser, like [`ParsedSegment::offset`], are relative to `offset` rather than the start of the
+    /// slice. The contents of the segments and the ticket may extend up to the end of the slice.
+    ///
+    /// # Errors
+    /// This method will return [`ParseError::TooShort`] in case `offset` is past the end of the
+    /// slice and the same errors as [`Parser::parse`] otherwise.
+    ///
+    /// [`ParseError::TooShort`]: error/enum.ParseError.html#variant.TooShort
+    pub fn parse_at(bytes: &'a [u8], offset: usize) -> Result<Self, ParseError> {
+        Self::parse_at_with_max_segments(bytes, offset, usize::MAX)
+    }
+
+    /// Parse a 'ftab' file embedded into a larger container starting at the specified offset of the
+    /// provided byte slice rejecting files which declare more than `max_segments` segments. See
+    /// [`Parser::parse_at`] and [`Parser::parse_with_max_segments`] for details.
+    ///
+    /// # Errors
+    /// This method will return the same errors as [`Parser::parse_at`] and
+    /// [`Parser::parse_with_max_segments`].
+    pub fn parse_at_with_max_segments(
+        bytes: &'a [u8],
+        offset: usize,
+        max_segments: usize,
+    ) -> Result<Self, ParseError> {
+        let bytes = bytes.get(offset..).ok_or(ParseError::TooShort)?;
+        debug!("Parsing ftab at offset {:#x}.", offset);
+
+        Self::parse_with_max_segments(bytes, max_segments)
+    }
+
     /// Returns the `unk_0` field of the 'ftab' header. Its purpose is currently unknown.
     #[inline]
     pub fn unk_0(&self) -> u32 {
@@ -231,12 +465,95 @@ impl<'a> Parser<'a> {
         self.unk_6
     }
 
+    /// Returns the bytes of the header and the segment list.
+    pub fn metadata_bytes(&self) -> &'a [u8] {
+        self.metadata
+    }
+
+    /// Returns the total length of the parsed file.
+    pub fn file_len(&self) -> usize {
+        HEADER_LEN + self.segments.len() * SEGMENT_HEADER_LEN + self.tail.len()
+    }
+
     /// Returns the slice containing the APTicket included into the 'ftab' file or `None` if an
     /// APTicket is not included.
     pub fn ticket(&self) -> Option<&'a [u8]> {
         self.ticket
     }
 
+    /// Returns the range of file offsets occupied by the APTicket or `None` if an APTicket is not
+    /// included.
+    pub fn ticket_range(&self) -> Option<Range<usize>> {
+        self.ticket
+            .map(|ticket| self.ticket_offset..self.ticket_offset + ticket.len())
+    }
+
+    /// Returns the range of base offsets which would make an out of bounds segment fit into the file
+    /// if the offset in its segment list entry was relative to such base instead of the start of
+    /// the file. The segment fits when its contents lie between the end of the segment list and the
+    /// end of the file. Returns `None` when the segment is longer than that.
+    ///
+    /// This is useful to tell whether a file uses a different offset convention, e.g. offsets
+    /// relative to the end of the segment list, in which case the range contains the offset of the
+    /// end of the segment list.
+    pub fn fitting_base_range(&self, error: &OobSegmentError) -> Option<RangeInclusive<i64>> {
+        let data_start = (HEADER_LEN + self.segments.len() * SEGMENT_HEADER_LEN) as i64;
+        let file_len = self.file_len() as i64;
+        let offset = error.offset as i64;
+        let len = error.len as i64;
+
+        let min = data_start - offset;
+        let max = file_len - len - offset;
+
+        (min <= max).then_some(min..=max)
+    }
+
+    /// Returns the bytes of the parsed file in the specified range of file offsets or `None` in case
+    /// the range is out of bounds or doesn't lie entirely past the segment list.
+    pub fn bytes_at(&self, range: Range<usize>) -> Option<&'a [u8]> {
+        let data_start = HEADER_LEN + self.segments.len() * SEGMENT_HEADER_LEN;
+        let start = range.start.checked_sub(data_start)?;
+        let end = range.end.checked_sub(data_start)?;
+
+        self.tail.get(start..end)
+    }
+
+    /// Checks that the APTicket doesn't share any bytes with the contents of a segment.
+    ///
+    /// The header and the segment list are verified not to overlap with the ticket by
+    /// [`Parser::parse`], but segment ranges are only known after iterating the segment list, so
+    /// this check has to be performed separately for each segment.
+    ///
+    /// # Errors
+    /// This method will return a [`TicketOverlapError`](error/struct.TicketOverlapError.html)
+    /// containing the overlapping range in case the ticket intersects with the segment's contents.
+    pub fn check_ticket_overlap(&self, segment: &ParsedSegment) -> Result<(), TicketOverlapError> {
+        let Some(ticket_range) = self.ticket_range() else {
+            return Ok(());
+        };
+
+        let start = ticket_range.start.max(segment.offset);
+        let end = ticket_range.end.min(segment.offset + segment.data.len());
+        if start < end {
+            Err(TicketOverlapError {
+                tag: segment.tag,
+                overlap: start..end,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns the raw entries of the segment list of the parsed 'ftab' file.
+    ///
+    /// This is a low-level accessor for callers that want to process the segment list on their
+    /// own, e.g. to hash it. Use [`Parser::segments`] to get decoded entries. A flat byte slice
+    /// may be obtained using the `as_flattened` method of the returned slice.
+    #[inline]
+    pub fn segments_slice(&self) -> &'a [[u8; SEGMENT_HEADER_LEN]] {
+        self.segments
+    }
+
     /// Returns a [`SegmentsParser`] over the segment list of the parsed 'ftab' file.
     #[inline]
     pub fn segments(&self) -> SegmentsParser<'a> {
@@ -246,10 +563,68 @@ impl<'a> Parser<'a> {
             // This should be the initial length of the slice provided to the constructor so this
             // will never overflow.
             data_offset: self.segments.len() * SEGMENT_HEADER_LEN + HEADER_LEN,
+            index: 0,
+            skip_empty: false,
+        }
+    }
+
+    /// Returns an iterator over the segments of the parsed 'ftab' file that yields tags along with
+    /// copies of the segments' contents.
+    ///
+    /// Unlike the zero-copy [`Parser::segments`], this allocates a vector for every segment, which
+    /// allows to release the buffer containing the file once the iteration is done.
+    pub fn into_owned_segments(self) -> OwnedSegments<'a> {
+        OwnedSegments {
+            parser: self.segments(),
+            failed: false,
+        }
+    }
+}
+
+/// An iterator over segments of a 'ftab' file that copies contents of each segment into an owned
+/// vector. Created by the [`Parser::into_owned_segments`] method.
+///
+/// The iteration stops after the first error.
+#[derive(Clone, Debug)]
+pub struct OwnedSegments<'a> {
+    parser: SegmentsParser<'a>,
+    failed: bool,
+}
+
+impl Iterator for OwnedSegments<'_> {
+    type Item = Result<(Tag, Vec<u8>), OobSegmentError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        let result = self.parser.next_segment();
+        self.failed = result.is_err();
+
+        result
+            .map(|segment| segment.map(|segment| (Tag(segment.tag), segment.data.to_vec())))
+            .transpose()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.failed {
+            (0, Some(0))
+        } else {
+            (0, Some(self.parser.count()))
         }
     }
 }
 
+impl<'a> TryFrom<&'a [u8]> for Parser<'a> {
+    type Error = ParseError;
+
+    /// Parses a 'ftab' file the same way [`Parser::parse`] does.
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        Self::parse(bytes)
+    }
+}
+
 /// A description of a parsed segment.
 #[derive(Clone, Debug)]
 pub struct ParsedSegment<'a> {
@@ -257,6 +632,8 @@ pub struct ParsedSegment<'a> {
     pub tag: [u8; 4],
     /// The segment's contents.
     pub data: &'a [u8],
+    /// The absolute offset of the segment's contents in the file.
+    pub offset: usize,
     /// An field with a currently unknown purpose from the segment list entry.
     ///
     /// At the time of writing it seems to be ignored by software interpreting the format.
@@ -269,6 +646,19 @@ pub struct SegmentsParser<'a> {
     headers: &'a [[u8; SEGMENT_HEADER_LEN]],
     data: &'a [u8],
     data_offset: usize,
+    /// The index of the next entry in the segment list.
+    index: usize,
+    /// Skip entries with an all-zero tag and a zero length.
+    skip_empty: bool,
+}
+
+/// Checks if a segment list entry has an all-zero tag and a zero length. Some tools pad the segment
+/// list with such entries.
+fn is_empty_entry(bytes: &[u8; SEGMENT_HEADER_LEN]) -> bool {
+    let (tag, bytes) = bytes.split_at(4);
+    let (_, len) = get_u32_le(&bytes[4..]);
+
+    tag == [0; 4] && len == 0
 }
 
 impl<'a> SegmentsParser<'a> {
@@ -277,12 +667,60 @@ impl<'a> SegmentsParser<'a> {
     ///
     /// # Errors
     /// This function will return an [`OobSegmentError`](error/struct.OobSegmentError.html) when
-    /// a segment list entry is encountered which points outside the range of the file.
-    pub fn next_segment(&mut self) -> Result<Option<ParsedSegment>, OobSegmentError> {
-        let Some((bytes, tail)) = self.headers.split_first() else {
-            return Ok(None);
-        };
+    /// a segment list entry is encountered which points outside the range of the file. The parser
+    /// is still advanced past such entry, so the following entries may be parsed afterwards.
+    pub fn next_segment(&mut self) -> Result<Option<ParsedSegment<'a>>, OobSegmentError> {
+        loop {
+            let Some((bytes, tail)) = self.headers.split_first() else {
+                return Ok(None);
+            };
+            self.headers = tail;
+            self.index += 1;
+
+            if self.skip_empty && is_empty_entry(bytes) {
+                trace!("Skipping empty segment list entry #{}.", self.index - 1);
+                continue;
+            }
+
+            return self.parse_entry(self.index - 1, bytes).map(Some);
+        }
+    }
+
+    /// Sets whether [`SegmentsParser::next_segment`] skips entries with an all-zero tag and a zero
+    /// length instead of returning them. Some tools pad the segment list with such entries, which
+    /// usually also have a zero offset and would be reported as out of bounds otherwise. The offset
+    /// of a skipped entry is not checked.
+    ///
+    /// Entries are not skipped by default. Neither [`SegmentsParser::count`] nor
+    /// [`SegmentsParser::nth_segment`] are affected by this setting.
+    pub fn set_skip_empty(&mut self, skip_empty: bool) {
+        self.skip_empty = skip_empty;
+    }
 
+    /// Parses the segment list entry at index `n` into a [`ParsedSegment`] without advancing the
+    /// parser. Returns `None` when there is no such entry.
+    ///
+    /// The index is relative to the entries that haven't been parsed yet, so for a parser returned
+    /// by [`Parser::segments`] it is the index of the entry in the segment list. Unlike
+    /// [`Iterator::nth`], only the requested entry is parsed and validated.
+    ///
+    /// # Errors
+    /// This function will return an [`OobSegmentError`](error/struct.OobSegmentError.html) when
+    /// the entry points outside the range of the file.
+    pub fn nth_segment(&self, n: usize) -> Result<Option<ParsedSegment<'a>>, OobSegmentError> {
+        match self.headers.get(n) {
+            Some(bytes) => self.parse_entry(self.index + n, bytes).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Parses a segment list entry validating that it points into the file. `index` is the index of
+    /// the entry in the segment list reported in errors.
+    fn parse_entry(
+        &self,
+        index: usize,
+        bytes: &[u8; SEGMENT_HEADER_LEN],
+    ) -> Result<ParsedSegment<'a>, OobSegmentError> {
         let (tag, bytes) = bytes.split_at(4);
         let (bytes, offset) = get_u32_le(bytes);
         let (bytes, len) = get_u32_le(bytes);
@@ -296,12 +734,20 @@ impl<'a> SegmentsParser<'a> {
         let len: usize = len.try_into().unwrap();
 
         // Validate offset and length and extract segment data.
-        let data = cut_subslice(self.data, offset, len, self.data_offset)
-            .ok_or(OobSegmentError { tag })?;
-
-        self.headers = tail;
-
-        Ok(Some(ParsedSegment { tag, data, unk }))
+        let data =
+            cut_subslice(self.data, offset, len, self.data_offset).ok_or(OobSegmentError {
+                index,
+                tag,
+                offset,
+                len,
+            })?;
+
+        Ok(ParsedSegment {
+            tag,
+            data,
+            offset,
+            unk,
+        })
     }
 
     /// Returns the remaining count of the segment list to be parsed.
@@ -309,3 +755,259 @@ impl<'a> SegmentsParser<'a> {
         self.headers.len()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a raw 'ftab' file with the specified `(tag, offset, length)` segment list entries and
+    /// `(offset, length)` ticket range, padded with zeroes to `file_len` bytes.
+    fn raw_ftab(segments: &[([u8; 4], u32, u32)], ticket: (u32, u32), file_len: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for field in [0, 0, 0, 0, ticket.0, ticket.1, 0, 0] {
+            bytes.extend_from_slice(&u32::to_le_bytes(field));
+        }
+        bytes.extend_from_slice(b"rkosftab");
+        bytes.extend_from_slice(&(segments.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        for (tag, offset, len) in segments.iter() {
+            bytes.extend_from_slice(tag);
+            bytes.extend_from_slice(&offset.to_le_bytes());
+            bytes.extend_from_slice(&len.to_le_bytes());
+            bytes.extend_from_slice(&0u32.to_le_bytes());
+        }
+        bytes.resize(bytes.len().max(file_len), 0);
+
+        bytes
+    }
+
+    #[test]
+    fn cut_subslice_in_bounds() {
+        let slice = b"0123456789";
+
+        assert_eq!(cut_subslice(slice, 102, 3, 100), Some(&b"234"[..]));
+        assert_eq!(cut_subslice(slice, 100, 10, 100), Some(&slice[..]));
+        assert_eq!(cut_subslice(slice, 2, 3, 0), Some(&b"234"[..]));
+    }
+
+    #[test]
+    fn cut_subslice_below_slice_offset() {
+        let slice = b"0123456789";
+
+        assert_eq!(cut_subslice(slice, 99, 1, 100), None);
+        assert_eq!(cut_subslice(slice, 0, 0, 100), None);
+    }
+
+    #[test]
+    fn cut_subslice_overflowing_range() {
+        let slice = b"0123456789";
+
+        // `offset + len` overflows usize
+        assert_eq!(cut_subslice(slice, 105, usize::MAX, 100), None);
+        assert_eq!(cut_subslice(slice, usize::MAX, 1, 100), None);
+        assert_eq!(cut_subslice(slice, usize::MAX, usize::MAX, 0), None);
+    }
+
+    #[test]
+    fn cut_subslice_at_slice_end() {
+        let slice = b"0123456789";
+
+        // a range may end exactly at the end of the slice
+        assert_eq!(cut_subslice(slice, 107, 3, 100), Some(&b"789"[..]));
+        // but not past it
+        assert_eq!(cut_subslice(slice, 107, 4, 100), None);
+        assert_eq!(cut_subslice(slice, 111, 0, 100), None);
+    }
+
+    #[test]
+    fn cut_subslice_empty_range() {
+        let slice = b"0123456789";
+
+        assert_eq!(cut_subslice(slice, 100, 0, 100), Some(&b""[..]));
+        assert_eq!(cut_subslice(slice, 105, 0, 100), Some(&b""[..]));
+        // a zero-length range starting right at the end is accepted
+        assert_eq!(cut_subslice(slice, 110, 0, 100), Some(&b""[..]));
+        assert_eq!(cut_subslice(&[], 0, 0, 0), Some(&b""[..]));
+    }
+
+    #[test]
+    fn try_from_matches_parse() {
+        let bytes = raw_ftab(&[(*b"rkos", 80, 8), (*b"rkrn", 88, 4)], (92, 8), 100);
+        let parsed = Parser::parse(&bytes).unwrap();
+        let converted = Parser::try_from(&bytes[..]).unwrap();
+
+        assert_eq!(converted.ticket_range(), parsed.ticket_range());
+        assert_eq!(converted.segments().count(), parsed.segments().count());
+        let (mut converted, mut parsed) = (converted.segments(), parsed.segments());
+        while let Some(segment) = parsed.next_segment().unwrap() {
+            let converted = converted.next_segment().unwrap().unwrap();
+            assert_eq!(converted.tag, segment.tag);
+            assert_eq!(converted.offset, segment.offset);
+            assert_eq!(converted.data, segment.data);
+        }
+        assert!(converted.next_segment().unwrap().is_none());
+    }
+
+    #[test]
+    fn try_from_rejects_what_parse_rejects() {
+        let bytes = raw_ftab(&[(*b"rkos", 64, 4)], (0, 0), 128);
+
+        assert!(matches!(
+            Parser::try_from(&bytes[..40]),
+            Err(ParseError::TooShort)
+        ));
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[32..40].copy_from_slice("rkösfta".as_bytes());
+        assert!(matches!(
+            Parser::try_from(&bad_magic[..]),
+            Err(ParseError::UnknownMagic)
+        ));
+
+        // the segment list doesn't fit in a file of exactly the header length
+        assert!(matches!(
+            Parser::try_from(&bytes[..HEADER_LEN]),
+            Err(ParseError::OobSegmentsList)
+        ));
+    }
+
+    #[test]
+    fn zero_segments() {
+        let bytes = raw_ftab(&[], (0, 0), 0);
+        assert_eq!(bytes.len(), HEADER_LEN);
+        assert_eq!(count_segments(&bytes).unwrap(), 0);
+
+        let parser = Parser::parse(&bytes).unwrap();
+        assert!(parser.segments_slice().is_empty());
+        assert!(parser.segments().next_segment().unwrap().is_none());
+        assert_eq!(parser.ticket_range(), None);
+    }
+
+    #[test]
+    fn trailing_empty_entries() {
+        let entries = [
+            (*b"rkos", 112, 4),
+            (*b"rkrn", 116, 4),
+            ([0; 4], 0, 0),
+            ([0; 4], 0, 0),
+        ];
+        let bytes = raw_ftab(&entries, (0, 0), 120);
+        let parser = Parser::parse(&bytes).unwrap();
+
+        // the zero offset of the padding entries is inside the header
+        let mut segments = parser.segments();
+        assert_eq!(segments.next_segment().unwrap().unwrap().tag, *b"rkos");
+        assert_eq!(segments.next_segment().unwrap().unwrap().tag, *b"rkrn");
+        let error = segments.next_segment().unwrap_err();
+        assert_eq!((error.index, error.tag), (2, [0; 4]));
+
+        let mut segments = parser.segments();
+        segments.set_skip_empty(true);
+        assert_eq!(segments.count(), 4);
+        assert_eq!(segments.next_segment().unwrap().unwrap().tag, *b"rkos");
+        assert_eq!(segments.next_segment().unwrap().unwrap().tag, *b"rkrn");
+        assert!(segments.next_segment().unwrap().is_none());
+        assert_eq!(segments.count(), 0);
+    }
+
+    #[test]
+    fn only_empty_entries_are_skipped() {
+        let entries = [([0; 4], 0, 0), ([0; 4], 112, 4), (*b"rkos", 0, 0)];
+        let bytes = raw_ftab(&entries, (0, 0), 116);
+        let parser = Parser::parse(&bytes).unwrap();
+
+        let mut segments = parser.segments();
+        segments.set_skip_empty(true);
+        // a zero tag with a non-zero length is a real segment
+        let segment = segments.next_segment().unwrap().unwrap();
+        assert_eq!((segment.tag, segment.offset), ([0; 4], 112));
+        // a real tag with a zero length is reported as usual
+        let error = segments.next_segment().unwrap_err();
+        assert_eq!((error.index, error.tag), (2, *b"rkos"));
+    }
+
+    #[test]
+    fn ticket_overlapping_header() {
+        let bytes = raw_ftab(&[(*b"rkos", 64, 4)], (16, 8), 128);
+
+        assert!(matches!(
+            Parser::parse(&bytes),
+            Err(ParseError::TicketOverlapsHeader { offset: 16, len: 8 })
+        ));
+    }
+
+    #[test]
+    fn ticket_overlapping_segment_list() {
+        let bytes = raw_ftab(&[(*b"rkos", 64, 4)], (56, 8), 128);
+
+        assert!(matches!(
+            Parser::parse(&bytes),
+            Err(ParseError::TicketOverlapsSegmentList {
+                offset: 56,
+                len: 8,
+                segments_end: 64,
+            })
+        ));
+    }
+
+    #[test]
+    fn ticket_past_eof() {
+        let bytes = raw_ftab(&[(*b"rkos", 64, 4)], (96, 8), 100);
+
+        assert!(matches!(
+            Parser::parse(&bytes),
+            Err(ParseError::TicketPastEof {
+                offset: 96,
+                len: 8,
+                file_len: 100,
+            })
+        ));
+
+        let bytes = raw_ftab(&[(*b"rkos", 64, 4)], (u32::MAX, u32::MAX), 100);
+        assert!(matches!(
+            Parser::parse(&bytes),
+            Err(ParseError::TicketPastEof { .. })
+        ));
+    }
+
+    #[test]
+    fn ticket_ending_at_eof() {
+        let bytes = raw_ftab(&[(*b"rkos", 64, 4)], (92, 8), 100);
+        let parser = Parser::parse(&bytes).unwrap();
+
+        assert_eq!(parser.ticket_range(), Some(92..100));
+    }
+
+    #[test]
+    fn ticket_overlapping_segment_data() {
+        let bytes = raw_ftab(&[(*b"rkos", 80, 8), (*b"rkrn", 88, 8)], (88, 16), 128);
+        let parser = Parser::parse(&bytes).unwrap();
+        let mut segments = parser.segments();
+
+        // the ticket starts right after the first segment
+        let segment = segments.next_segment().unwrap().unwrap();
+        assert!(parser.check_ticket_overlap(&segment).is_ok());
+
+        let segment = segments.next_segment().unwrap().unwrap();
+        let error = parser.check_ticket_overlap(&segment).unwrap_err();
+        assert_eq!(error.tag, *b"rkrn");
+        assert_eq!(error.overlap, 88..96);
+    }
+
+    #[test]
+    fn segments_limit_is_opt_in() {
+        let segments = vec![(*b"rkos", 0, 0); DEFAULT_MAX_SEGMENTS + 1];
+        let bytes = raw_ftab(&segments, (0, 0), 0);
+
+        assert_eq!(
+            Parser::parse(&bytes).unwrap().segments().count(),
+            DEFAULT_MAX_SEGMENTS + 1
+        );
+        assert!(matches!(
+            Parser::parse_with_max_segments(&bytes, DEFAULT_MAX_SEGMENTS),
+            Err(ParseError::TooManySegments { count, max: DEFAULT_MAX_SEGMENTS })
+                if count as usize == DEFAULT_MAX_SEGMENTS + 1
+        ));
+        assert!(Parser::parse_with_max_segments(&bytes, DEFAULT_MAX_SEGMENTS + 1).is_ok());
+    }
+}
@@ -0,0 +1,78 @@
+//! Provides helpers for colorizing the summary output printed to stdout.
+
+use std::{
+    env, fmt,
+    io::{self, IsTerminal},
+    sync::OnceLock,
+};
+
+/// Kinds of values that are displayed in distinct colors.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Color {
+    /// Segment tags.
+    Tag,
+    /// Offsets, lengths and other numeric values.
+    Offset,
+    /// Problems found in a file.
+    Warning,
+}
+
+impl Color {
+    fn escape_code(self) -> &'static str {
+        match self {
+            Color::Tag => "\x1b[36m",
+            Color::Offset => "\x1b[33m",
+            Color::Warning => "\x1b[31m",
+        }
+    }
+}
+
+/// Checks if the output should be colorized. This is the case when stdout is a terminal and the
+/// `NO_COLOR` environment variable is either not set or empty.
+pub fn colors_enabled() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+
+    *ENABLED.get_or_init(|| {
+        let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        !no_color && io::stdout().is_terminal()
+    })
+}
+
+/// A value that is displayed in a color when colors are enabled and as is otherwise.
+///
+/// Formatting flags like width and alternate form are applied to the value itself.
+#[derive(Copy, Clone, Debug)]
+pub struct Painted<T>(Color, T);
+
+/// Wraps a value so that it is displayed in the specified color when colors are enabled.
+pub fn paint<T>(color: Color, value: T) -> Painted<T> {
+    Painted(color, value)
+}
+
+impl<T> Painted<T> {
+    fn fmt_with(
+        &self,
+        f: &mut fmt::Formatter,
+        fmt_value: impl FnOnce(&T, &mut fmt::Formatter) -> fmt::Result,
+    ) -> fmt::Result {
+        if colors_enabled() {
+            f.write_str(self.0.escape_code())?;
+            fmt_value(&self.1, f)?;
+            f.write_str("\x1b[0m")
+        } else {
+            fmt_value(&self.1, f)
+        }
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Painted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_with(f, fmt::Display::fmt)
+    }
+}
+
+impl<T: fmt::LowerHex> fmt::LowerHex for Painted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_with(f, fmt::LowerHex::fmt)
+    }
+}
@@ -1,12 +1,88 @@
-use crate::error::FileOpError;
+use crate::{error::FileOpError, gzip};
 use dialoguer::Confirm;
 use std::{
     borrow::Cow,
-    fs::{File, OpenOptions},
-    io::{Read, Write},
-    path::Path,
+    env,
+    ffi::{OsStr, OsString},
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    process,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
+/// The log target of purely informational messages reporting successful operations, e.g. saved
+/// files. Loggers may filter this target to silence such messages without affecting others.
+pub const SUCCESS_LOG_TARGET: &str = "ftabutil::success";
+
+/// Formats of input 'ftab' files.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub enum InputFormat {
+    /// Detect the format using the magic value at the start of the file.
+    #[default]
+    Auto,
+    /// Treat the file as an uncompressed 'ftab' file.
+    Raw,
+    /// Treat the file as a gzip-compressed 'ftab' file.
+    Gzip,
+}
+
+impl InputFormat {
+    /// Checks if data starting with the specified bytes has to be decompressed.
+    fn is_compressed(self, prefix: &[u8]) -> bool {
+        match self {
+            InputFormat::Auto => gzip::is_gzip(prefix),
+            InputFormat::Raw => false,
+            InputFormat::Gzip => true,
+        }
+    }
+}
+
+/// Magic values of compression formats that are detected in input files but not supported.
+const UNSUPPORTED_COMPRESSION_MAGICS: [(&[u8], &str); 2] =
+    [(b"\x28\xb5\x2f\xfd", "zstd"), (b"\xfd7zXZ\x00", "xz")];
+
+/// The number of bytes at the start of an input file that is enough to detect its compression.
+const COMPRESSION_MAGIC_MAX_LEN: usize = 16;
+
+/// Converts the data read from an input file into an uncompressed 'ftab' file.
+///
+/// In the [`InputFormat::Auto`] mode gzip-compressed data is detected by its magic value and
+/// decompressed, other compression formats are detected and rejected with an error.
+fn decode_input(
+    name: &'static str,
+    path: &Path,
+    data: Vec<u8>,
+    format: InputFormat,
+) -> Result<Vec<u8>, Box<FileOpError>> {
+    let map_error = |error| FileOpError::make_read(name, path.to_path_buf(), error);
+
+    if format.is_compressed(&data) {
+        debug!("Decompressing gzip-compressed file at {}.", path.display());
+
+        return gzip::decompress(&data)
+            .map_err(|error| map_error(io::Error::new(io::ErrorKind::InvalidData, error)));
+    }
+
+    if format == InputFormat::Auto {
+        if let Some((_, compression)) = UNSUPPORTED_COMPRESSION_MAGICS
+            .iter()
+            .find(|(magic, _)| data.starts_with(magic))
+        {
+            let error = io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "the file is {}-compressed which is not supported, decompress it first",
+                    compression
+                ),
+            );
+            return Err(map_error(error));
+        }
+    }
+
+    Ok(data)
+}
+
 fn read_file_impl(name: &'static str, path: &Path) -> Result<Vec<u8>, Box<FileOpError>> {
     let mut f = File::open(path)
         .map_err(|error| FileOpError::make_open(name, path.to_path_buf(), error))?;
@@ -25,12 +101,231 @@ pub fn read_file<P: AsRef<Path>>(name: &'static str, path: P) -> Result<Vec<u8>,
     read_file_impl(name, path.as_ref())
 }
 
+/// Policies of handling symbolic links in paths of files referenced by manifests.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub enum SymlinkPolicy {
+    /// Refuse to read a file when any component of its path from the manifest is a symbolic link.
+    #[default]
+    Reject,
+    /// Follow symbolic links.
+    Follow,
+}
+
+/// Checks that no component of a path from a manifest is a symbolic link. The components are
+/// resolved relative to the manifest's directory.
+fn check_no_symlinks(rel_path: &Path, dir: Option<&Path>) -> io::Result<()> {
+    let mut path = dir.map(Path::to_path_buf).unwrap_or_default();
+    for component in rel_path.components() {
+        path.push(component);
+
+        if fs::symlink_metadata(&path)?.file_type().is_symlink() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} is a symbolic link and following symbolic links is disabled",
+                    path.display()
+                ),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads a file referenced by a manifest located in the specified directory applying the symbolic
+/// link policy to every component of the path from the manifest.
+///
+/// # Errors
+/// This function will return a boxed `FileOpError` with the `FileOpAction::Open` action when the
+/// path contains a symbolic link and the policy is [`SymlinkPolicy::Reject`] and the same errors as
+/// [`read_file`] otherwise.
+pub fn read_manifest_file(
+    name: &'static str,
+    rel_path: &Path,
+    dir: Option<&Path>,
+    policy: SymlinkPolicy,
+) -> Result<Vec<u8>, Box<FileOpError>> {
+    let path = qualify_path_if_needed(rel_path, dir);
+
+    if policy == SymlinkPolicy::Reject {
+        check_no_symlinks(rel_path, dir)
+            .map_err(|error| FileOpError::make_open(name, path.to_path_buf(), error))?;
+    }
+
+    read_file(name, path)
+}
+
+/// Checks that a file referenced by a manifest located in the specified directory can be opened
+/// applying the symbolic link policy the same way [`read_manifest_file`] does, but without reading
+/// it.
+///
+/// # Errors
+/// This function will return a boxed `FileOpError` with the `FileOpAction::Open` action when the
+/// file can't be opened, isn't a regular file or its path violates the policy.
+pub fn check_manifest_file(
+    name: &'static str,
+    rel_path: &Path,
+    dir: Option<&Path>,
+    policy: SymlinkPolicy,
+) -> Result<(), Box<FileOpError>> {
+    let path = qualify_path_if_needed(rel_path, dir);
+    let map_error = |error| FileOpError::make_open(name, path.to_path_buf(), error);
+
+    if policy == SymlinkPolicy::Reject {
+        check_no_symlinks(rel_path, dir).map_err(map_error)?;
+    }
+
+    let file = File::open(&path).map_err(map_error)?;
+    if !file.metadata().map_err(map_error)?.is_file() {
+        return Err(map_error(io::Error::other("not a regular file")));
+    }
+
+    Ok(())
+}
+
+fn read_file_prefix_impl(
+    name: &'static str,
+    path: &Path,
+    len: usize,
+) -> Result<Vec<u8>, Box<FileOpError>> {
+    let f = File::open(path)
+        .map_err(|error| FileOpError::make_open(name, path.to_path_buf(), error))?;
+    let mut v = Vec::with_capacity(len);
+    f.take(len.try_into().unwrap())
+        .read_to_end(&mut v)
+        .map_err(|error| FileOpError::make_read(name, path.to_path_buf(), error))?;
+    Ok(v)
+}
+
+/// Reads at most `len` bytes from the start of a file at the specified path. The returned vector
+/// will be shorter than `len` bytes in case the file is shorter.
+///
+/// # Errors
+/// This function will return a boxed `FileOpError` with either the `FileOpAction::Open` or the
+/// `FileOpAction::Read` action in case an I/O error occurs while opening or reading the file.
+pub fn read_file_prefix<P: AsRef<Path>>(
+    name: &'static str,
+    path: P,
+    len: usize,
+) -> Result<Vec<u8>, Box<FileOpError>> {
+    read_file_prefix_impl(name, path.as_ref(), len)
+}
+
+/// Reads an input 'ftab' file from the specified path decompressing it when needed and checking
+/// that its format is supported, see [`InputFormat`].
+///
+/// All subcommands reading 'ftab' files should use this function so that input formats are handled
+/// consistently.
+///
+/// # Errors
+/// This function will return a boxed `FileOpError` with either the `FileOpAction::Open` or the
+/// `FileOpAction::Read` action in case an I/O error occurs while opening or reading the file or
+/// in case the file's format is not supported.
+pub fn read_input_file<P: AsRef<Path>>(
+    name: &'static str,
+    path: P,
+    format: InputFormat,
+) -> Result<Vec<u8>, Box<FileOpError>> {
+    let data = read_file_impl(name, path.as_ref())?;
+    decode_input(name, path.as_ref(), data, format)
+}
+
+/// Reads at most `len` bytes from the start of an input 'ftab' file checking that its format is
+/// supported. See [`read_input_file`] and [`read_file_prefix`] for details. Compressed files are
+/// read and decompressed entirely before the prefix is taken.
+///
+/// # Errors
+/// Returns the same errors as [`read_input_file`].
+pub fn read_input_file_prefix<P: AsRef<Path>>(
+    name: &'static str,
+    path: P,
+    len: usize,
+    format: InputFormat,
+) -> Result<Vec<u8>, Box<FileOpError>> {
+    let data = read_file_prefix_impl(name, path.as_ref(), len)?;
+
+    // a prefix of compressed data can't be decompressed reliably, so the whole file is read
+    if format.is_compressed(&data) {
+        let data = read_file_impl(name, path.as_ref())?;
+        let mut data = decode_input(name, path.as_ref(), data, format)?;
+        data.truncate(len);
+        return Ok(data);
+    }
+
+    decode_input(name, path.as_ref(), data, format)
+}
+
+/// Reads at most `len` bytes starting at `offset` of an input 'ftab' file along with the length of
+/// the whole file. Only the range is read from uncompressed files, compressed files are read and
+/// decompressed entirely before the range is taken. See [`read_input_file_prefix`] for details.
+///
+/// # Errors
+/// Returns the same errors as [`read_input_file`].
+pub fn read_input_file_range<P: AsRef<Path>>(
+    name: &'static str,
+    path: P,
+    offset: usize,
+    len: usize,
+    format: InputFormat,
+) -> Result<(Vec<u8>, usize), Box<FileOpError>> {
+    let path = path.as_ref();
+    let prefix = read_file_prefix_impl(name, path, COMPRESSION_MAGIC_MAX_LEN)?;
+
+    if format.is_compressed(&prefix) {
+        let data = read_input_file(name, path, format)?;
+        let range = data
+            .get(offset..)
+            .map(|data| data[..len.min(data.len())].to_vec())
+            .unwrap_or_default();
+        return Ok((range, data.len()));
+    }
+
+    let map_error = |error| FileOpError::make_read(name, path.to_path_buf(), error);
+    let mut f = File::open(path)
+        .map_err(|error| FileOpError::make_open(name, path.to_path_buf(), error))?;
+    let file_len = f.metadata().map_err(map_error)?.len();
+    let mut v = Vec::new();
+    if (offset as u64) < file_len {
+        f.seek(SeekFrom::Start(offset as u64)).map_err(map_error)?;
+        f.take(len as u64).read_to_end(&mut v).map_err(map_error)?;
+    }
+
+    Ok((v, file_len.try_into().unwrap_or(usize::MAX)))
+}
+
+/// Asks the user if they want to overwrite the file at the specified path.
+fn confirm_overwrite(path: &Path) -> bool {
+    Confirm::new()
+        .with_prompt(format!(
+            "Do you want to overwrite the file at '{}'?",
+            path.display()
+        ))
+        .default(false)
+        .interact()
+        .expect("failed to display a prompt to the user")
+}
+
 fn create_file_impl(
     name: &'static str,
     path: &Path,
     overwrite: bool,
     silent: bool,
 ) -> Result<File, Box<FileOpError>> {
+    create_file_confirmed(name, path, overwrite, silent, confirm_overwrite)
+}
+
+/// Creates a file the same way [`create_file`] does asking whether an existing file may be
+/// overwritten using the `confirm` function.
+fn create_file_confirmed<F>(
+    name: &'static str,
+    path: &Path,
+    overwrite: bool,
+    silent: bool,
+    confirm: F,
+) -> Result<File, Box<FileOpError>>
+where
+    F: FnOnce(&Path) -> bool,
+{
     let map_error = |error| FileOpError::make_create(name, path.to_path_buf(), error);
     let result = OpenOptions::new()
         .write(true)
@@ -46,24 +341,20 @@ fn create_file_impl(
 
     // In case neither the overwrite flag nor the silent flag was passed, we want to ask the user if
     // they want to overwrite the file on receiving a "file exists" error.
-    if !overwrite && !silent && error.is_exists() && path.is_file() {
-        let response = Confirm::new()
-            .with_prompt(format!(
-                "Do you want to overwrite the file at '{}'?",
-                path.display()
-            ))
-            .default(false)
-            .interact()
-            .expect("failed to display a prompt to the user");
-
-        if response {
-            return OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(path)
-                .map_err(map_error);
+    if !overwrite && !silent && error.is_exists() && path.is_file() && confirm(path) {
+        // The path may have been replaced while the prompt was displayed, so make sure it still is
+        // a regular file before truncating it. The file isn't recreated in case it was removed.
+        if !path.is_file() {
+            return Err(map_error(io::Error::other(
+                "the path is no longer a regular file",
+            )));
         }
+
+        return OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .open(path)
+            .map_err(map_error);
     }
 
     Err(error)
@@ -98,7 +389,7 @@ fn save_file_impl(
         .write_all(data)
         .map_err(|error| FileOpError::make_write(name, path.to_path_buf(), error))?;
 
-    info!("Saved {} to {}.", name, path.display());
+    info!(target: SUCCESS_LOG_TARGET, "Saved {} to {}.", name, path.display());
 
     Ok(())
 }
@@ -126,6 +417,173 @@ pub fn save_file<P: AsRef<Path>>(
     save_file_impl(name, path.as_ref(), data, overwrite, silent)
 }
 
+/// Checks if a file may be written at the specified path following the same rules as
+/// [`create_file`] without creating or truncating the file.
+fn check_overwrite(
+    name: &'static str,
+    path: &Path,
+    overwrite: bool,
+    silent: bool,
+) -> Result<(), Box<FileOpError>> {
+    if overwrite || fs::symlink_metadata(path).is_err() {
+        return Ok(());
+    }
+
+    if !silent && path.is_file() && confirm_overwrite(path) {
+        Ok(())
+    } else {
+        let error = io::Error::from(io::ErrorKind::AlreadyExists);
+        Err(FileOpError::make_create(name, path.to_path_buf(), error))
+    }
+}
+
+/// Creates a new temporary file in the specified directory with a name derived from `file_name`
+/// and a suffix that makes it unique.
+fn create_temp_file(dir: &Path, file_name: &OsStr) -> io::Result<(File, PathBuf)> {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.subsec_nanos())
+        .unwrap_or(0);
+
+    let mut attempt = 0;
+    loop {
+        let mut temp_name = OsString::from(".");
+        temp_name.push(file_name);
+        temp_name.push(format!(".{}-{:08x}-{}.tmp", process::id(), nanos, attempt));
+        let temp_path = dir.join(temp_name);
+
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+        {
+            Ok(file) => return Ok((file, temp_path)),
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists && attempt < 16 => {
+                attempt += 1
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// Writes into a temporary file with a function and flushes it to the disk removing the file on
+/// failure.
+fn write_temp_file(
+    name: &'static str,
+    mut file: File,
+    temp_path: &Path,
+    write: &mut dyn FnMut(&mut File) -> io::Result<()>,
+) -> Result<(), Box<FileOpError>> {
+    write(&mut file)
+        .and_then(|_| file.sync_all())
+        .map_err(|error| {
+            let _ = fs::remove_file(temp_path);
+            FileOpError::make_write(name, temp_path.to_path_buf(), error)
+        })
+}
+
+fn save_file_atomic_impl(
+    name: &'static str,
+    path: &Path,
+    overwrite: bool,
+    silent: bool,
+    write: &mut dyn FnMut(&mut File) -> io::Result<()>,
+) -> Result<(), Box<FileOpError>> {
+    check_overwrite(name, path, overwrite, silent)?;
+
+    let Some(file_name) = path.file_name() else {
+        let error = io::Error::new(io::ErrorKind::InvalidInput, "the path has no file name");
+        return Err(FileOpError::make_create(name, path.to_path_buf(), error));
+    };
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    match create_temp_file(dir, file_name) {
+        Ok((file, temp_path)) => {
+            write_temp_file(name, file, &temp_path, write)?;
+
+            // The temporary file is on the same file system, so renaming it is atomic.
+            fs::rename(&temp_path, path).map_err(|error| {
+                let _ = fs::remove_file(&temp_path);
+                FileOpError::make_write(name, path.to_path_buf(), error)
+            })?;
+        }
+        Err(error) => {
+            warn!(
+                "Couldn't create a temporary file next to {}: {}. The file will be written \
+                through the system temporary directory which may not be atomic.",
+                path.display(),
+                error
+            );
+
+            let temp_dir = env::temp_dir();
+            let (file, temp_path) = create_temp_file(&temp_dir, file_name)
+                .map_err(|error| FileOpError::make_create(name, temp_dir, error))?;
+            write_temp_file(name, file, &temp_path, write)?;
+
+            // Renaming fails when the temporary directory is on a different file system.
+            if fs::rename(&temp_path, path).is_err() {
+                let result = fs::copy(&temp_path, path);
+                let _ = fs::remove_file(&temp_path);
+                result.map_err(|error| FileOpError::make_write(name, path.to_path_buf(), error))?;
+            }
+        }
+    }
+
+    info!(target: SUCCESS_LOG_TARGET, "Saved {} to {}.", name, path.display());
+
+    Ok(())
+}
+
+/// Writes data into a file at the specified path atomically, i.e. the file either keeps its
+/// previous contents or gets the new contents in full even if the process is interrupted.
+///
+/// The data is written into a temporary file in the same directory which is then renamed over
+/// the destination path, so the rename never crosses file systems. In case a temporary file can't
+/// be created in that directory, a warning is logged and the data is written through the system
+/// temporary directory falling back to copying when renaming fails.
+///
+/// Existing files are handled the same way as by [`save_file`].
+///
+/// # Errors
+/// This function will return a boxed [`FileOpError`] with either [`FileOpAction::Create`] or
+/// [`FileOpAction::Write`] action in case an I/O error occurs while either creating, writing or
+/// renaming the file.
+pub fn save_file_atomic<P: AsRef<Path>>(
+    name: &'static str,
+    path: P,
+    data: &[u8],
+    overwrite: bool,
+    silent: bool,
+) -> Result<(), Box<FileOpError>> {
+    save_file_atomic_impl(name, path.as_ref(), overwrite, silent, &mut |file| {
+        file.write_all(data)
+    })
+}
+
+/// Writes a file at the specified path atomically the same way [`save_file_atomic`] does, but
+/// produces the contents with a function writing into the temporary file instead of taking them
+/// from memory.
+///
+/// # Errors
+/// Returns the same errors as [`save_file_atomic`], errors returned by the function are reported
+/// with the [`FileOpAction::Write`] action.
+pub fn save_file_atomic_with<P, F>(
+    name: &'static str,
+    path: P,
+    overwrite: bool,
+    silent: bool,
+    mut write: F,
+) -> Result<(), Box<FileOpError>>
+where
+    P: AsRef<Path>,
+    F: FnMut(&mut File) -> io::Result<()>,
+{
+    save_file_atomic_impl(name, path.as_ref(), overwrite, silent, &mut write)
+}
+
 fn qualify_path_if_needed_impl<'a>(path: &'a Path, dir: Option<&Path>) -> Cow<'a, Path> {
     if path.is_absolute() {
         Cow::from(path)
@@ -172,3 +630,220 @@ where
     let path = path.map(AsRef::as_ref).unwrap_or_else(|| default.as_ref());
     qualify_path_if_needed(path, dir)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::FileOpAction;
+
+    /// Creates an empty directory for a test under the system's temporary directory.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("ftabutil-test-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_segment() {
+        use std::os::unix::fs::symlink;
+
+        let dir = test_dir("symlinked-segment");
+        fs::write(dir.join("target.bin"), b"abc").unwrap();
+        symlink("target.bin", dir.join("rkos.bin")).unwrap();
+        let path = Path::new("rkos.bin");
+
+        let data = read_manifest_file("segment", path, Some(&dir), SymlinkPolicy::Follow).unwrap();
+        assert_eq!(data, b"abc");
+
+        let error =
+            read_manifest_file("segment", path, Some(&dir), SymlinkPolicy::Reject).unwrap_err();
+        assert_eq!(error.error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(error.path, dir.join(path));
+        assert!(check_manifest_file("segment", path, Some(&dir), SymlinkPolicy::Reject).is_err());
+        assert!(check_manifest_file("segment", path, Some(&dir), SymlinkPolicy::Follow).is_ok());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_directory() {
+        use std::os::unix::fs::symlink;
+
+        let dir = test_dir("symlinked-directory");
+        fs::create_dir(dir.join("real")).unwrap();
+        fs::write(dir.join("real").join("rkos.bin"), b"abc").unwrap();
+        symlink("real", dir.join("link")).unwrap();
+        let path = Path::new("link/rkos.bin");
+
+        let data = read_manifest_file("segment", path, Some(&dir), SymlinkPolicy::Follow).unwrap();
+        assert_eq!(data, b"abc");
+
+        let error =
+            read_manifest_file("segment", path, Some(&dir), SymlinkPolicy::Reject).unwrap_err();
+        assert_eq!(error.error.kind(), io::ErrorKind::InvalidInput);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dangling_symlink() {
+        use std::os::unix::fs::symlink;
+
+        let dir = test_dir("dangling-symlink");
+        symlink("missing.bin", dir.join("rkos.bin")).unwrap();
+        let path = Path::new("rkos.bin");
+
+        let error =
+            read_manifest_file("segment", path, Some(&dir), SymlinkPolicy::Follow).unwrap_err();
+        assert_eq!(error.error.kind(), io::ErrorKind::NotFound);
+
+        // the link itself exists, so it is rejected before trying to follow it
+        let error =
+            read_manifest_file("segment", path, Some(&dir), SymlinkPolicy::Reject).unwrap_err();
+        assert_eq!(error.error.kind(), io::ErrorKind::InvalidInput);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Returns the names of the entries of a directory sorted alphabetically.
+    fn dir_entries(dir: &Path) -> Vec<OsString> {
+        let mut entries: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        entries.sort_unstable();
+
+        entries
+    }
+
+    #[test]
+    fn temp_files_are_unique() {
+        let dir = test_dir("temp-files");
+
+        let (_, first) = create_temp_file(&dir, OsStr::new("ftab.bin")).unwrap();
+        let (_, second) = create_temp_file(&dir, OsStr::new("ftab.bin")).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(first.parent(), Some(dir.as_path()));
+        let first_name = first.file_name().unwrap().to_str().unwrap();
+        assert!(first_name.starts_with(".ftab.bin."), "{}", first_name);
+        assert!(first_name.ends_with(".tmp"), "{}", first_name);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn atomic_save_renames_over_destination() {
+        let dir = test_dir("atomic-save");
+        let path = dir.join("ftab.bin");
+
+        save_file_atomic("output file", &path, b"first", false, true).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"first");
+
+        // an existing file is only replaced when overwriting is allowed
+        let error = save_file_atomic("output file", &path, b"second", false, true).unwrap_err();
+        assert!(error.is_exists());
+        assert_eq!(fs::read(&path).unwrap(), b"first");
+
+        save_file_atomic("output file", &path, b"second", true, true).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+
+        // no temporary files are left behind
+        assert_eq!(dir_entries(&dir), [OsString::from("ftab.bin")]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn atomic_save_removes_temp_file_when_rename_fails() {
+        let dir = test_dir("atomic-save-rename-fails");
+        let path = dir.join("ftab.bin");
+        fs::create_dir(&path).unwrap();
+        fs::write(path.join("entry"), b"").unwrap();
+
+        // a file can't be renamed over a non-empty directory
+        let error = save_file_atomic("output file", &path, b"data", true, true).unwrap_err();
+        assert_eq!(error.action, FileOpAction::Write);
+        assert_eq!(error.path, path);
+        assert!(path.join("entry").is_file());
+        assert_eq!(dir_entries(&dir), [OsString::from("ftab.bin")]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn overwritten_path_replaced_by_directory() {
+        let dir = test_dir("overwrite-race");
+        let path = dir.join("ftab.bin");
+        fs::write(&path, b"old").unwrap();
+
+        // the path turns into a directory while the user is being asked
+        let error = create_file_confirmed("output file", &path, false, false, |path| {
+            fs::remove_file(path).unwrap();
+            fs::create_dir(path).unwrap();
+            true
+        })
+        .unwrap_err();
+        assert_eq!(error.action, FileOpAction::Create);
+        assert!(
+            error.to_string().contains("no longer a regular file"),
+            "{}",
+            error
+        );
+        assert!(path.is_dir());
+
+        // a removed file isn't recreated
+        fs::remove_dir(&path).unwrap();
+        fs::write(&path, b"old").unwrap();
+        let result = create_file_confirmed("output file", &path, false, false, |path| {
+            fs::remove_file(path).unwrap();
+            true
+        });
+        assert!(result.is_err());
+        assert!(!path.exists());
+
+        // declining keeps the file intact
+        fs::write(&path, b"old").unwrap();
+        let error =
+            create_file_confirmed("output file", &path, false, false, |_| false).unwrap_err();
+        assert!(error.is_exists());
+        assert_eq!(fs::read(&path).unwrap(), b"old");
+        let mut file = create_file_confirmed("output file", &path, false, false, |_| true).unwrap();
+        file.write_all(b"new").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn input_file_range() {
+        let dir = test_dir("input-file-range");
+        let raw = dir.join("raw.bin");
+        fs::write(&raw, b"rkosftab").unwrap();
+        let read = |path: &Path, offset, len, format| {
+            read_input_file_range("input file", path, offset, len, format).unwrap()
+        };
+
+        assert_eq!(read(&raw, 2, 4, InputFormat::Auto), (b"osft".to_vec(), 8));
+        assert_eq!(read(&raw, 6, 4, InputFormat::Auto), (b"ab".to_vec(), 8));
+        assert_eq!(read(&raw, 9, 4, InputFormat::Auto), (Vec::new(), 8));
+
+        // `rkosftab` compressed into a single stored block.
+        let gzip = dir.join("gzip.bin");
+        fs::write(
+            &gzip,
+            b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x00\xff\x01\x08\x00\xf7\xff\x72\x6b\x6f\
+            \x73\x66\x74\x61\x62\xca\x44\xd9\xd7\x08\x00\x00\x00",
+        )
+        .unwrap();
+        assert_eq!(read(&gzip, 2, 4, InputFormat::Auto), (b"osft".to_vec(), 8));
+        assert_eq!(read(&gzip, 6, 4, InputFormat::Gzip), (b"ab".to_vec(), 8));
+        assert_eq!(read(&gzip, 2, 4, InputFormat::Raw).1, 31);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,283 @@
+//! Provides the [`verify`] function that checks a parsed 'ftab' file for problems that don't prevent
+//! it from being parsed and reports them as a list of [`Diagnostic`]s.
+
+use crate::{
+    checksum::crc32,
+    format::*,
+    manifest::Tag,
+    parser::{OobSegmentError, Parser},
+};
+use std::fmt;
+
+/// Kinds of problems reported by [`verify`].
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[non_exhaustive]
+pub enum DiagnosticKind {
+    /// A segment list entry points outside the range of the file.
+    OobSegment,
+    /// The ticket shares bytes with the contents of a segment.
+    Overlap,
+    /// The file contains bytes past the end of the last segment and the ticket.
+    TrailingData,
+    /// The file contains unreferenced bytes between segments that exceed the alignment padding.
+    Gap,
+    /// The `unk` field of a segment list entry is not zero.
+    NonzeroUnk,
+    /// The checksum of the header and the segment list differs from the expected one.
+    ChecksumMismatch,
+    /// The contents of a segment don't start on the expected boundary.
+    Misaligned,
+}
+
+impl DiagnosticKind {
+    /// Returns the `snake_case` name of the kind used in structured output.
+    pub fn name(self) -> &'static str {
+        match self {
+            DiagnosticKind::OobSegment => "oob_segment",
+            DiagnosticKind::Overlap => "overlap",
+            DiagnosticKind::TrailingData => "trailing_data",
+            DiagnosticKind::Gap => "gap",
+            DiagnosticKind::NonzeroUnk => "nonzero_unk",
+            DiagnosticKind::ChecksumMismatch => "checksum_mismatch",
+            DiagnosticKind::Misaligned => "misaligned",
+        }
+    }
+}
+
+/// A problem found in a 'ftab' file.
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    /// The kind of the problem.
+    pub kind: DiagnosticKind,
+    /// The tag of the segment the problem relates to, if any.
+    pub tag: Option<Tag>,
+    /// A human-readable description of the problem.
+    pub message: String,
+    /// An optional human-readable hint on the cause of the problem.
+    pub hint: Option<String>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// A named range of a 'ftab' file used to find gaps between referenced data.
+struct Region {
+    start: usize,
+    end: usize,
+    tag: Option<Tag>,
+}
+
+impl Region {
+    fn describe(&self) -> String {
+        match self.tag {
+            Some(tag) => format!("segment with tag {}", tag),
+            None => "the ticket".to_owned(),
+        }
+    }
+}
+
+/// Finds unreferenced ranges between segments and the ticket that are larger than the padding
+/// required to align the following data to a 4-byte boundary.
+fn find_gaps(mut regions: Vec<Region>, data_start: usize, diagnostics: &mut Vec<Diagnostic>) {
+    regions.sort_by_key(|region| region.start);
+
+    let mut prev_end = data_start;
+    let mut prev_desc = "the segment list".to_owned();
+    for region in regions.iter() {
+        let padding = alignment_padding(prev_end);
+        if region.start > prev_end + padding {
+            diagnostics.push(Diagnostic {
+                kind: DiagnosticKind::Gap,
+                tag: region.tag,
+                message: format!(
+                    "{} unreferenced bytes at {:#x}..{:#x} between {} and {}",
+                    region.start - prev_end,
+                    prev_end,
+                    region.start,
+                    prev_desc,
+                    region.describe()
+                ),
+                hint: None,
+            });
+        }
+
+        if region.end >= prev_end {
+            prev_end = region.end;
+            prev_desc = region.describe();
+        }
+    }
+}
+
+/// Describes which base offsets would make an out of bounds segment fit into the file.
+fn offset_base_hint(parser: &Parser, error: &OobSegmentError, data_start: usize) -> String {
+    let Some(range) = parser.fitting_base_range(error) else {
+        return format!(
+            "segment is {} bytes long which doesn't fit into the file with any base offset",
+            error.len
+        );
+    };
+
+    let base = |base: &i64| {
+        if *base < 0 {
+            format!("-{:#x}", base.unsigned_abs())
+        } else {
+            format!("{:#x}", base)
+        }
+    };
+    let mut hint = format!(
+        "segment would fit if its offset was relative to a base in {}..={}",
+        base(range.start()),
+        base(range.end())
+    );
+    if range.contains(&(data_start as i64)) {
+        hint.push_str(", which includes the end of the segment list");
+    } else if range.contains(&(HEADER_LEN as i64)) {
+        hint.push_str(", which includes the end of the header");
+    }
+
+    hint
+}
+
+/// Checks a parsed 'ftab' file for problems and returns the list of all problems found.
+pub fn verify(parser: &Parser) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut segments_parser = parser.segments();
+    let data_start = HEADER_LEN + segments_parser.count() * SEGMENT_HEADER_LEN;
+    let mut end = data_start;
+    let mut regions = Vec::with_capacity(segments_parser.count() + 1);
+
+    while segments_parser.count() != 0 {
+        let segment = match segments_parser.next_segment() {
+            Ok(Some(segment)) => segment,
+            Ok(None) => break,
+            Err(e) => {
+                diagnostics.push(Diagnostic {
+                    kind: DiagnosticKind::OobSegment,
+                    tag: Some(Tag(e.tag)),
+                    message: e.to_string(),
+                    hint: Some(offset_base_hint(parser, &e, data_start)),
+                });
+                continue;
+            }
+        };
+
+        if let Err(e) = parser.check_ticket_overlap(&segment) {
+            diagnostics.push(Diagnostic {
+                kind: DiagnosticKind::Overlap,
+                tag: Some(Tag(e.tag)),
+                message: e.to_string(),
+                hint: None,
+            });
+        }
+
+        if segment.unk != 0 {
+            diagnostics.push(Diagnostic {
+                kind: DiagnosticKind::NonzeroUnk,
+                tag: Some(Tag(segment.tag)),
+                message: format!(
+                    "segment with tag {} has a non-zero unk field {:#x}",
+                    segment.tag.escape_ascii(),
+                    segment.unk
+                ),
+                hint: None,
+            });
+        }
+
+        end = end.max(segment.offset + segment.data.len());
+        regions.push(Region {
+            start: segment.offset,
+            end: segment.offset + segment.data.len(),
+            tag: Some(Tag(segment.tag)),
+        });
+    }
+
+    if let Some(ticket_range) = parser.ticket_range() {
+        end = end.max(ticket_range.end);
+        regions.push(Region {
+            start: ticket_range.start,
+            end: ticket_range.end,
+            tag: None,
+        });
+    }
+
+    find_gaps(regions, data_start, &mut diagnostics);
+
+    if end < parser.file_len() {
+        diagnostics.push(Diagnostic {
+            kind: DiagnosticKind::TrailingData,
+            tag: None,
+            message: format!(
+                "file contains {} bytes of trailing data at offset {:#x}",
+                parser.file_len() - end,
+                end
+            ),
+            hint: None,
+        });
+    }
+
+    diagnostics
+}
+
+/// Computes the CRC-32 checksum of the header and the segment list of a parsed 'ftab' file.
+///
+/// The checksum doesn't cover segment contents and the ticket, so it can be used to detect
+/// corruption of the file's metadata independently of the data.
+pub fn metadata_crc32(parser: &Parser) -> u32 {
+    crc32(parser.metadata_bytes())
+}
+
+/// Compares the CRC-32 checksum of the header and the segment list with an expected value and
+/// returns a diagnostic in case they differ.
+pub fn check_metadata_checksum(parser: &Parser, expected: u32) -> Option<Diagnostic> {
+    let actual = metadata_crc32(parser);
+    (actual != expected).then(|| Diagnostic {
+        kind: DiagnosticKind::ChecksumMismatch,
+        tag: None,
+        message: format!(
+            "metadata CRC-32 is {:#010x} while {:#010x} was expected",
+            actual, expected
+        ),
+        hint: Some("the header or the segment list may be corrupted".to_owned()),
+    })
+}
+
+/// Checks that the contents of every segment start at an offset divisible by `alignment` and
+/// returns a diagnostic for each segment that doesn't. Files produced by the builder use
+/// [`SEGMENT_ALIGNMENT`]. A segment starting right after the segment list is never reported since
+/// nothing precedes it that could be padded. Out of bounds segments are skipped as [`verify`]
+/// reports them.
+///
+/// # Panics
+/// Panics in case `alignment` is zero.
+pub fn check_alignment(parser: &Parser, alignment: usize) -> Vec<Diagnostic> {
+    assert_ne!(alignment, 0, "alignment must not be zero");
+
+    let mut diagnostics = Vec::new();
+    let mut segments_parser = parser.segments();
+    let data_start = HEADER_LEN + segments_parser.count() * SEGMENT_HEADER_LEN;
+
+    while segments_parser.count() != 0 {
+        let Ok(Some(segment)) = segments_parser.next_segment() else {
+            continue;
+        };
+
+        if segment.offset != data_start && segment.offset % alignment != 0 {
+            diagnostics.push(Diagnostic {
+                kind: DiagnosticKind::Misaligned,
+                tag: Some(Tag(segment.tag)),
+                message: format!(
+                    "segment with tag {} at offset {:#x} is not aligned to {} bytes",
+                    segment.tag.escape_ascii(),
+                    segment.offset,
+                    alignment
+                ),
+                hint: None,
+            });
+        }
+    }
+
+    diagnostics
+}